@@ -1,12 +1,14 @@
-use std::{io::Read, process};
+use std::process;
 
-use datastore::{dbms::{HashTableConfig, ManagedHashTable}, hash_table::{HashTable, HashTableEntry, HashTableScanFilter, HashTableScanner}};
+use datastore::prelude::*;
 
 pub fn main() {
-    let mut config: HashTableConfig = Default::default();
-    config.page_size = 64;
-    config.index_chunk_size = 64;
-    config.section_count = 4;
+    let config = HashTableConfig {
+        page_size: 64,
+        index_chunk_size: 64,
+        section_count: 4,
+        ..Default::default()
+    };
 
     let mut hash_table = match ManagedHashTable::open("dev/example-hash-table", config) {
         Ok(store) => store,
@@ -51,39 +53,15 @@ pub fn main() {
                 },
             };
             found_any = true;
-            let key = {
-                let mut key_reader = match entry.key() {
-                    Ok(r) => r,
-                    Err(e) => {
-                        eprintln!("Failed to read key: {}", e);
-                        process::exit(1);
-                    }
-                };
-                
-                key_buf.clear();
-                if let Err(e) = key_reader.read_to_end(&mut key_buf) {
-                    eprintln!("Failed to read key: {}", e);
-                    process::exit(1);
-                }
-                &key_buf[..]
-            };
-            let value = {
-                let mut value_reader = match entry.value() {
-                    Ok(r) => r,
-                    Err(e) => {
-                        eprintln!("Failed to read value: {}", e);
-                        process::exit(1);
-                    }
-                };
-
-                value_buf.clear();
-                if let Err(e) = value_reader.read_to_end(&mut value_buf) {
-                    eprintln!("Failed to read value: {}", e);
-                    process::exit(1);
-                }
-                &value_buf[..]
-            };
-            println!("Key: {:?}, Value: {:?}", String::from_utf8_lossy(&key), String::from_utf8_lossy(&value));
+            if let Err(e) = entry.read_key_into(&mut key_buf) {
+                eprintln!("Failed to read key: {}", e);
+                process::exit(1);
+            }
+            if let Err(e) = entry.read_value_into(&mut value_buf) {
+                eprintln!("Failed to read value: {}", e);
+                process::exit(1);
+            }
+            println!("Key: {:?}, Value: {:?}", String::from_utf8_lossy(&key_buf), String::from_utf8_lossy(&value_buf));
         }
         if !found_any {
             println!("No entries found.");
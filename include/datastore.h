@@ -0,0 +1,43 @@
+/* C API of the datastore crate, built with `--features ffi`. See src/ffi.rs for ownership rules. */
+#ifndef DATASTORE_H
+#define DATASTORE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DATASTORE_OK 0
+#define DATASTORE_NOT_FOUND 1
+#define DATASTORE_END 2
+#define DATASTORE_INVALID_ARGUMENT (-1)
+#define DATASTORE_IO_ERROR (-2)
+#define DATASTORE_UNSUPPORTED (-3)
+#define DATASTORE_PANIC (-4)
+
+typedef struct DatastoreHashTable DatastoreHashTable;
+typedef struct DatastoreScan DatastoreScan;
+
+const char *datastore_last_error_message(void);
+
+int datastore_open(const char *path, DatastoreHashTable **out_table);
+void datastore_close(DatastoreHashTable *table);
+
+int datastore_put(DatastoreHashTable *table, const uint8_t *key, size_t key_len, const uint8_t *value, size_t value_len);
+int datastore_get(DatastoreHashTable *table, const uint8_t *key, size_t key_len, uint8_t **out_value, size_t *out_value_len);
+int datastore_delete(DatastoreHashTable *table, const uint8_t *key, size_t key_len);
+int datastore_sync(DatastoreHashTable *table);
+
+int datastore_scan_open(DatastoreHashTable *table, DatastoreScan **out_scan);
+int datastore_scan_next(DatastoreScan *scan, uint8_t **out_key, size_t *out_key_len, uint8_t **out_value, size_t *out_value_len);
+void datastore_scan_close(DatastoreScan *scan);
+
+void datastore_buffer_free(uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
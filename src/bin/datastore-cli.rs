@@ -0,0 +1,142 @@
+use std::{fs::File, io::{self, BufReader, BufWriter, Write}, path::Path, process};
+
+use datastore::{dbms::ManagedHashTable, hash_table::{HashTableEntry, HashTableScanFilter, HashTableScanner}};
+
+const USAGE: &str = "\
+Usage: datastore-cli <command> <dir> [args]
+
+Commands:
+  inspect <dir>          Print the config and the files of the store
+  stats <dir>            Print the statistics of the store as JSON
+  compact <dir>          Drop all but the latest entry of every key
+  verify <dir>           Read every entry, reporting corrupt ranges
+  export <dir> [file]    Write a dump of the entries, to stdout by default
+  import <dir> [file]    Insert the entries of a dump, from stdin by default
+  wal-dump <dir>         Print the events of the write-ahead log";
+
+fn open_read_only(dir_path: &str) -> io::Result<ManagedHashTable> {
+    Ok(ManagedHashTable::builder(dir_path).read_only(true).open()?)
+}
+
+fn to_json(value: &impl serde::Serialize) -> io::Result<String> {
+    serde_json::to_string_pretty(value).map_err(io::Error::other)
+}
+
+fn inspect(dir_path: &str) -> io::Result<()> {
+    let hash_table = open_read_only(dir_path)?;
+    println!("{}", to_json(hash_table.config())?);
+    let mut files = std::fs::read_dir(dir_path)?.collect::<io::Result<Vec<_>>>()?;
+    files.sort_by_key(|file| file.file_name());
+    for file in files {
+        let metadata = file.metadata()?;
+        if metadata.is_file() {
+            println!("{:>12}  {}", metadata.len(), file.file_name().to_string_lossy());
+        }
+    }
+    println!("epoch {}, approximately {} keys", hash_table.epoch(), hash_table.approx_key_count());
+    Ok(())
+}
+
+fn stats(dir_path: &str) -> io::Result<()> {
+    let stats = open_read_only(dir_path)?.stats()?;
+    println!("{}", to_json(&stats)?);
+    Ok(())
+}
+
+fn compact(dir_path: &str) -> io::Result<()> {
+    let mut hash_table = ManagedHashTable::open_with_existing_config(dir_path)?;
+    let before = hash_table.stats()?;
+    let mut hash_table = hash_table.compact()?;
+    let after = hash_table.stats()?;
+    println!("entries {} -> {}, pages {} -> {}", before.entry_count, after.entry_count, before.page_count, after.page_count);
+    Ok(())
+}
+
+/// Fails if any corruption was found, so scripts can check the exit code.
+fn verify(dir_path: &str) -> io::Result<()> {
+    let hash_table = open_read_only(dir_path)?;
+    let mut corrupt_ranges = Vec::new();
+    let mut entry_count = 0u64;
+    {
+        let mut scanner = hash_table.scan_with_recovery(HashTableScanFilter::All, |range| corrupt_ranges.push(range))?;
+        while let Some(mut entry) = scanner.next()? {
+            entry.verify()?;
+            entry_count += 1;
+        }
+    }
+    println!("{entry_count} entries read");
+    for range in &corrupt_ranges {
+        println!("corrupt: section {} bytes {}..{}", range.section_index, range.start_offset, range.end_offset);
+    }
+    if !corrupt_ranges.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} corrupt ranges found", corrupt_ranges.len())));
+    }
+    Ok(())
+}
+
+fn export(dir_path: &str, file_path: Option<&str>) -> io::Result<()> {
+    let hash_table = open_read_only(dir_path)?;
+    let count = match file_path {
+        Some(file_path) => {
+            let mut writer = BufWriter::new(File::create(file_path)?);
+            let count = hash_table.export(&mut writer)?;
+            writer.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+            count
+        },
+        None => {
+            let mut writer = BufWriter::new(io::stdout().lock());
+            let count = hash_table.export(&mut writer)?;
+            writer.flush()?;
+            count
+        },
+    };
+    eprintln!("{count} entries exported");
+    Ok(())
+}
+
+fn import(dir_path: &str, file_path: Option<&str>) -> io::Result<()> {
+    let mut hash_table = if Path::new(dir_path).exists() {
+        ManagedHashTable::open_with_existing_config(dir_path)?
+    } else {
+        ManagedHashTable::builder(dir_path).open()?
+    };
+    let count = match file_path {
+        Some(file_path) => hash_table.import(&mut BufReader::new(File::open(file_path)?))?,
+        None => hash_table.import(&mut io::stdin().lock())?,
+    };
+    hash_table.full_sync()?;
+    println!("{count} entries imported");
+    Ok(())
+}
+
+fn wal_dump(dir_path: &str) -> io::Result<()> {
+    let hash_table = open_read_only(dir_path)?;
+    let mut stdout = io::stdout().lock();
+    let count = hash_table.dump_wal(&mut stdout)?;
+    writeln!(stdout, "{count} events")?;
+    Ok(())
+}
+
+pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[1..] {
+        ["inspect", dir_path] => inspect(dir_path),
+        ["stats", dir_path] => stats(dir_path),
+        ["compact", dir_path] => compact(dir_path),
+        ["verify", dir_path] => verify(dir_path),
+        ["export", dir_path] => export(dir_path, None),
+        ["export", dir_path, file_path] => export(dir_path, Some(file_path)),
+        ["import", dir_path] => import(dir_path, None),
+        ["import", dir_path, file_path] => import(dir_path, Some(file_path)),
+        ["wal-dump", dir_path] => wal_dump(dir_path),
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("{}: {}", args[1], e);
+        process::exit(1);
+    }
+}
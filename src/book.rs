@@ -1,4 +1,4 @@
-use std::io::{Read, Seek, Write};
+use std::io::{self, IoSlice, Read, Seek, Write};
 
 pub mod pager;
 
@@ -12,4 +12,22 @@ pub trait Book {
 
 pub trait Section: Read + Write + Seek + Clone {
     fn index(&self) -> SectionIndex;
+
+    /// Shortens the section to `len` bytes, so the bytes past it read as zeros again. The
+    /// position is left as it is. Does nothing if the section is `len` bytes or shorter.
+    fn truncate(&mut self, len: u64) -> io::Result<()>;
+}
+
+/// Writes all of `bufs`, as `Write::write_all` does for a single buffer.
+pub fn write_all_vectored(writer: &mut impl Write, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match writer.write_vectored(bufs) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer")),
+            Ok(written) => IoSlice::advance_slices(&mut bufs, written),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
 }
@@ -1,6 +1,7 @@
-use std::{cmp::min, collections::BTreeMap, io::{self, Read, Seek, SeekFrom, Write}, sync::RwLock};
+use std::{cmp::min, collections::BTreeMap, io::{self, IoSlice, Read, Seek, SeekFrom, Write}, sync::{Arc, Mutex, RwLock}};
 
-use crate::{book::{Book, Section, SectionIndex, SectionPageIndex}, pager::{PageIndex, Pager}};
+use crate::{book::{Book, Section, SectionIndex, SectionPageIndex}, lru::Lru, pager::{PageIndex, Pager}};
+use crate::PoisonedLockError;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PageKey {
@@ -16,13 +17,27 @@ pub struct PageHeader {
 pub trait PageRegistry {
     fn try_resolve_page(&self, key: &PageKey) -> io::Result<Option<PageHeader>>;
     fn resolve_page(&mut self, key: &PageKey) -> io::Result<PageHeader>;
+
+    /// Unassigns the pager page of `key`, so a later `resolve_page` of any key may reuse it. The
+    /// page's contents are left as they are. Registries that only ever append, such as
+    /// `PagerBookMemoryHeader`, fail with `Unsupported`.
+    fn release_page(&mut self, key: &PageKey) -> io::Result<()> {
+        let _ = key;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Page registry cannot release pages"))
+    }
+
+    /// Releases the pages of `section_index` from `section_page_index` on, as `release_page` does.
+    fn release_section_pages(&mut self, section_index: SectionIndex, section_page_index: SectionPageIndex) -> io::Result<()> {
+        let _ = (section_index, section_page_index);
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Page registry cannot release pages"))
+    }
 }
 
 pub type PagerBookMemoryHeader = RwLock<BTreeMap<PageKey, PageHeader>>;
 
 impl PageRegistry for PagerBookMemoryHeader {
     fn try_resolve_page(&self, key: &PageKey) -> io::Result<Option<PageHeader>> {
-        let lock = self.read().map_err(|_| io::Error::new(io::ErrorKind::Other, "Lock poisoned"))?;
+        let lock = self.read().map_err(|_| io::Error::other(PoisonedLockError))?;
         Ok(lock.get(key).cloned())
     }
 
@@ -30,15 +45,51 @@ impl PageRegistry for PagerBookMemoryHeader {
         if let Some(page_header) = self.try_resolve_page(key)? {
             return Ok(page_header);
         }
-        let mut lock = self.write().map_err(|_| io::Error::new(io::ErrorKind::Other, "Lock poisoned"))?;
+        let mut lock = self.write().map_err(|_| io::Error::other(PoisonedLockError))?;
         let pager_page_index = lock.len() as PageIndex;
         Ok(lock.entry(*key).or_insert_with(|| PageHeader { pager_page_index }).clone())
     }
 }
 
+/// Contents of the least recently read pages, see `PagerBook::with_page_cache`.
+struct PageCache {
+    capacity: usize,
+    pages: Lru<PageKey, Arc<[u8]>>,
+}
+
+impl PageCache {
+    fn get(&mut self, key: &PageKey) -> Option<Arc<[u8]>> {
+        self.pages.get_mut(key).cloned()
+    }
+
+    fn insert(&mut self, key: PageKey, data: Arc<[u8]>) {
+        self.pages.insert(key, data);
+        while self.pages.len() > self.capacity {
+            let Some((&evicted, _)) = self.pages.iter_by_use().next() else {
+                break;
+            };
+            self.pages.remove(&evicted);
+        }
+    }
+
+    fn remove(&mut self, key: &PageKey) {
+        self.pages.remove(key);
+    }
+
+    fn remove_section_pages(&mut self, section_index: SectionIndex, section_page_index: SectionPageIndex) {
+        let start = PageKey { section_index, section_page_index };
+        let end = PageKey { section_index, section_page_index: SectionPageIndex::MAX };
+        let keys: Vec<_> = self.pages.keys_in(start..=end).copied().collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+}
+
 pub struct PagerBook<Pager, Registry> {
     pager: Pager,
     registry: RwLock<Registry>,
+    page_cache: Option<Mutex<PageCache>>,
 }
 
 impl<P: Pager, R: PageRegistry> PagerBook<P, R> {
@@ -46,15 +97,36 @@ impl<P: Pager, R: PageRegistry> PagerBook<P, R> {
         Self {
             pager,
             registry: RwLock::new(registry),
+            page_cache: None,
         }
     }
 
+    /// Keeps the contents of up to `capacity` pages read through any of the sections, so reading
+    /// them again does not go to the pager. Pages are dropped from the cache when written through
+    /// a section; `clear_page_cache` has to be called when the pager is changed otherwise.
+    /// A `capacity` of zero disables the cache.
+    pub fn with_page_cache(mut self, capacity: usize) -> Self {
+        self.page_cache = (capacity > 0).then(|| Mutex::new(PageCache {
+            capacity,
+            pages: Lru::new(),
+        }));
+        self
+    }
+
+    pub fn clear_page_cache(&self) -> io::Result<()> {
+        if let Some(page_cache) = &self.page_cache {
+            let mut page_cache = page_cache.lock().map_err(|_| io::Error::other(PoisonedLockError))?;
+            page_cache.pages.clear();
+        }
+        Ok(())
+    }
+
     pub fn pager(&mut self) -> &mut P {
         &mut self.pager
     }
 
     pub fn registry(&mut self) -> io::Result<&mut R> {
-        self.registry.get_mut().map_err(|_| io::Error::new(io::ErrorKind::Other, "Lock poisoned"))
+        self.registry.get_mut().map_err(|_| io::Error::other(PoisonedLockError))
     }
 }
 
@@ -93,9 +165,62 @@ impl<'a, P: Pager, R: PageRegistry> Section for PagerBookSection<'a, P, R> {
     fn index(&self) -> SectionIndex {
         self.section_index
     }
+
+    /// Releases the pages past `len` through the registry, and zeros the rest of the page `len`
+    /// ends in.
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        let page_size = self.book.pager.page_size() as u64;
+        let kept_pages = SectionPageIndex::try_from(len.div_ceil(page_size))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Section length overflow"))?;
+        self.current_page = None;
+        self.book.registry.write().map_err(|_| io::Error::other(PoisonedLockError))?
+            .release_section_pages(self.section_index, kept_pages)?;
+        if let Some(page_cache) = &self.book.page_cache {
+            page_cache.lock().map_err(|_| io::Error::other(PoisonedLockError))?
+                .remove_section_pages(self.section_index, kept_pages.saturating_sub(1));
+        }
+        let page_offset = len % page_size;
+        if page_offset > 0 {
+            let page_key = PageKey { section_index: self.section_index, section_page_index: kept_pages - 1 };
+            let registry = self.book.registry.read().map_err(|_| io::Error::other(PoisonedLockError))?;
+            if let Some(page_header) = registry.try_resolve_page(&page_key)? {
+                let mut page = self.book.pager.page(page_header.pager_page_index)?;
+                page.seek(SeekFrom::Start(page_offset))?;
+                page.write_all(&vec![0u8; (page_size - page_offset) as usize])?;
+                page.flush()?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a, P: Pager, R: PageRegistry> PagerBookSection<'a, P, R> {
+    fn current_page_key(&self) -> PageKey {
+        PageKey {
+            section_index: self.section_index,
+            section_page_index: (self.section_offset / self.book.pager.page_size() as u64) as SectionPageIndex,
+        }
+    }
+
+    /// The contents of the current page from the cache, read into it if missing. `None` if the
+    /// book has no cache or the page was never written.
+    fn cached_current_page(&mut self, page_cache: &Mutex<PageCache>) -> io::Result<Option<Arc<[u8]>>> {
+        let page_key = self.current_page_key();
+        if let Some(data) = page_cache.lock().map_err(|_| io::Error::other(PoisonedLockError))?.get(&page_key) {
+            return Ok(Some(data));
+        }
+        self.try_fetch_current_page()?;
+        let Some((page, _)) = self.current_page.as_mut() else {
+            return Ok(None);
+        };
+        let mut data = vec![0u8; self.book.pager.page_size() as usize];
+        page.seek(SeekFrom::Start(0))?;
+        page.read_exact(&mut data)?;
+        let data: Arc<[u8]> = data.into();
+        page_cache.lock().map_err(|_| io::Error::other(PoisonedLockError))?.insert(page_key, data.clone());
+        Ok(Some(data))
+    }
+
     fn try_fetch_current_page(&mut self) -> io::Result<()> {
         let section_page_index = (self.section_offset / self.book.pager.page_size() as u64) as SectionPageIndex;
         if let Some((_, current_section_page_index)) = &self.current_page {
@@ -108,12 +233,12 @@ impl<'a, P: Pager, R: PageRegistry> PagerBookSection<'a, P, R> {
             section_index: self.section_index,
             section_page_index,
         };
-        let registry = self.book.registry.read().map_err(|_| io::Error::new(io::ErrorKind::Other, "Lock poisoned"))?;
+        let registry = self.book.registry.read().map_err(|_| io::Error::other(PoisonedLockError))?;
         if let Some(page_header) = registry.try_resolve_page(&page_key)? {
             let page = self.book.pager.page(page_header.pager_page_index)?;
             self.current_page = Some((page, section_page_index));
         }
-        return Ok(());
+        Ok(())
     }
 
     fn get_or_assign_current_page(&mut self) -> io::Result<&mut P::Page<'a>> {
@@ -132,7 +257,7 @@ impl<'a, P: Pager, R: PageRegistry> PagerBookSection<'a, P, R> {
             section_index: *section_index,
             section_page_index,
         };
-        let mut registry = book.registry.write().map_err(|_| io::Error::new(io::ErrorKind::Other, "Lock poisoned"))?;
+        let mut registry = book.registry.write().map_err(|_| io::Error::other(PoisonedLockError))?;
         let PageHeader { pager_page_index } = registry.resolve_page(&page_key)?;
         let page = book.pager.page(pager_page_index)?;
         *current_page = Some((page, section_page_index));
@@ -145,6 +270,14 @@ impl<'a, P: Pager, R: PageRegistry> Read for PagerBookSection<'a, P, R> {
         let page_size = self.book.pager.page_size() as u64;
         let page_offset = self.section_offset % page_size;
         let max_read_size = min(buf.len() as u64, page_size - page_offset) as usize;
+        if let Some(page_cache) = &self.book.page_cache {
+            match self.cached_current_page(page_cache)? {
+                Some(data) => buf[..max_read_size].copy_from_slice(&data[page_offset as usize..page_offset as usize + max_read_size]),
+                None => buf[..max_read_size].fill(0),
+            }
+            self.section_offset += max_read_size as u64;
+            return Ok(max_read_size);
+        }
         self.try_fetch_current_page()?;
         let read_size = if let Some((page, _)) = self.current_page.as_mut() {
             page.seek(SeekFrom::Start(page_offset))?;
@@ -163,13 +296,40 @@ impl<'a, P: Pager, R: PageRegistry> Write for PagerBookSection<'a, P, R> {
         let page_size = self.book.pager.page_size() as u64;
         let page_offset = self.section_offset % page_size;
         let max_write_size = min(buf.len() as u64, page_size - page_offset) as usize;
+        let page_key = self.current_page_key();
         let page = self.get_or_assign_current_page()?;
         page.seek(SeekFrom::Start(page_offset))?;
         let written = page.write(&buf[..max_write_size])?;
+        if let Some(page_cache) = &self.book.page_cache {
+            page_cache.lock().map_err(|_| io::Error::other(PoisonedLockError))?.remove(&page_key);
+        }
         self.section_offset += written as u64;
         Ok(written)
     }
 
+    /// Gathers as much of `bufs` as fits in the current page into a single page write.
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let page_size = self.book.pager.page_size() as u64;
+        let capacity = (page_size - self.section_offset % page_size) as usize;
+        let mut bufs = bufs.iter().filter(|buf| !buf.is_empty());
+        let Some(first) = bufs.next() else {
+            return Ok(0);
+        };
+        if first.len() >= capacity {
+            return self.write(first);
+        }
+        let mut gathered = Vec::with_capacity(capacity);
+        gathered.extend_from_slice(first);
+        for buf in bufs {
+            let take = min(buf.len(), capacity - gathered.len());
+            gathered.extend_from_slice(&buf[..take]);
+            if gathered.len() == capacity {
+                break;
+            }
+        }
+        self.write(&gathered)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.try_fetch_current_page()?;
         if let Some((page, _)) = self.current_page.as_mut() {
@@ -232,11 +392,11 @@ mod tests {
         let book = create_test_book(1024);
         let mut section = book.section(0);
 
-        section.write(b"Hello, World!")?;
+        section.write_all(b"Hello, World!")?;
         section.rewind()?;
         
         let mut buffer = vec![0u8; 13];
-        section.read(&mut buffer)?;
+        section.read_exact(&mut buffer)?;
         assert_eq!(&buffer, b"Hello, World!");
         Ok(())
     }
@@ -266,20 +426,73 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_vectored() -> io::Result<()> {
+        use crate::{book::write_all_vectored, testing::counting::CountingPager};
+
+        let book = PagerBook::new(CountingPager::new(MemoryPager::new(16)), PagerBookMemoryHeader::default());
+        let mut section = book.section(0);
+        section.seek(SeekFrom::Start(4))?;
+        write_all_vectored(&mut section, &mut [
+            IoSlice::new(&[1u8; 2]),
+            IoSlice::new(&[2u8; 2]),
+            IoSlice::new(&[]),
+            IoSlice::new(&[3u8; 20]),
+        ])?;
+        // Once per page spanned.
+        assert_eq!(book.pager.counts()?.writes, 2);
+
+        let mut buffer = [0u8; 24];
+        section.seek(SeekFrom::Start(4))?;
+        section.read_exact(&mut buffer)?;
+        assert_eq!(buffer[..4], [1, 1, 2, 2]);
+        assert_eq!(buffer[4..], [3u8; 20]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_cache() -> io::Result<()> {
+        use crate::testing::counting::CountingPager;
+
+        let book = PagerBook::new(CountingPager::new(MemoryPager::new(64)), PagerBookMemoryHeader::default())
+            .with_page_cache(2);
+        let mut section = book.section(0);
+        section.write_all(&[1u8; 192])?;
+
+        let mut buffer = [0u8; 128];
+        section.rewind()?;
+        section.read_exact(&mut buffer)?;
+        let first_read = book.pager.counts()?;
+        section.rewind()?;
+        section.read_exact(&mut buffer)?;
+        assert_eq!(book.pager.counts()?.bytes_read, first_read.bytes_read);
+
+        // The third page is read once, the written first page again.
+        section.rewind()?;
+        section.write_all(&[2u8; 8])?;
+        section.seek(SeekFrom::Start(128))?;
+        section.read_exact(&mut buffer[..64])?;
+        section.rewind()?;
+        section.read_exact(&mut buffer[..8])?;
+        assert_eq!(buffer[..8], [2u8; 8]);
+        assert_eq!(book.pager.counts()?.bytes_read, first_read.bytes_read + 128);
+        Ok(())
+    }
+
     #[test]
     fn test_seeking() -> io::Result<()> {
         let book = create_test_book(1024);
         let mut section = book.section(0);
 
-        section.write(b"0123456789ABCDEF")?;
+        section.write_all(b"0123456789ABCDEF")?;
 
         section.seek(SeekFrom::Start(5))?;
         let mut buf = [0u8; 3];
-        section.read(&mut buf)?;
+        section.read_exact(&mut buf)?;
         assert_eq!(&buf, b"567");
 
         section.seek(SeekFrom::Current(2))?;
-        section.read(&mut buf)?;
+        section.read_exact(&mut buf)?;
         assert_eq!(&buf, b"ABC");
 
         assert!(section.seek(SeekFrom::End(0)).is_err());
@@ -293,17 +506,17 @@ mod tests {
         let mut s0 = book.section(0);
         let mut s1 = book.section(1);
         
-        s0.write(b"Section0")?;
-        s1.write(b"Section1")?;
+        s0.write_all(b"Section0")?;
+        s1.write_all(b"Section1")?;
 
         s0.rewind()?;
         s1.rewind()?;
 
         let mut buf = vec![0u8; 8];
-        s0.read(&mut buf)?;
+        s0.read_exact(&mut buf)?;
         assert_eq!(&buf, b"Section0");
         
-        s1.read(&mut buf)?;
+        s1.read_exact(&mut buf)?;
         assert_eq!(&buf, b"Section1");
         Ok(())
     }
@@ -313,23 +526,23 @@ mod tests {
         let book = create_test_book(64);
         let mut section = book.section(0);
 
-        section.write(b"Page0")?;
+        section.write_all(b"Page0")?;
         section.seek(SeekFrom::Start(128))?; // Skip to page 2
-        section.write(b"Page2")?;
+        section.write_all(b"Page2")?;
 
         // Read from different section (unallocated, should be zeros)
         let mut other_section = book.section(1);
         let mut buf = [0u8; 5];
-        other_section.read(&mut buf)?;
+        other_section.read_exact(&mut buf)?;
         assert_eq!(buf, [0u8; 5]);
 
         // Verify page 0 and 2
         section.seek(SeekFrom::Start(0))?;
-        section.read(&mut buf)?;
+        section.read_exact(&mut buf)?;
         assert_eq!(&buf, b"Page0");
 
         section.seek(SeekFrom::Start(128))?;
-        section.read(&mut buf)?;
+        section.read_exact(&mut buf)?;
         assert_eq!(&buf, b"Page2");
         Ok(())
     }
@@ -339,13 +552,13 @@ mod tests {
         let book = create_test_book(1024);
         let mut section = book.section(0);
 
-        section.write(b"XXXXXXXXXX")?;
+        section.write_all(b"XXXXXXXXXX")?;
         section.seek(SeekFrom::Start(2))?;
-        section.write(b"YYY")?;
+        section.write_all(b"YYY")?;
 
         section.rewind()?;
         let mut buf = [0u8; 10];
-        section.read(&mut buf)?;
+        section.read_exact(&mut buf)?;
         assert_eq!(&buf, b"XXYYYXXXXX");
         Ok(())
     }
@@ -372,12 +585,12 @@ mod tests {
         let mut s1 = book.section(0);
         let mut s2 = book.section(0);
 
-        s1.write(b"Test")?;
+        s1.write_all(b"Test")?;
         assert_eq!(s1.stream_position()?, 4);
         assert_eq!(s2.stream_position()?, 0);
 
         let mut buf = [0u8; 2];
-        s2.read(&mut buf)?;
+        s2.read_exact(&mut buf)?;
         assert_eq!(&buf, b"Te");
         assert_eq!(s2.stream_position()?, 2);
         assert_eq!(s1.stream_position()?, 4);
@@ -391,8 +604,8 @@ mod tests {
         for size in [5, 10].into_iter() {
             for section_index in 0..2 {
                 let mut data = vec![0u8; size];
-                for i in 0..data.len() {
-                    data[i] = ((i + section_index) % 256) as u8;
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = ((i + section_index) % 256) as u8;
                 }
 
                 let mut section = book.section(section_index as SectionIndex);
@@ -404,8 +617,8 @@ mod tests {
             let mut section = book.section(section_index as SectionIndex);
 
             let mut data = vec![0u8; 10];
-            for i in 0..data.len() {
-                data[i] = ((i + section_index) % 256) as u8;
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte = ((i + section_index) % 256) as u8;
             }
 
             let mut read_back = vec![0u8; data.len()];
@@ -0,0 +1,272 @@
+//! Building blocks for distributing a store over several nodes.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::hash_table::fnv_hasher::{FNV_OFFSET_BASIS, fmix32, fnv1a};
+
+pub type RingPosition = u32;
+
+/// The positions `(start, end]` clockwise around the ring, wrapping past `RingPosition::MAX`.
+/// `start == end` covers the whole ring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RingRange {
+    pub start: RingPosition,
+    pub end: RingPosition,
+}
+
+impl RingRange {
+    pub fn contains(&self, position: RingPosition) -> bool {
+        match self.start.cmp(&self.end) {
+            std::cmp::Ordering::Less => self.start < position && position <= self.end,
+            std::cmp::Ordering::Greater => self.start < position || position <= self.end,
+            std::cmp::Ordering::Equal => true,
+        }
+    }
+}
+
+/// Keys whose owner changes from `from` to `to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RangeMove {
+    pub range: RingRange,
+    pub from: String,
+    pub to: String,
+}
+
+/// The ranges of keys that have to be copied to move from one ring to another.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RebalancePlan {
+    pub moves: Vec<RangeMove>,
+}
+
+impl RebalancePlan {
+    /// The move covering `key`, if its owner changes.
+    pub fn move_for(&self, key: &[u8]) -> Option<&RangeMove> {
+        let position = HashRing::key_position(key);
+        self.moves.iter().find(|range_move| range_move.range.contains(position))
+    }
+}
+
+/// A consistent-hash ring mapping keys to named nodes, e.g. hosts or shard directories.
+///
+/// Each node is placed at `virtual_nodes` positions; a key belongs to the node at the first
+/// position at or after its own. Adding or removing a node therefore only moves the keys next to
+/// its positions. Only the node names and `virtual_nodes` are serialized, the positions are derived.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "HashRingSpec", into = "HashRingSpec"))]
+pub struct HashRing {
+    virtual_nodes: u32,
+    nodes: BTreeSet<String>,
+    positions: BTreeMap<RingPosition, String>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HashRingSpec {
+    virtual_nodes: u32,
+    nodes: BTreeSet<String>,
+}
+
+#[cfg(feature = "serde")]
+impl From<HashRingSpec> for HashRing {
+    fn from(spec: HashRingSpec) -> Self {
+        let mut ring = HashRing::new(spec.virtual_nodes);
+        ring.nodes = spec.nodes;
+        ring.rebuild();
+        ring
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<HashRing> for HashRingSpec {
+    fn from(ring: HashRing) -> Self {
+        HashRingSpec {
+            virtual_nodes: ring.virtual_nodes,
+            nodes: ring.nodes,
+        }
+    }
+}
+
+impl HashRing {
+    pub fn new(virtual_nodes: u32) -> Self {
+        Self {
+            virtual_nodes: virtual_nodes.max(1),
+            nodes: BTreeSet::new(),
+            positions: BTreeMap::new(),
+        }
+    }
+
+    pub fn virtual_nodes(&self) -> u32 {
+        self.virtual_nodes
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().map(String::as_str)
+    }
+
+    /// Returns `false` if the node is already part of the ring.
+    pub fn add_node(&mut self, node: impl Into<String>) -> bool {
+        let added = self.nodes.insert(node.into());
+        if added {
+            self.rebuild();
+        }
+        added
+    }
+
+    /// Returns `false` if the node is not part of the ring.
+    pub fn remove_node(&mut self, node: &str) -> bool {
+        let removed = self.nodes.remove(node);
+        if removed {
+            self.rebuild();
+        }
+        removed
+    }
+
+    pub fn key_position(key: &[u8]) -> RingPosition {
+        fmix32(fnv1a(FNV_OFFSET_BASIS, key))
+    }
+
+    /// The node owning `key`, `None` if the ring is empty.
+    pub fn node_for(&self, key: &[u8]) -> Option<&str> {
+        self.owner_at(Self::key_position(key))
+    }
+
+    /// The ranges of keys owned by another node in `target` than in `self`.
+    pub fn rebalance_plan(&self, target: &HashRing) -> RebalancePlan {
+        let boundaries = self.positions.keys().chain(target.positions.keys()).copied().collect::<BTreeSet<_>>();
+        let Some(&last) = boundaries.last() else {
+            return RebalancePlan::default();
+        };
+
+        let mut moves = Vec::<RangeMove>::new();
+        let mut start = last;
+        for &end in &boundaries {
+            // No boundary lies inside `(start, end]`, so every position in it has the owner of `end` in both rings.
+            if let (Some(from), Some(to)) = (self.owner_at(end), target.owner_at(end)) && from != to {
+                match moves.last_mut() {
+                    Some(range_move) if range_move.range.end == start && range_move.from == from && range_move.to == to => {
+                        range_move.range.end = end;
+                    },
+                    _ => moves.push(RangeMove {
+                        range: RingRange { start, end },
+                        from: from.to_string(),
+                        to: to.to_string(),
+                    }),
+                }
+            }
+            start = end;
+        }
+
+        // The first range starts where the last one ends when the move wraps past zero.
+        if moves.len() > 1 {
+            let (first, last) = (&moves[0], &moves[moves.len() - 1]);
+            if first.range.start == last.range.end && first.from == last.from && first.to == last.to {
+                let last = moves.pop().unwrap();
+                moves[0].range.start = last.range.start;
+            }
+        }
+        RebalancePlan { moves }
+    }
+
+    /// The plan for adding `node`, see `rebalance_plan`.
+    pub fn plan_add_node(&self, node: impl Into<String>) -> RebalancePlan {
+        let mut target = self.clone();
+        target.add_node(node);
+        self.rebalance_plan(&target)
+    }
+
+    /// The plan for removing `node`, see `rebalance_plan`.
+    pub fn plan_remove_node(&self, node: &str) -> RebalancePlan {
+        let mut target = self.clone();
+        target.remove_node(node);
+        self.rebalance_plan(&target)
+    }
+
+    fn owner_at(&self, position: RingPosition) -> Option<&str> {
+        self.positions
+            .range(position..)
+            .next()
+            .or_else(|| self.positions.iter().next())
+            .map(|(_, node)| node.as_str())
+    }
+
+    fn rebuild(&mut self) {
+        self.positions.clear();
+        for node in &self.nodes {
+            let node_hash = fnv1a(FNV_OFFSET_BASIS, node.as_bytes());
+            for virtual_node in 0..self.virtual_nodes {
+                // On a collision the position stays with the node that sorts first.
+                self.positions
+                    .entry(fmix32(fnv1a(node_hash, &virtual_node.to_le_bytes())))
+                    .or_insert_with(|| node.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_keys() -> impl Iterator<Item = Vec<u8>> {
+        (0..2000).map(|i| format!("key-{i}").into_bytes())
+    }
+
+    #[test]
+    fn test_adding_node_moves_only_planned_keys() {
+        let mut ring = HashRing::new(64);
+        for node in ["node-a", "node-b", "node-c"] {
+            ring.add_node(node);
+        }
+        let plan = ring.plan_add_node("node-d");
+        let mut target = ring.clone();
+        assert!(target.add_node("node-d"));
+
+        let mut moved = 0;
+        for key in test_keys() {
+            let (from, to) = (ring.node_for(&key).unwrap(), target.node_for(&key).unwrap());
+            match plan.move_for(&key) {
+                Some(range_move) => {
+                    assert_eq!((range_move.from.as_str(), range_move.to.as_str()), (from, to));
+                    moved += 1;
+                },
+                None => assert_eq!(from, to),
+            }
+        }
+        assert!(plan.moves.iter().all(|range_move| range_move.to == "node-d"));
+        // About a quarter of the keys move to the new node.
+        assert!((300..700).contains(&moved), "{moved} keys moved");
+
+        let plan = target.plan_remove_node("node-d");
+        assert!(plan.moves.iter().all(|range_move| range_move.from == "node-d"));
+        assert!(test_keys().all(|key| plan.move_for(&key).is_some() == (target.node_for(&key) == Some("node-d"))));
+    }
+
+    #[test]
+    fn test_single_node_owns_whole_ring() {
+        let mut ring = HashRing::new(1);
+        assert_eq!(ring.node_for(b"key"), None);
+        ring.add_node("only");
+        assert!(test_keys().all(|key| ring.node_for(&key) == Some("only")));
+
+        let plan = HashRing::new(1).rebalance_plan(&ring);
+        assert!(plan.moves.is_empty());
+        let plan = ring.plan_add_node("other");
+        assert!(plan.moves.iter().all(|range_move| range_move.from == "only" && range_move.to == "other"));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_ring_serialization() {
+        let mut ring = HashRing::new(16);
+        ring.add_node("node-a");
+        ring.add_node("node-b");
+        let json = serde_json::to_value(&ring).unwrap();
+        assert_eq!(json, serde_json::json!({ "virtual_nodes": 16, "nodes": ["node-a", "node-b"] }));
+        let restored: HashRing = serde_json::from_value(json).unwrap();
+        assert_eq!(restored, ring);
+    }
+}
@@ -1,7 +1,24 @@
 pub mod hash_table;
+#[cfg(feature = "async")]
+mod async_hash_table;
+pub mod sharded;
 mod page_registry;
 mod section_registry;
 mod index_registry;
-mod wal;
+mod key_sketch;
+mod double_write;
+pub mod wal;
+mod sync_sequence;
 
+#[cfg(feature = "async")]
+pub use async_hash_table::{AsyncHashTable, AsyncHashTableScanner, AsyncManagedHashTable, OwnedEntry};
+#[cfg(feature = "async")]
+pub use crate::pager::async_pager::Reply;
 pub use hash_table::*;
+pub use page_registry::PageRegistryError;
+pub use sharded::{ShardIndex, ShardedHashTable, ShardedHashTableError};
+pub use sync_sequence::SyncSequence;
+pub use wal::WALRecovery;
+
+/// A `ManagedHashTable` stored in a directory of the operating system's file system.
+pub type FileHashTable = ManagedHashTable<crate::vfs::StdFs>;
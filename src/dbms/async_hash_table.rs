@@ -0,0 +1,196 @@
+use std::{collections::VecDeque, future::Future, sync::mpsc, thread};
+
+use crate::{pager::async_pager::{Reply, reply_channel}, hash_table::{EntryMetadata, HashTableEntry, HashTableScanFilter, HashTableScanner, ResumableScanner, ScanCursor}, vfs::Vfs};
+
+use super::ManagedHashTable;
+
+/// Number of entries an `AsyncScanner` reads per trip to the worker thread.
+const SCAN_BATCH_SIZE: usize = 64;
+
+/// `HashTable` with futures in place of blocking calls. The futures own their arguments, so they
+/// can be sent to other tasks.
+pub trait AsyncHashTable {
+    fn insert(&self, key: &[u8], value: &[u8]) -> impl Future<Output = crate::Result<()>> + Send + 'static;
+
+    /// The value inserted last for `key`, `None` if there is none.
+    fn get(&self, key: &[u8]) -> impl Future<Output = crate::Result<Option<Vec<u8>>>> + Send + 'static;
+
+    fn scan(&self, filter: HashTableScanFilter<'_>) -> impl AsyncHashTableScanner + Send + 'static;
+}
+
+pub trait AsyncHashTableScanner {
+    fn next(&mut self) -> impl Future<Output = crate::Result<Option<OwnedEntry>>> + Send + '_;
+}
+
+/// An entry read by an `AsyncHashTableScanner`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedEntry {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub metadata: EntryMetadata,
+}
+
+type Job<V> = Box<dyn FnOnce(&mut ManagedHashTable<V>) + Send>;
+
+/// A `ManagedHashTable` moved to a worker thread of its own, which runs the operations in the
+/// order they were started. Works with any executor, as no runtime is needed to drive the I/O.
+///
+/// The worker stops once the table and all its scanners were dropped.
+pub struct AsyncManagedHashTable<V: Vfs> {
+    jobs: mpsc::Sender<Job<V>>,
+}
+
+impl<V: Vfs> Clone for AsyncManagedHashTable<V> {
+    fn clone(&self) -> Self {
+        Self { jobs: self.jobs.clone() }
+    }
+}
+
+impl<V: Vfs + Send + 'static> AsyncManagedHashTable<V>
+where
+    V::File: Send,
+{
+    pub fn new(mut hash_table: ManagedHashTable<V>) -> crate::Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job<V>>();
+        thread::Builder::new()
+            .name("datastore-async".into())
+            .spawn(move || {
+                for job in receiver {
+                    job(&mut hash_table);
+                }
+            })?;
+        Ok(Self { jobs })
+    }
+}
+
+impl<V: Vfs> AsyncManagedHashTable<V> {
+    /// Runs `operation` on the worker thread, e.g. to reach methods without an async counterpart.
+    pub fn run<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&mut ManagedHashTable<V>) -> crate::Result<T> + Send + 'static,
+    ) -> Reply<T, crate::Error> {
+        let (sender, reply) = reply_channel();
+        // A failed send drops the sender, which resolves the reply with an error.
+        let _ = self.jobs.send(Box::new(move |hash_table| sender.send(operation(hash_table))));
+        reply
+    }
+
+    pub fn sync(&self) -> Reply<(), crate::Error> {
+        self.run(|hash_table| hash_table.sync())
+    }
+
+    pub fn full_sync(&self) -> Reply<(), crate::Error> {
+        self.run(|hash_table| hash_table.full_sync())
+    }
+}
+
+impl<V: Vfs + 'static> AsyncHashTable for AsyncManagedHashTable<V> {
+    fn insert(&self, key: &[u8], value: &[u8]) -> impl Future<Output = crate::Result<()>> + Send + 'static {
+        let (key, value) = (key.to_vec(), value.to_vec());
+        self.run(move |hash_table| crate::hash_table::HashTable::insert(hash_table, &key, &value))
+    }
+
+    fn get(&self, key: &[u8]) -> impl Future<Output = crate::Result<Option<Vec<u8>>>> + Send + 'static {
+        let key = key.to_vec();
+        self.run(move |hash_table| crate::hash_table::HashTable::get(hash_table, &key))
+    }
+
+    fn scan(&self, filter: HashTableScanFilter<'_>) -> impl AsyncHashTableScanner + Send + 'static {
+        AsyncScanner {
+            hash_table: self.clone(),
+            key: match filter {
+                HashTableScanFilter::Key(key) => Some(key.to_vec()),
+                HashTableScanFilter::All => None,
+            },
+            cursor: ScanCursor::default(),
+            buffered: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+/// Resumes the scan from its cursor for every batch, so entries inserted between batches into
+/// sections not reached yet are returned too.
+struct AsyncScanner<V: Vfs> {
+    hash_table: AsyncManagedHashTable<V>,
+    /// The key filtered for, `None` for all entries.
+    key: Option<Vec<u8>>,
+    cursor: ScanCursor,
+    buffered: VecDeque<OwnedEntry>,
+    done: bool,
+}
+
+impl<V: Vfs> AsyncHashTableScanner for AsyncScanner<V> {
+    async fn next(&mut self) -> crate::Result<Option<OwnedEntry>> {
+        if self.buffered.is_empty() && !self.done {
+            let (key, cursor) = (self.key.clone(), self.cursor);
+            let (entries, cursor, done) = self.hash_table.run(move |hash_table| {
+                let filter = match &key {
+                    Some(key) => HashTableScanFilter::Key(key),
+                    None => HashTableScanFilter::All,
+                };
+                let mut scanner = hash_table.scan_from(filter, cursor)?;
+                let mut entries = Vec::new();
+                while entries.len() < SCAN_BATCH_SIZE {
+                    let Some(mut entry) = scanner.next()? else {
+                        return Ok((entries, scanner.cursor(), true));
+                    };
+                    entries.push(OwnedEntry {
+                        key: entry.read_key_to_vec()?,
+                        value: entry.read_value_to_vec()?,
+                        metadata: entry.metadata()?,
+                    });
+                }
+                Ok((entries, scanner.cursor(), false))
+            }).await?;
+            self.buffered.extend(entries);
+            self.cursor = cursor;
+            self.done = done;
+        }
+        Ok(self.buffered.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeSet, io};
+    use crate::{dbms::test_config, pager::async_pager::block_on};
+
+    #[test]
+    fn test_async_hash_table() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let hash_table = AsyncManagedHashTable::new(ManagedHashTable::open(dir.path(), test_config())?)?;
+        block_on(async {
+            for i in 0..100 {
+                hash_table.insert(format!("key-{i}").as_bytes(), format!("value-{i}").as_bytes()).await?;
+            }
+            hash_table.insert(b"key-7", b"value-7b").await?;
+            hash_table.sync().await?;
+
+            assert_eq!(hash_table.get(b"key-7").await?, Some(b"value-7b".to_vec()));
+            assert_eq!(hash_table.get(b"missing").await?, None);
+
+            let mut scanner = hash_table.scan(HashTableScanFilter::Key(b"key-7"));
+            let mut values = Vec::new();
+            while let Some(entry) = scanner.next().await? {
+                values.push(entry.value);
+            }
+            assert_eq!(values.len(), 2);
+
+            // More than one batch.
+            let mut scanner = hash_table.scan(HashTableScanFilter::All);
+            let mut keys = BTreeSet::new();
+            let mut count = 0;
+            while let Some(entry) = scanner.next().await? {
+                keys.insert(entry.key);
+                count += 1;
+            }
+            assert_eq!((keys.len(), count), (100, 101));
+
+            let entry_count = hash_table.run(|hash_table| Ok(hash_table.stats()?.entry_count)).await?;
+            assert_eq!(entry_count, 101);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,227 @@
+use std::{collections::BTreeSet, io::{self, Read, Seek, SeekFrom, Write}, sync::Mutex};
+
+use crate::{crc32::Crc32, pager::{Page, PageIndex, PageSize, Pager, fs::{FilePage, FilePager}}, vfs::VfsFile};
+use crate::PoisonedLockError;
+
+/// Page index, image size and CRC-32 of the three, followed by the image.
+const RECORD_HEADER_SIZE: usize = 12;
+
+/// Images of the start of pages as they were at the last sync, kept in `pages.dwb` until the next one.
+struct Journal<F> {
+    file: F,
+    end: u64,
+    /// Pages whose image is in the journal, or that were empty at the last sync.
+    protected: BTreeSet<PageIndex>,
+}
+
+/// A `FilePager` that copies a page to a double-write journal before it is first written after
+/// a sync. A crash tearing a page write may destroy bytes that were synced before, such as the
+/// entries at the start of a section's last page, which the write-ahead log cannot restore as it
+/// holds no page contents; the next writable open copies the journaled images back instead.
+///
+/// Sections are only appended to, so only the bytes before the first write are journaled and
+/// restored, leaving those written after the sync to the recovery of the write-ahead log.
+///
+/// Every first write to a page costs a sync of the journal, hence `HashTableConfig::double_write`.
+pub(crate) struct DoubleWritePager<F> {
+    pager: FilePager<F>,
+    journal: Option<Mutex<Journal<F>>>,
+}
+
+impl<F: VfsFile> DoubleWritePager<F> {
+    pub(crate) fn new(pager: FilePager<F>) -> Self {
+        Self { pager, journal: None }
+    }
+
+    /// Restores the pages journaled before a crash, then journals pages written from now on
+    /// unless `enabled` is false.
+    pub(crate) fn with_journal(pager: FilePager<F>, mut file: F, enabled: bool) -> io::Result<Self> {
+        if !file.is_empty()? {
+            restore(&pager, &mut file)?;
+            pager.sync()?;
+            file.set_len(0)?;
+            file.sync_all()?;
+        }
+        let journal = enabled.then(|| Mutex::new(Journal { file, end: 0, protected: BTreeSet::new() }));
+        Ok(Self { pager, journal })
+    }
+
+    pub(crate) fn refresh(&self) -> io::Result<()> {
+        self.pager.refresh()
+    }
+
+    /// Syncs the pages, then empties the journal, as the images it holds are outdated from here on.
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.pager.sync()?;
+        if let Some(journal) = &self.journal {
+            let mut journal = journal.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+            if journal.end > 0 {
+                journal.file.set_len(0)?;
+                journal.file.sync_all()?;
+                journal.end = 0;
+            }
+            journal.protected.clear();
+        }
+        Ok(())
+    }
+
+    /// Journals the image of `page` unless it already is, syncing the journal before returning.
+    fn protect(&self, page: &FilePage<'_, F>) -> io::Result<()> {
+        let Some(journal) = &self.journal else {
+            return Ok(());
+        };
+        let mut journal = journal.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        if journal.protected.contains(&page.index()) {
+            return Ok(());
+        }
+        let mut reader = page.clone();
+        let mut image = vec![0u8; reader.stream_position()? as usize];
+        reader.rewind()?;
+        reader.read_exact(&mut image)?;
+        // Nothing to restore on a page never written.
+        if image.iter().any(|&byte| byte != 0) {
+            let mut header = [0u8; RECORD_HEADER_SIZE];
+            header[0..4].copy_from_slice(&page.index().to_le_bytes());
+            header[4..8].copy_from_slice(&(image.len() as u32).to_le_bytes());
+            let mut crc = Crc32::new();
+            crc.update(&header[0..8]);
+            crc.update(&image);
+            header[8..12].copy_from_slice(&crc.finalize().to_le_bytes());
+            let end = journal.end;
+            journal.file.seek(SeekFrom::Start(end))?;
+            journal.file.write_all(&header)?;
+            journal.file.write_all(&image)?;
+            journal.file.sync_data()?;
+            journal.end += (RECORD_HEADER_SIZE + image.len()) as u64;
+        }
+        journal.protected.insert(page.index());
+        Ok(())
+    }
+}
+
+/// Writes the images of the journal back into their pages, up to the first torn record.
+fn restore<F: VfsFile>(pager: &FilePager<F>, file: &mut F) -> io::Result<()> {
+    let mut image = Vec::new();
+    let mut header = [0u8; RECORD_HEADER_SIZE];
+    file.seek(SeekFrom::Start(0))?;
+    loop {
+        match file.read_exact(&mut header) {
+            Ok(()) => {},
+            // Torn while it was journaled, so the page write it protects never started.
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let page_index = PageIndex::from_le_bytes(header[0..4].try_into().unwrap());
+        let image_size = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if image_size > pager.page_size() {
+            return Ok(());
+        }
+        image.resize(image_size as usize, 0);
+        match file.read_exact(&mut image) {
+            Ok(()) => {},
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let mut crc = Crc32::new();
+        crc.update(&header[0..8]);
+        crc.update(&image);
+        if crc.finalize() != u32::from_le_bytes(header[8..12].try_into().unwrap()) {
+            return Ok(());
+        }
+        pager.page(page_index)?.write_all(&image)?;
+    }
+}
+
+impl<F: VfsFile> Pager for DoubleWritePager<F> {
+    type Page<'a> = DoubleWritePage<'a, F> where Self: 'a;
+
+    fn page_size(&self) -> PageSize {
+        self.pager.page_size()
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        Ok(DoubleWritePage {
+            page: self.pager.page(page_index)?,
+            pager: self,
+        })
+    }
+}
+
+pub(crate) struct DoubleWritePage<'a, F> {
+    page: FilePage<'a, F>,
+    pager: &'a DoubleWritePager<F>,
+}
+
+impl<F> Clone for DoubleWritePage<'_, F> {
+    fn clone(&self) -> Self {
+        Self {
+            page: self.page.clone(),
+            pager: self.pager,
+        }
+    }
+}
+
+impl<F: VfsFile> Page for DoubleWritePage<'_, F> {
+    fn index(&self) -> PageIndex {
+        self.page.index()
+    }
+}
+
+impl<F: VfsFile> Read for DoubleWritePage<'_, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.page.read(buf)
+    }
+}
+
+impl<F: VfsFile> Write for DoubleWritePage<'_, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.pager.protect(&self.page)?;
+        }
+        self.page.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.page.flush()
+    }
+}
+
+impl<F: VfsFile> Seek for DoubleWritePage<'_, F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.page.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test_torn_page_is_restored() -> io::Result<()> {
+        let (pages, journal) = (tempfile()?, tempfile()?);
+        let pager = DoubleWritePager::with_journal(FilePager::new(pages.try_clone()?, 64)?, journal.try_clone()?, true)?;
+        pager.page(1)?.write_all(b"synced")?;
+        pager.sync()?;
+        assert_eq!(journal.len()?, 0);
+
+        let mut page = pager.page(1)?;
+        page.seek(SeekFrom::Start(6))?;
+        page.write_all(b" and appended")?;
+        pager.page(1)?.write_all(b"more")?;
+        // One image per page and sync, of the bytes before the first write.
+        assert_eq!(journal.len()?, (RECORD_HEADER_SIZE + 6) as u64);
+        drop(pager);
+
+        // The crash tore the page, destroying the bytes synced before.
+        let torn = FilePager::new(pages.try_clone()?, 64)?;
+        torn.page(1)?.write_all(&[0xff; 3])?;
+
+        let pager = DoubleWritePager::with_journal(FilePager::new(pages, 64)?, journal.try_clone()?, false)?;
+        let mut image = [0u8; 19];
+        pager.page(1)?.read_exact(&mut image)?;
+        assert_eq!(&image, b"synced and appended");
+        assert_eq!(journal.len()?, 0);
+        Ok(())
+    }
+}
@@ -1,16 +1,137 @@
 use core::slice;
-use std::{fs::{self, create_dir_all}, io::{self}, path::Path};
+use std::{fmt, io::{self, Read, Write}, path::{Path, PathBuf}, sync::Arc, thread, time::{Duration, Instant}};
 
-use crate::{dbms::{index_registry::IndexEvent, section_registry::SectionEvent, wal::{ConvertWAL, FileWAL, FileWALReader, SerializableEvent, WALReader}}, pager::{PageSize, fs::FilePager}};
-use crate::hash_table::{self, HashTable, book::{BookHashTable, IndexChunkSize}, prefix_hasher::PrefixHasherBuilder};
-use crate::dbms::{index_registry::ManagedIndexRegistry, page_registry::{ManagedPageRegistry, PageEvent}, section_registry::ManagedSectionRegistry};
+use crate::{vfs::{StdFs, Vfs, VfsFile}, dbms::{index_registry::IndexEvent, key_sketch::KeySketchEvent, section_registry::SectionEvent, sync_sequence::SyncSequence, wal::{ConvertWAL, FileWAL, FileWALReader, SerializableEvent, WALReader, WALRecovery}}, pager::{PageSize, fs::FilePager}};
+use crate::hash_table::{self, HashTable, HashTableEntry, HashTableScanner, metrics::HashTableMetrics, book::{BloomConfig, BookHashTable, CorruptRange, DuplicateKeys, EntryChecksum, EntryMetadataFormat, EntryPreview, EntrySizeLimits, IndexChunkSize, SectionRegistry}, hasher_kind::HasherKind};
+use crate::dbms::{index_registry::{ManagedIndexRegistry, encode_index_entries}, double_write::DoubleWritePager, key_sketch::ManagedKeySketch, page_registry::{ManagedPageRegistry, PageEvent}, section_registry::ManagedSectionRegistry};
 use crate::book::{SectionIndex, pager::PagerBook};
 
+mod backup;
+mod builder;
+mod entry;
+mod hooks;
+mod rewrite;
+mod shared;
+mod stats;
+mod transaction;
+
+pub use builder::ManagedHashTableBuilder;
+pub use entry::Entry;
+pub use hooks::{CompactionEvent, InsertEvent, SyncEvent};
+pub use shared::SharedHashTable;
+pub use stats::HashTableStats;
+pub use transaction::{Savepoint, Transaction};
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct HashTableConfig {
     pub page_size: PageSize,
     pub section_count: SectionIndex,
     pub index_chunk_size: IndexChunkSize,
+    /// How keys are hashed to pick their section. Stores created before this field existed use
+    /// `HasherKind::Prefix`.
+    #[serde(default)]
+    pub hasher: HasherKind,
+    /// Shape of the bloom filters of the index chunks. Stores created before this field existed
+    /// use the default of 64 bits with one probe.
+    #[serde(default)]
+    pub bloom: BloomConfig,
+    /// Upper bounds for the key and value sizes accepted by `insert`.
+    /// Not part of the on-disk format, so they may change between opens.
+    #[serde(default)]
+    pub entry_size_limits: EntrySizeLimits,
+    /// How a torn tail of the write-ahead log is handled while opening.
+    #[serde(default)]
+    pub wal_recovery: WALRecovery,
+    /// Metadata stored with every entry, see `HashTableEntry::metadata`.
+    #[serde(default)]
+    pub entry_metadata: EntryMetadataFormat,
+    /// Checksum stored with every entry, see `EntryChecksum`.
+    #[serde(default)]
+    pub entry_checksum: EntryChecksum,
+    /// Limits on how large the store may grow, see `StoreQuotas`.
+    /// Not part of the on-disk format, so they may change between opens.
+    #[serde(default)]
+    pub quotas: StoreQuotas,
+    /// Number of pages whose contents are kept in memory after being read, zero for none.
+    /// Not part of the on-disk format, so it may change between opens.
+    #[serde(default)]
+    pub page_cache_pages: usize,
+    /// Whether scans return every entry of a key or only the latest, see `DuplicateKeys`.
+    /// Not part of the on-disk format, so it may change between opens.
+    #[serde(default)]
+    pub duplicate_keys: DuplicateKeys,
+    /// When inserts are synced without an explicit `sync`, see `SyncPolicy`.
+    /// Not part of the on-disk format, so it may change between opens.
+    #[serde(default)]
+    pub sync_policy: SyncPolicy,
+    /// Syncs made by a thread once the table is shared, see `BackgroundSync`.
+    /// Not part of the on-disk format, so it may change between opens.
+    #[serde(default)]
+    pub background_sync: Option<BackgroundSync>,
+    /// When `full_sync` compacts the store on its own, see `AutoCompaction`.
+    /// Not part of the on-disk format, so it may change between opens.
+    #[serde(default)]
+    pub auto_compaction: Option<AutoCompaction>,
+    /// Whether pages are copied to `pages.dwb` before they are first written after a sync, so a
+    /// crash tearing a page write cannot destroy bytes synced before. Costs a sync of that file
+    /// per page first written. Not part of the on-disk format, so it may change between opens.
+    #[serde(default)]
+    pub double_write: bool,
+}
+
+/// Periodic syncs made by a thread of the `SharedHashTable` returned by
+/// `ManagedHashTable::into_shared`, which stops with the last handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BackgroundSync {
+    /// How often the inserts made in the meantime are synced.
+    pub interval: Duration,
+    /// Bytes of events in the write-ahead log, its header not counted, from which on the inserts
+    /// are synced with a `full_sync` instead, so the log is checkpointed into the registries. `None` to leave `full_sync` to the application.
+    pub full_sync_wal_bytes: Option<u64>,
+}
+
+/// Compactions made by `full_sync` once the store reaches one of the limits, whether it is called
+/// by the application or by the `BackgroundSync` thread. Only made while there are shadowed
+/// entries to drop; `None` fields never trigger one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AutoCompaction {
+    /// Share of the sections' bytes taken by entries shadowed by a later entry of the same key,
+    /// from 0 to 1. Checking it reads the key of every entry in each `full_sync`.
+    pub dead_bytes_ratio: Option<f64>,
+    /// Size of the largest section.
+    pub section_bytes: Option<u64>,
+    /// Size of the write-ahead log checkpointed by the `full_sync`, i.e. written since the last one.
+    pub wal_bytes: Option<u64>,
+}
+
+/// When `insert` syncs on its own. An insert is only durable once a sync covers it, whichever
+/// policy is used; `sync` still works as usual with all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPolicy {
+    /// Only when `sync` or `full_sync` is called.
+    #[default]
+    Manual,
+    /// After every insert.
+    SyncEveryWrite,
+    /// Once this many inserts were made since the last sync.
+    SyncEveryNRecords(u64),
+    /// With the first insert made once this long passed since the last sync. Nothing is synced
+    /// while no inserts are made.
+    SyncEveryInterval(Duration),
+}
+
+/// Upper bounds on the size of a store; `None` fields are unlimited.
+/// An `insert` that would exceed one fails with `ManagedHashTableError::QuotaExceeded`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StoreQuotas {
+    /// Pages allocated in `pages.dat`.
+    pub max_pages: Option<u64>,
+    /// Size of the write-ahead log. It only shrinks in `full_sync`, so once it is reached inserts
+    /// fail until `full_sync` is called.
+    pub max_wal_bytes: Option<u64>,
+    /// Size of the allocated pages plus the write-ahead log.
+    pub max_total_bytes: Option<u64>,
 }
 
 impl Default for HashTableConfig {
@@ -19,15 +140,40 @@ impl Default for HashTableConfig {
             page_size: 4096,
             section_count: 1024,
             index_chunk_size: 4096,
+            hasher: HasherKind::default(),
+            bloom: BloomConfig::default(),
+            entry_size_limits: EntrySizeLimits::default(),
+            wal_recovery: WALRecovery::default(),
+            entry_metadata: EntryMetadataFormat::default(),
+            entry_checksum: EntryChecksum::default(),
+            quotas: StoreQuotas::default(),
+            page_cache_pages: 0,
+            duplicate_keys: DuplicateKeys::default(),
+            sync_policy: SyncPolicy::default(),
+            background_sync: None,
+            auto_compaction: None,
+            double_write: false,
         }
     }
 }
 
+/// Small pages, sections and index chunks, so that tests fill several of each with a few entries.
+#[cfg(test)]
+pub(crate) fn test_config() -> HashTableConfig {
+    HashTableConfig {
+        page_size: 64,
+        section_count: 4,
+        index_chunk_size: 64,
+        ..Default::default()
+    }
+}
+
 #[derive(Clone, Debug)]
 enum HashTableEvent {
     PageEvent(PageEvent),
     SectionEvent(SectionEvent),
     IndexEvent(IndexEvent),
+    KeySketchEvent(KeySketchEvent),
 }
 
 impl From<PageEvent> for HashTableEvent {
@@ -36,9 +182,9 @@ impl From<PageEvent> for HashTableEvent {
     }
 }
 
-impl Into<PageEvent> for HashTableEvent {
-    fn into(self) -> PageEvent {
-        match self {
+impl From<HashTableEvent> for PageEvent {
+    fn from(val: HashTableEvent) -> Self {
+        match val {
             HashTableEvent::PageEvent(event) => event,
             _ => panic!("Not a PageEvent"),
         }
@@ -51,9 +197,9 @@ impl From<SectionEvent> for HashTableEvent {
     }
 }
 
-impl Into<SectionEvent> for HashTableEvent {
-    fn into(self) -> SectionEvent {
-        match self {
+impl From<HashTableEvent> for SectionEvent {
+    fn from(val: HashTableEvent) -> Self {
+        match val {
             HashTableEvent::SectionEvent(event) => event,
             _ => panic!("Not a SectionEvent"),
         }
@@ -66,15 +212,30 @@ impl From<IndexEvent> for HashTableEvent {
     }
 }
 
-impl Into<IndexEvent> for HashTableEvent {
-    fn into(self) -> IndexEvent {
-        match self {
+impl From<HashTableEvent> for IndexEvent {
+    fn from(val: HashTableEvent) -> Self {
+        match val {
             HashTableEvent::IndexEvent(event) => event,
             _ => panic!("Not an IndexEvent"),
         }
     }
 }
 
+impl From<KeySketchEvent> for HashTableEvent {
+    fn from(event: KeySketchEvent) -> Self {
+        HashTableEvent::KeySketchEvent(event)
+    }
+}
+
+impl From<HashTableEvent> for KeySketchEvent {
+    fn from(val: HashTableEvent) -> Self {
+        match val {
+            HashTableEvent::KeySketchEvent(event) => event,
+            _ => panic!("Not a KeySketchEvent"),
+        }
+    }
+}
+
 impl SerializableEvent for HashTableEvent {
     fn read(reader: &mut impl io::Read) -> io::Result<Self> {
         let mut tag: u8 = 0;
@@ -93,6 +254,10 @@ impl SerializableEvent for HashTableEvent {
                 let event = IndexEvent::read(reader)?;
                 Ok(HashTableEvent::IndexEvent(event))
             }
+            4 => {
+                let event = KeySketchEvent::read(reader)?;
+                Ok(HashTableEvent::KeySketchEvent(event))
+            }
             _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown HashTableEvent type")),
         }
     }
@@ -111,6 +276,10 @@ impl SerializableEvent for HashTableEvent {
                 writer.write_all(&[3u8])?;
                 event.write(writer)?;
             },
+            HashTableEvent::KeySketchEvent(event) => {
+                writer.write_all(&[4u8])?;
+                event.write(writer)?;
+            },
         }
         Ok(())
     }
@@ -122,29 +291,32 @@ struct Header {
     config: HashTableConfig,
 }
 
-type TWAL = FileWAL<HashTableEvent>;
+type TWAL<F> = FileWAL<HashTableEvent, F>;
 
-type TPager = FilePager;
+type TPager<F> = DoubleWritePager<F>;
 
-type TPageRegistryWal = ConvertWAL<PageEvent, TWAL>;
-type TPageRegistry = ManagedPageRegistry<TPageRegistryWal>;
+type TPageRegistryWal<F> = ConvertWAL<PageEvent, TWAL<F>>;
+type TPageRegistry<F> = ManagedPageRegistry<TPageRegistryWal<F>, F>;
 
-type TBook = PagerBook<
-    TPager,
-    TPageRegistry,
+type TBook<F> = PagerBook<
+    TPager<F>,
+    TPageRegistry<F>,
 >;
 
-type TSectionRegistryWal = ConvertWAL<SectionEvent, TWAL>;
-type TSectionRegistry = ManagedSectionRegistry<TSectionRegistryWal>;
+type TSectionRegistryWal<F> = ConvertWAL<SectionEvent, TWAL<F>>;
+type TSectionRegistry<F> = ManagedSectionRegistry<TSectionRegistryWal<F>, F>;
 
-type TIndexRegistryWal = ConvertWAL<IndexEvent, TWAL>;
-type TIndexRegistry = ManagedIndexRegistry<TIndexRegistryWal>;
+type TIndexRegistryWal<F> = ConvertWAL<IndexEvent, TWAL<F>>;
+type TIndexRegistry<F> = ManagedIndexRegistry<TIndexRegistryWal<F>, F>;
 
-type THashTable = BookHashTable<
-    PrefixHasherBuilder,
-    TBook,
-    TSectionRegistry,
-    TIndexRegistry,
+type TKeySketchWal<F> = ConvertWAL<KeySketchEvent, TWAL<F>>;
+type TKeySketch<F> = ManagedKeySketch<TKeySketchWal<F>, F>;
+
+type THashTable<F> = BookHashTable<
+    HasherKind,
+    TBook<F>,
+    TSectionRegistry<F>,
+    TIndexRegistry<F>,
 >;
 
 /// ## Guarantees:
@@ -152,144 +324,611 @@ type THashTable = BookHashTable<
 /// - Duration of `sync` is independent of size of entries BUT their count.
 /// - `scan`s using `HashTableScanFilter::Key` will iterate over entries in the order of inserts.
 /// - `insert` operations are O(1) on average, and duration depends on the size of the entry being inserted.
-pub struct ManagedHashTable {
-    hash_table: THashTable,
-    wal: TWAL,
+///
+/// ## Sharing between processes:
+/// Only one writer may have a store open at a time; it holds an exclusive lock on `store.lock`, and
+/// other writers fail with `ManagedHashTableError::Locked`. Any number of instances opened read-only
+/// may attach next to it and follow its syncs with `refresh` or `wait_for_sync`.
+pub struct ManagedHashTable<V: Vfs = StdFs> {
+    vfs: V,
+    dir_path: PathBuf,
+    config: HashTableConfig,
+    hash_table: THashTable<V::File>,
+    key_sketch: TKeySketch<V::File>,
+    wal: TWAL<V::File>,
+    discarded_wal_bytes: u64,
+    read_only: bool,
+    /// `None` when read-only.
+    sync_file: Option<V::File>,
+    sync_sequence: SyncSequence,
+    /// Where the events applied to the registries end in the write-ahead log. For the writer,
+    /// where the events committed by the last sync end.
+    wal_replay_height: u64,
+    /// Inserts made since the last sync, for the `SyncPolicy`.
+    unsynced_inserts: u64,
+    last_sync: Instant,
+    hooks: hooks::Hooks,
+    /// Holds the exclusive lock of the writer, `None` when read-only.
+    _lock_file: Option<V::File>,
 }
 
-impl ManagedHashTable {
-    pub fn open(dir_path: impl AsRef<Path>, config: HashTableConfig) -> io::Result<Self> {
-        create_dir_all(&dir_path)?;
+/// Errors raised by `ManagedHashTable` itself, converted into `io::Error`s and from there into `crate::Error`s.
+#[derive(Debug, thiserror::Error)]
+pub enum ManagedHashTableError {
+    /// The store was created with a different on-disk format than the one requested.
+    /// Such stores cannot be opened as is; open them with `open_with_existing_config` or
+    /// migrate them by copying the entries into a new store.
+    #[error("Config field `{field}` is {on_disk} on disk, but {requested} was requested")]
+    ConfigMismatch { field: &'static str, on_disk: String, requested: String },
+    #[error("No store found at {}", dir_path.display())]
+    NotFound { dir_path: PathBuf },
+    #[error("Invalid option `{option}`: {reason}")]
+    InvalidOption { option: &'static str, reason: &'static str },
+    #[error("The store is opened read-only")]
+    ReadOnly,
+    /// Another writer, possibly in another process, has the store open.
+    #[error("Store at {} is locked by another writer", dir_path.display())]
+    Locked { dir_path: PathBuf },
+    /// The key was changed since the caller read it, see `ManagedHashTable::insert_if_version`.
+    #[error("Key is at version {actual:?}, but {expected:?} was expected")]
+    VersionConflict { expected: Option<u64>, actual: Option<u64> },
+    #[error("Quota `{quota}` of {limit} would be exceeded, {requested} needed")]
+    QuotaExceeded { quota: &'static str, limit: u64, requested: u64 },
+}
+
+impl From<ManagedHashTableError> for io::Error {
+    fn from(err: ManagedHashTableError) -> Self {
+        let kind = match err {
+            ManagedHashTableError::ConfigMismatch { .. } => io::ErrorKind::InvalidData,
+            ManagedHashTableError::NotFound { .. } => io::ErrorKind::NotFound,
+            ManagedHashTableError::InvalidOption { .. } => io::ErrorKind::InvalidInput,
+            ManagedHashTableError::ReadOnly => io::ErrorKind::PermissionDenied,
+            ManagedHashTableError::Locked { .. } => io::ErrorKind::WouldBlock,
+            ManagedHashTableError::VersionConflict { .. } => io::ErrorKind::Other,
+            ManagedHashTableError::QuotaExceeded { .. } => io::ErrorKind::QuotaExceeded,
+        };
+        io::Error::new(kind, err)
+    }
+}
 
-        let header_path = dir_path.as_ref().join("header.json");
+impl From<ManagedHashTableError> for crate::Error {
+    fn from(err: ManagedHashTableError) -> Self {
+        io::Error::from(err).into()
+    }
+}
 
-        let header = if header_path.try_exists()? {
-            let header_file = fs::OpenOptions::new()
-                .read(true)
-                .open(&header_path)?;
-            let header: Header = serde_json::from_reader(&header_file)
-                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse metadata: {}", err)))?;
+impl HashTableConfig {
+    /// Rejects configs the hash table cannot work with, naming the offending field.
+    pub fn validate(&self) -> Result<(), ManagedHashTableError> {
+        let invalid = |option, reason| Err(ManagedHashTableError::InvalidOption { option, reason });
+        if self.page_size == 0 {
+            return invalid("page_size", "must be greater than zero");
+        }
+        if self.section_count == 0 {
+            return invalid("section_count", "must be greater than zero, as keys are distributed over the sections");
+        }
+        if self.index_chunk_size == 0 {
+            return invalid("index_chunk_size", "must be greater than zero, as section offsets are divided by it");
+        }
+        if let Some(reason) = self.bloom.invalid_reason() {
+            return invalid("bloom", reason);
+        }
+        if self.sync_policy == SyncPolicy::SyncEveryNRecords(0) {
+            return invalid("sync_policy", "must sync after at least one record");
+        }
+        if self.background_sync.is_some_and(|background_sync| background_sync.interval.is_zero()) {
+            return invalid("background_sync", "interval must be greater than zero");
+        }
+        if self.auto_compaction.and_then(|auto_compaction| auto_compaction.dead_bytes_ratio).is_some_and(|ratio| !(0.0..=1.0).contains(&ratio)) {
+            return invalid("auto_compaction", "dead_bytes_ratio must be between 0 and 1");
+        }
+        Ok(())
+    }
 
-            if header.config.page_size != config.page_size {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "Page size in metadata does not match the provided configuration"));
+    /// Checks that a store created with `self` can be opened with `requested`.
+    /// Only the fields defining the on-disk format have to match.
+    pub fn check_compatible(&self, requested: &HashTableConfig) -> Result<(), ManagedHashTableError> {
+        fn check<T: PartialEq + fmt::Debug>(field: &'static str, on_disk: T, requested: T) -> Result<(), ManagedHashTableError> {
+            if on_disk == requested {
+                return Ok(());
             }
+            Err(ManagedHashTableError::ConfigMismatch {
+                field,
+                on_disk: format!("{:?}", on_disk),
+                requested: format!("{:?}", requested),
+            })
+        }
 
-            if header.config.section_count != config.section_count {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "Section count in metadata does not match the provided configuration"));
-            }
+        check("page_size", self.page_size, requested.page_size)?;
+        check("section_count", self.section_count, requested.section_count)?;
+        check("index_chunk_size", self.index_chunk_size, requested.index_chunk_size)?;
+        check("hasher", self.hasher, requested.hasher)?;
+        check("bloom", self.bloom, requested.bloom)?;
+        check("entry_metadata", self.entry_metadata, requested.entry_metadata)?;
+        check("entry_checksum", self.entry_checksum, requested.entry_checksum)?;
+        Ok(())
+    }
+}
 
-            if header.config.index_chunk_size != config.index_chunk_size {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "Index chunk size in metadata does not match the provided configuration"));
-            }
+/// Options of a single `open`; `None` fields are taken from the existing store,
+/// or from `HashTableConfig::default()` when it is created.
+#[derive(Clone, Debug, Default)]
+struct OpenOptions {
+    page_size: Option<PageSize>,
+    section_count: Option<SectionIndex>,
+    index_chunk_size: Option<IndexChunkSize>,
+    hasher: Option<HasherKind>,
+    bloom: Option<BloomConfig>,
+    entry_size_limits: Option<EntrySizeLimits>,
+    wal_recovery: Option<WALRecovery>,
+    entry_metadata: Option<EntryMetadataFormat>,
+    entry_checksum: Option<EntryChecksum>,
+    quotas: Option<StoreQuotas>,
+    page_cache_pages: Option<usize>,
+    duplicate_keys: Option<DuplicateKeys>,
+    sync_policy: Option<SyncPolicy>,
+    background_sync: Option<Option<BackgroundSync>>,
+    auto_compaction: Option<Option<AutoCompaction>>,
+    double_write: Option<bool>,
+    create_if_missing: Option<bool>,
+    read_only: bool,
+}
 
-            header
+impl OpenOptions {
+    fn from_config(config: HashTableConfig) -> Self {
+        Self {
+            page_size: Some(config.page_size),
+            section_count: Some(config.section_count),
+            index_chunk_size: Some(config.index_chunk_size),
+            hasher: Some(config.hasher),
+            bloom: Some(config.bloom),
+            entry_size_limits: Some(config.entry_size_limits),
+            wal_recovery: Some(config.wal_recovery),
+            entry_metadata: Some(config.entry_metadata),
+            entry_checksum: Some(config.entry_checksum),
+            quotas: Some(config.quotas),
+            page_cache_pages: Some(config.page_cache_pages),
+            duplicate_keys: Some(config.duplicate_keys),
+            sync_policy: Some(config.sync_policy),
+            background_sync: Some(config.background_sync),
+            auto_compaction: Some(config.auto_compaction),
+            double_write: Some(config.double_write),
+            ..Default::default()
+        }
+    }
+
+    fn apply_to(&self, config: HashTableConfig) -> HashTableConfig {
+        HashTableConfig {
+            page_size: self.page_size.unwrap_or(config.page_size),
+            section_count: self.section_count.unwrap_or(config.section_count),
+            index_chunk_size: self.index_chunk_size.unwrap_or(config.index_chunk_size),
+            hasher: self.hasher.unwrap_or(config.hasher),
+            bloom: self.bloom.unwrap_or(config.bloom),
+            entry_size_limits: self.entry_size_limits.unwrap_or(config.entry_size_limits),
+            wal_recovery: self.wal_recovery.unwrap_or(config.wal_recovery),
+            entry_metadata: self.entry_metadata.unwrap_or(config.entry_metadata),
+            entry_checksum: self.entry_checksum.unwrap_or(config.entry_checksum),
+            quotas: self.quotas.unwrap_or(config.quotas),
+            page_cache_pages: self.page_cache_pages.unwrap_or(config.page_cache_pages),
+            duplicate_keys: self.duplicate_keys.unwrap_or(config.duplicate_keys),
+            sync_policy: self.sync_policy.unwrap_or(config.sync_policy),
+            background_sync: self.background_sync.unwrap_or(config.background_sync),
+            auto_compaction: self.auto_compaction.unwrap_or(config.auto_compaction),
+            double_write: self.double_write.unwrap_or(config.double_write),
+        }
+    }
+
+    fn create_if_missing(&self) -> bool {
+        self.create_if_missing.unwrap_or(!self.read_only)
+    }
+}
+
+impl ManagedHashTable {
+    pub fn open(dir_path: impl AsRef<Path>, config: HashTableConfig) -> crate::Result<Self> {
+        Self::open_with_vfs(StdFs, dir_path, config)
+    }
+
+    /// Opens an existing store with the config it was created with.
+    pub fn open_with_existing_config(dir_path: impl AsRef<Path>) -> crate::Result<Self> {
+        Self::open_existing_with_vfs(StdFs, dir_path)
+    }
+
+    pub fn builder(dir_path: impl AsRef<Path>) -> ManagedHashTableBuilder {
+        ManagedHashTableBuilder::new(dir_path)
+    }
+}
+
+impl<V: Vfs> ManagedHashTable<V> {
+    pub fn open_with_vfs(vfs: V, dir_path: impl AsRef<Path>, config: HashTableConfig) -> crate::Result<Self> {
+        Ok(Self::open_inner(vfs, dir_path.as_ref(), OpenOptions::from_config(config))?)
+    }
+
+    /// Like `open_with_existing_config`, on the given file system.
+    pub fn open_existing_with_vfs(vfs: V, dir_path: impl AsRef<Path>) -> crate::Result<Self> {
+        Ok(Self::open_inner(vfs, dir_path.as_ref(), OpenOptions {
+            create_if_missing: Some(false),
+            ..Default::default()
+        })?)
+    }
+
+    fn open_inner(vfs: V, dir_path: &Path, options: OpenOptions) -> io::Result<Self> {
+        let dir_path = dir_path.to_path_buf();
+        let header_path = dir_path.join("header.json");
+        let read_only = options.read_only;
+        rewrite::finish_interrupted_rewrite(&vfs, &dir_path, read_only)?;
+
+        let header = if vfs.exists(&header_path)? {
+            let mut header_file = vfs.open_read_only(&header_path)?;
+            let mut header_bytes = Vec::new();
+            header_file.read_to_end(&mut header_bytes)?;
+            let header: Header = serde_json::from_slice(&header_bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse metadata: {}", err)))?;
+
+            let config = options.apply_to(header.config.clone());
+            config.validate()?;
+            header.config.check_compatible(&config)?;
+
+            Header {
+                config,
+            }
         } else {
-            let header_file = fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(&header_path)?;
+            if !options.create_if_missing() {
+                return Err(ManagedHashTableError::NotFound { dir_path }.into());
+            }
             let header = Header {
-                config,
+                config: options.apply_to(HashTableConfig::default()),
             };
+            header.config.validate()?;
+            vfs.create_dir_all(&dir_path)?;
 
-            serde_json::to_writer_pretty(&header_file, &header)
-                .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("Failed to write metadata: {}", err)))?;
+            let header_bytes = serde_json::to_vec_pretty(&header)
+                .map_err(|err| io::Error::other(format!("Failed to write metadata: {}", err)))?;
+            write_file_atomically(&vfs, &dir_path, "header.json", &header_bytes)?;
 
             header
         };
 
-        let wal_path = dir_path.as_ref().join("events.log");
-        let wal_file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&wal_path)?;
-
-        let pages_path = dir_path.as_ref().join("pages.dat");
-        let pages_file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&pages_path)?;
+        // Taken before anything is written, e.g. the torn tail of the write-ahead log truncated.
+        let lock_file = if read_only {
+            None
+        } else {
+            let lock_file = vfs.open(&dir_path.join("store.lock"))?;
+            lock_file.try_lock().map_err(|err| match err.kind() {
+                io::ErrorKind::WouldBlock => ManagedHashTableError::Locked { dir_path: dir_path.clone() }.into(),
+                _ => err,
+            })?;
+            Some(lock_file)
+        };
+
+        let pages_file = open_store_file(&vfs, &dir_path, "pages.dat", read_only)?;
         let pager = FilePager::new(pages_file, header.config.page_size)?;
+        // Restores the pages a crash tore before anything reads them, even if no longer enabled.
+        let pager = if !read_only && (header.config.double_write || vfs.exists(&dir_path.join("pages.dwb"))?) {
+            let journal_file = open_store_file(&vfs, &dir_path, "pages.dwb", false)?;
+            DoubleWritePager::with_journal(pager, journal_file, header.config.double_write)?
+        } else {
+            DoubleWritePager::new(pager)
+        };
 
-        let page_registry_file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&dir_path.as_ref().join("pages.reg"))?;
-        let mut page_registry = ManagedPageRegistry::load(
-            page_registry_file,
-        )?;
-
-        let section_registry_file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&dir_path.as_ref().join("sections.reg"))?;
-        let mut section_registry = ManagedSectionRegistry::load(
-            section_registry_file,
-            header.config.section_count,
-        )?;
-
-        let index_registry_file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&dir_path.as_ref().join("indexes.reg"))?;
-        let mut index_registry = ManagedIndexRegistry::load(
-            index_registry_file,
-        )?;
-
-        let mut wal_reader = FileWALReader::<HashTableEvent>::new(wal_file)?;
-        while let Some(event) = wal_reader.read_next()? {
-            match event {
-                HashTableEvent::PageEvent(page_event) => page_registry.apply(page_event)?,
-                HashTableEvent::SectionEvent(section_event) => section_registry.apply(section_event)?,
-                HashTableEvent::IndexEvent(index_event) => index_registry.apply(index_event)?,
-            }
-        }
+        let (registries, sync_sequence, sync_file) = if read_only {
+            let (registries, sync_sequence) = load_registries_consistently(&vfs, &dir_path, &header.config)?;
+            (registries, sync_sequence, None)
+        } else {
+            let registries = load_registries(&vfs, &dir_path, &header.config, false)?;
+            let mut sync_file = open_store_file(&vfs, &dir_path, "sync.seq", false)?;
+            let sync_sequence = SyncSequence::read(&mut sync_file)?;
+            (registries, sync_sequence, Some(sync_file))
+        };
+        let LoadedRegistries { page_registry, section_registry, index_registry, key_sketch, wal_reader } = registries;
 
-        let wal = FileWAL::load(wal_reader.into_file())?;
+        let discarded_wal_bytes = wal_reader.discarded_bytes();
+        let wal_replay_height = wal_reader.height().unwrap_or(WAL_START);
+        let wal = if read_only {
+            FileWAL::read_only(wal_reader.into_file())
+        } else {
+            FileWAL::load(wal_reader.into_file())?
+        };
         let page_registry = ManagedPageRegistry::with_wal(page_registry, ConvertWAL::new(wal.clone()));
         let section_registry = ManagedSectionRegistry::with_wal(section_registry, ConvertWAL::new(wal.clone()));
         let index_registry = ManagedIndexRegistry::with_wal(index_registry, ConvertWAL::new(wal.clone()));
+        let key_sketch = key_sketch.with_wal(ConvertWAL::new(wal.clone()));
 
         let book = PagerBook::new(
             pager,
             page_registry,
-        );
+        )
+            .with_page_cache(header.config.page_cache_pages);
 
-        let hash_table = BookHashTable::new(
-            PrefixHasherBuilder,
+        let mut hash_table = BookHashTable::new(
+            header.config.hasher,
             book,
             header.config.section_count,
             section_registry,
             header.config.index_chunk_size,
             index_registry,
-        );
+        )
+            .with_limits(header.config.entry_size_limits)
+            .with_metadata_format(header.config.entry_metadata)
+            .with_checksum(header.config.entry_checksum)
+            .with_duplicate_keys(header.config.duplicate_keys)
+            .with_bloom(header.config.bloom);
+        hash_table.recover_sequence()?;
 
         let mut managed = ManagedHashTable {
+            vfs,
+            dir_path,
+            config: header.config,
             hash_table,
+            key_sketch,
             wal,
+            discarded_wal_bytes,
+            read_only,
+            sync_file,
+            sync_sequence,
+            wal_replay_height,
+            unsynced_inserts: 0,
+            last_sync: Instant::now(),
+            hooks: hooks::Hooks::default(),
+            _lock_file: lock_file,
         };
 
-        managed.full_sync()?;
+        if !read_only {
+            // Make sure the files created above are reachable after a crash.
+            managed.vfs.sync_dir(&managed.dir_path)?;
+            managed.full_sync_inner()?;
+        }
 
         Ok(managed)
     }
 }
 
-impl ManagedHashTable {
-    pub fn sync(&mut self) -> io::Result<()> {
+/// Offset of the first record in the write-ahead log, after its height.
+const WAL_START: u64 = 8;
+
+/// How often a read-only open retries loading the registries while the writer keeps replacing them.
+const MAX_LOAD_ATTEMPTS: usize = 16;
+
+/// The registries as saved in their files, with the committed events of the write-ahead log applied.
+struct LoadedRegistries<F> {
+    page_registry: TPageRegistry<F>,
+    section_registry: TSectionRegistry<F>,
+    index_registry: TIndexRegistry<F>,
+    key_sketch: TKeySketch<F>,
+    wal_reader: FileWALReader<HashTableEvent, F>,
+}
+
+fn open_store_file<V: Vfs>(vfs: &V, dir_path: &Path, file_name: &str, read_only: bool) -> io::Result<V::File> {
+    let file_path = dir_path.join(file_name);
+    if read_only {
+        vfs.open_read_only(&file_path)
+    } else {
+        vfs.open(&file_path)
+    }
+}
+
+fn load_registries<V: Vfs>(vfs: &V, dir_path: &Path, config: &HashTableConfig, read_only: bool) -> io::Result<LoadedRegistries<V::File>> {
+    let wal_file = open_store_file(vfs, dir_path, "events.log", read_only)?;
+
+    let mut page_registry = ManagedPageRegistry::load(
+        open_store_file(vfs, dir_path, "pages.reg", read_only)?,
+    )?;
+
+    let mut section_registry = ManagedSectionRegistry::load(
+        open_store_file(vfs, dir_path, "sections.reg", read_only)?,
+        config.section_count,
+    )?;
+
+    let mut index_registry = ManagedIndexRegistry::load(
+        open_store_file(vfs, dir_path, "indexes.reg", read_only)?,
+        open_store_file(vfs, dir_path, "filters.reg", read_only)?,
+        config.section_count,
+        config.bloom.words(),
+    )?;
+
+    let mut key_sketch = ManagedKeySketch::load(
+        open_store_file(vfs, dir_path, "keys.hll", read_only)?,
+    )?;
+
+    let mut wal_reader = if read_only {
+        FileWALReader::<HashTableEvent, _>::new_read_only(wal_file, config.wal_recovery)?
+    } else {
+        FileWALReader::<HashTableEvent, _>::new(wal_file, config.wal_recovery)?
+    };
+    while let Some(event) = wal_reader.read_next()? {
+        match event {
+            HashTableEvent::PageEvent(page_event) => page_registry.apply(page_event)?,
+            HashTableEvent::SectionEvent(section_event) => section_registry.apply(section_event)?,
+            HashTableEvent::IndexEvent(index_event) => index_registry.apply(index_event)?,
+            HashTableEvent::KeySketchEvent(key_sketch_event) => key_sketch.apply(key_sketch_event)?,
+        }
+    }
+
+    Ok(LoadedRegistries { page_registry, section_registry, index_registry, key_sketch, wal_reader })
+}
+
+/// Loads the registries of a store another process may be writing to, retrying when a `full_sync`
+/// replaced them in the meantime. Returns the sync sequence read before loading.
+fn load_registries_consistently<V: Vfs>(vfs: &V, dir_path: &Path, config: &HashTableConfig) -> io::Result<(LoadedRegistries<V::File>, SyncSequence)> {
+    for _ in 0..MAX_LOAD_ATTEMPTS {
+        let sync_sequence = read_sync_sequence(vfs, dir_path)?;
+        let registries = load_registries(vfs, dir_path, config, true);
+        if read_sync_sequence(vfs, dir_path)?.checkpoint == sync_sequence.checkpoint {
+            return Ok((registries?, sync_sequence));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::Interrupted, "Store kept changing while loading its registries"))
+}
+
+fn read_sync_sequence<V: Vfs>(vfs: &V, dir_path: &Path) -> io::Result<SyncSequence> {
+    let file_path = dir_path.join("sync.seq");
+    if !vfs.exists(&file_path)? {
+        return Ok(SyncSequence::default());
+    }
+    SyncSequence::read(&mut vfs.open_read_only(&file_path)?)
+}
+
+/// Replaces `dir_path/file_name` with `bytes` such that a crash leaves either the old or the new
+/// contents behind, never a partial file.
+pub(crate) fn write_file_atomically<V: Vfs>(vfs: &V, dir_path: &Path, file_name: &str, bytes: &[u8]) -> io::Result<()> {
+    let file_path = dir_path.join(file_name);
+    let temp_file_path = dir_path.join(format!("{}.tmp", file_name));
+    let mut file = vfs.open(&temp_file_path)?;
+    file.set_len(0)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    vfs.rename(&temp_file_path, &file_path)?;
+    vfs.sync_dir(dir_path)
+}
+
+impl<V: Vfs> ManagedHashTable<V> {
+    pub fn dir_path(&self) -> &Path {
+        &self.dir_path
+    }
+
+    /// The config the store is opened with.
+    pub fn config(&self) -> &HashTableConfig {
+        &self.config
+    }
+
+    /// Number of uncommitted write-ahead log bytes that were discarded while opening.
+    pub fn discarded_wal_bytes(&self) -> u64 {
+        self.discarded_wal_bytes
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The writer's progress as of its last `sync`, or as seen by the last `refresh` when read-only.
+    pub fn sync_sequence(&self) -> SyncSequence {
+        self.sync_sequence
+    }
+
+    /// Number of `full_sync`s the store went through, which never decreases, not even across a
+    /// crash. Copies or caches of the store can be tagged with it to tell their generations apart.
+    pub fn epoch(&self) -> u64 {
+        self.sync_sequence.checkpoint
+    }
+
+    /// Estimated number of distinct keys, within a few percent, from a HyperLogLog sketch updated
+    /// on insert. Keys inserted before the store kept the sketch in `keys.hll` are not counted.
+    pub fn approx_key_count(&self) -> u64 {
+        self.key_sketch.estimate()
+    }
+
+    /// Calls `hook` after every successful insert.
+    pub fn on_insert(&mut self, hook: impl FnMut(&InsertEvent) + Send + 'static) {
+        self.hooks.add_insert(Box::new(hook));
+    }
+
+    /// Reports inserts, scans, bloom filter checks and syncs to `metrics` from now on.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn HashTableMetrics>) {
+        self.hash_table.set_metrics(Some(metrics));
+    }
+
+    /// Calls `hook` after every successful `sync` and `full_sync`.
+    pub fn on_sync(&mut self, hook: impl FnMut(&SyncEvent) + Send + 'static) {
+        self.hooks.add_sync(Box::new(hook));
+    }
+
+    /// Calls `hook` before `compact` or `resize_sections` starts rewriting the store.
+    pub fn on_compaction_start(&mut self, hook: impl FnMut(&CompactionEvent) + Send + 'static) {
+        self.hooks.add_compaction_start(Box::new(hook));
+    }
+
+    /// Calls `hook` once `compact` or `resize_sections` replaced the store with the rewritten one.
+    /// Not called if the rewrite failed.
+    pub fn on_compaction_finish(&mut self, hook: impl FnMut(&CompactionEvent) + Send + 'static) {
+        self.hooks.add_compaction_finish(Box::new(hook));
+    }
+
+    pub fn sync(&mut self) -> crate::Result<()> {
+        let started = Instant::now();
+        self.sync_inner()?;
+        if let Some(metrics) = self.hash_table.metrics() {
+            metrics.sync(false, started.elapsed());
+        }
+        self.hooks.sync(&SyncEvent { full: false, sync_sequence: self.sync_sequence });
+        Ok(())
+    }
+
+    fn sync_inner(&mut self) -> io::Result<()> {
+        self.check_writable()?;
         self.hash_table.book().pager().sync()?;
         self.wal.sync()?;
 
+        self.wal_replay_height = self.wal.height()?;
+        self.sync_sequence.sync += 1;
+        self.write_sync_sequence()?;
+
+        self.unsynced_inserts = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Whether inserts were made since the last sync. Calling `sync` only when they were
+    /// coalesces the syncs of writers sharing the store, see `SharedHashTable::sync`.
+    pub fn has_unsynced_inserts(&self) -> crate::Result<bool> {
+        Ok(self.wal.height()? != self.wal_replay_height)
+    }
+
+    /// Bytes of the events in the write-ahead log, i.e. written since the last `full_sync`.
+    fn wal_bytes(&self) -> io::Result<u64> {
+        Ok(self.wal.height()?.saturating_sub(WAL_START))
+    }
+
+    /// Syncs after an insert if the `SyncPolicy` asks for it.
+    fn apply_sync_policy(&mut self) -> io::Result<()> {
+        self.unsynced_inserts += 1;
+        let due = match self.config.sync_policy {
+            SyncPolicy::Manual => false,
+            SyncPolicy::SyncEveryWrite => true,
+            SyncPolicy::SyncEveryNRecords(records) => self.unsynced_inserts >= records,
+            SyncPolicy::SyncEveryInterval(interval) => self.last_sync.elapsed() >= interval,
+        };
+        if due {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs, saves the registries and clears the write-ahead log, then compacts the store if it
+    /// reached a limit of the `AutoCompaction`.
+    pub fn full_sync(&mut self) -> crate::Result<()> {
+        let wal_bytes = self.wal_bytes()?;
+        self.full_sync_inner()?;
+        if self.auto_compaction_due(wal_bytes)? {
+            self.rewrite(self.config.clone(), DuplicateKeys::LatestWins)?;
+        }
         Ok(())
     }
 
-    pub fn full_sync(&mut self) -> io::Result<()> {
-        self.sync()?;
+    /// Whether the `AutoCompaction` limits ask for a compaction after a `full_sync` that
+    /// checkpointed `wal_bytes`.
+    fn auto_compaction_due(&mut self, wal_bytes: u64) -> io::Result<bool> {
+        let Some(auto_compaction) = self.config.auto_compaction else {
+            return Ok(false);
+        };
+        let section_bytes = (0..self.config.section_count)
+            .map(|section_index| Ok(self.hash_table.section_registry().resolve_section(section_index)?.end_offset))
+            .collect::<io::Result<Vec<_>>>()?;
+        let limit_reached = auto_compaction.wal_bytes.is_some_and(|limit| wal_bytes >= limit)
+            || auto_compaction.section_bytes.is_some_and(|limit| section_bytes.iter().any(|bytes| *bytes >= limit));
+        if !limit_reached && auto_compaction.dead_bytes_ratio.is_none() {
+            return Ok(false);
+        }
+        let shadowed_bytes = self.hash_table.shadowed_entry_bytes()?;
+        let total_bytes = section_bytes.iter().sum::<u64>();
+        let ratio_reached = auto_compaction.dead_bytes_ratio
+            .is_some_and(|limit| total_bytes > 0 && shadowed_bytes as f64 / total_bytes as f64 >= limit);
+        Ok(shadowed_bytes > 0 && (limit_reached || ratio_reached))
+    }
+
+    /// `full_sync` without the `AutoCompaction`.
+    fn full_sync_inner(&mut self) -> io::Result<()> {
+        let started = Instant::now();
+        self.sync_inner()?;
 
         // TODO: Acquire locks in a consistent order to avoid deadlocks
 
@@ -299,18 +938,1341 @@ impl ManagedHashTable {
 
         self.hash_table.index_registry().save()?;
 
+        self.key_sketch.save()?;
+
+        self.vfs.sync_dir(&self.dir_path)?;
+
+        // Announced before the log is cleared, so readers tailing it notice they have to reload.
+        // Synced, unlike the sync counter, as it is the store's epoch.
+        self.sync_sequence.checkpoint += 1;
+        self.write_sync_sequence()?;
+        if let Some(sync_file) = &mut self.sync_file {
+            sync_file.sync_all()?;
+        }
+
         self.wal.clear()?;
+        self.wal_replay_height = WAL_START;
 
+        if let Some(metrics) = self.hash_table.metrics() {
+            metrics.sync(true, started.elapsed());
+        }
+        self.hooks.sync(&SyncEvent { full: true, sync_sequence: self.sync_sequence });
+        Ok(())
+    }
+
+    /// Catches up with the writer of a store opened read-only, e.g. by another process or on a
+    /// replicated copy of its directory. Returns whether anything changed.
+    ///
+    /// The events committed by the writer's `sync`s since the last refresh are applied to the
+    /// registries; only after a `full_sync` are the registries reloaded from their files.
+    /// Does nothing for the writer itself.
+    pub fn refresh(&mut self) -> crate::Result<bool> {
+        if !self.read_only {
+            return Ok(false);
+        }
+        let sync_sequence = read_sync_sequence(&self.vfs, &self.dir_path)?;
+        if sync_sequence == self.sync_sequence {
+            return Ok(false);
+        }
+
+        if sync_sequence.checkpoint == self.sync_sequence.checkpoint {
+            let replayed = self.replay_wal_tail();
+            // A `full_sync` in the meantime may have cleared the log while it was read.
+            if read_sync_sequence(&self.vfs, &self.dir_path)?.checkpoint == sync_sequence.checkpoint && replayed? {
+                self.sync_sequence = sync_sequence;
+                self.hash_table.book().pager().refresh()?;
+                self.hash_table.book().clear_page_cache()?;
+                return Ok(true);
+            }
+        }
+
+        let (registries, sync_sequence) = load_registries_consistently(&self.vfs, &self.dir_path, &self.config)?;
+        let LoadedRegistries { page_registry, section_registry, index_registry, key_sketch, wal_reader } = registries;
+        self.wal_replay_height = wal_reader.height().unwrap_or(WAL_START);
+        *self.hash_table.book().registry()? = page_registry.with_wal(ConvertWAL::new(self.wal.clone()));
+        *self.hash_table.section_registry() = section_registry.with_wal(ConvertWAL::new(self.wal.clone()));
+        *self.hash_table.index_registry() = index_registry.with_wal(ConvertWAL::new(self.wal.clone()));
+        self.key_sketch = key_sketch.with_wal(ConvertWAL::new(self.wal.clone()));
+        self.sync_sequence = sync_sequence;
+        self.hash_table.book().pager().refresh()?;
+        self.hash_table.book().clear_page_cache()?;
+        Ok(true)
+    }
+
+    /// Waits until the writer of a store opened read-only syncs, polling its `sync.seq` every
+    /// `poll_interval`, and refreshes. Returns `false` if nothing changed within `timeout`.
+    pub fn wait_for_sync(&mut self, poll_interval: Duration, timeout: Duration) -> crate::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.refresh()? {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if !self.read_only || now >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(poll_interval.min(deadline - now));
+        }
+    }
+
+    /// Applies the events committed after `wal_replay_height`; `false` if the log no longer reaches it.
+    fn replay_wal_tail(&mut self) -> io::Result<bool> {
+        let wal_file = self.vfs.open_read_only(&self.dir_path.join("events.log"))?;
+        let mut wal_reader = FileWALReader::<HashTableEvent, _>::new_read_only(wal_file, self.config.wal_recovery)?;
+        if wal_reader.height().unwrap_or(WAL_START) < self.wal_replay_height {
+            return Ok(false);
+        }
+        wal_reader.skip_to(self.wal_replay_height)?;
+        while let Some(event) = wal_reader.read_next()? {
+            match event {
+                HashTableEvent::PageEvent(page_event) => self.hash_table.book().registry()?.apply(page_event)?,
+                HashTableEvent::SectionEvent(section_event) => self.hash_table.section_registry().apply(section_event)?,
+                HashTableEvent::IndexEvent(index_event) => self.hash_table.index_registry().apply(index_event)?,
+                HashTableEvent::KeySketchEvent(key_sketch_event) => self.key_sketch.apply(key_sketch_event)?,
+            }
+        }
+        self.wal_replay_height = wal_reader.height().unwrap_or(WAL_START);
+        Ok(true)
+    }
+
+    fn write_sync_sequence(&mut self) -> io::Result<()> {
+        match &mut self.sync_file {
+            Some(sync_file) => self.sync_sequence.write(sync_file),
+            None => Ok(()),
+        }
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.read_only {
+            return Err(ManagedHashTableError::ReadOnly.into());
+        }
+        Ok(())
+    }
+
+    fn check_quotas(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        let quotas = self.config.quotas;
+        if quotas == StoreQuotas::default() {
+            return Ok(());
+        }
+        let check = |quota, limit: Option<u64>, requested| match limit {
+            Some(limit) if requested > limit => Err(ManagedHashTableError::QuotaExceeded { quota, limit, requested }),
+            _ => Ok(()),
+        };
+
+        let wal_bytes = self.wal.height()?;
+        check("max_wal_bytes", quotas.max_wal_bytes, wal_bytes)?;
+
+        // Sections are filled page by page, so the entry needs the pages between the ends of its old and new last page.
+        let page_size = self.config.page_size as u64;
+        let (entry_offset, entry_end) = self.hash_table.entry_range(key, value)?;
+        let new_pages = entry_end.div_ceil(page_size) - entry_offset.div_ceil(page_size);
+        let pages = self.hash_table.book().registry()?.page_count() as u64 + new_pages;
+        check("max_pages", quotas.max_pages, pages)?;
+        check("max_total_bytes", quotas.max_total_bytes, pages * page_size + wal_bytes)?;
         Ok(())
     }
 }
 
-impl HashTable for ManagedHashTable {
-    fn insert(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
-        self.hash_table.insert(key, value)
+impl<V: Vfs> ManagedHashTable<V> {
+    /// See [`BookHashTable::scan_with_recovery`].
+    pub fn scan_with_recovery<'a>(
+        &'a self,
+        filter: hash_table::HashTableScanFilter<'a>,
+        on_corruption: impl FnMut(CorruptRange) + 'a,
+    ) -> crate::Result<impl hash_table::HashTableScanner + 'a> {
+        Ok(self.hash_table.scan_with_recovery(filter, on_corruption)?)
+    }
+
+    /// See [`BookHashTable::scan_where`].
+    pub fn scan_where<'a>(
+        &'a self,
+        filter: hash_table::HashTableScanFilter<'a>,
+        value_prefix_size: usize,
+        predicate: impl FnMut(&EntryPreview) -> bool + 'a,
+    ) -> crate::Result<impl hash_table::ResumableScanner + 'a> {
+        Ok(self.hash_table.scan_where(filter, value_prefix_size, predicate)?)
+    }
+
+    /// See [`BookHashTable::scan_from`].
+    pub fn scan_from<'a>(
+        &'a self,
+        filter: hash_table::HashTableScanFilter<'a>,
+        cursor: hash_table::ScanCursor,
+    ) -> crate::Result<impl hash_table::ResumableScanner + 'a> {
+        Ok(self.hash_table.scan_from(filter, cursor)?)
+    }
+
+    /// See [`hash_table::dump::export`]. With `DuplicateKeys::LatestWins`, only the latest entry
+    /// of every key is exported.
+    pub fn export(&self, writer: &mut impl Write) -> crate::Result<u64> {
+        Ok(hash_table::dump::export(self, writer)?)
+    }
+
+    /// See [`hash_table::dump::import`]. The entries are only durable once synced, unless the
+    /// `SyncPolicy` syncs them.
+    pub fn import(&mut self, reader: &mut impl Read) -> crate::Result<u64> {
+        Ok(hash_table::dump::import(self, reader)?)
+    }
+
+    /// Writes the events of the write-ahead log synced since the last `full_sync` to `writer`,
+    /// one per line, and returns how many there were. Events recorded but not synced yet are not
+    /// included, as recovery would not replay them either.
+    pub fn dump_wal(&self, writer: &mut impl Write) -> crate::Result<u64> {
+        let wal_file = open_store_file(&self.vfs, &self.dir_path, "events.log", true)?;
+        let mut wal_reader = FileWALReader::<HashTableEvent, _>::new_read_only(wal_file, self.config.wal_recovery)?;
+        let mut count = 0;
+        while let Some(event) = wal_reader.read_next()? {
+            writeln!(writer, "{event:?}")?;
+            count += 1;
+        }
+        Ok(count)
     }
 
-    fn scan<'a>(&'a self, filter: hash_table::HashTableScanFilter<'a>) -> io::Result<impl hash_table::HashTableScanner + 'a> {
+    /// See [`BookHashTable::snapshot`].
+    pub fn snapshot(&self) -> crate::Result<hash_table::HashTableSnapshot> {
+        Ok(self.hash_table.snapshot()?)
+    }
+
+    /// See [`BookHashTable::scan_snapshot`].
+    pub fn scan_snapshot<'a>(
+        &'a self,
+        snapshot: &'a hash_table::HashTableSnapshot,
+        filter: hash_table::HashTableScanFilter<'a>,
+    ) -> crate::Result<impl hash_table::ResumableScanner + 'a> {
+        Ok(self.hash_table.scan_snapshot(snapshot, filter)?)
+    }
+}
+
+impl<V: Vfs> ManagedHashTable<V> {
+    /// Starts staging inserts that become visible and durable together, see `Transaction`.
+    /// Syncs inserts made before, so a failed commit can return to this state.
+    pub fn begin_transaction(&mut self) -> crate::Result<Transaction<'_, V>> {
+        self.check_writable()?;
+        if self.wal.height()? != self.wal_replay_height {
+            self.sync()?;
+        }
+        Ok(Transaction::new(self))
+    }
+
+    /// Inserts without calling the hooks, returning the sequence number stored with the entry.
+    fn insert_inner(&mut self, key: &[u8], value: &[u8]) -> io::Result<Option<u64>> {
+        self.check_writable()?;
+        self.check_quotas(key, value)?;
+        let sequence = match self.config.entry_metadata {
+            EntryMetadataFormat::None => None,
+            _ => Some(self.hash_table.next_sequence()),
+        };
+        self.hash_table.insert(key, value)?;
+        self.key_sketch.insert(key)?;
+        Ok(sequence)
+    }
+
+    /// Regenerates the index chunks and bloom filters from the entries in the sections, e.g. after
+    /// `indexes.reg` or `filters.reg` was damaged. Entries are not rewritten.
+    pub fn rebuild_indexes(&mut self) -> crate::Result<()> {
+        self.check_writable()?;
+        self.full_sync()?;
+        let index_registry = self.hash_table.build_index_registry()?;
+        // While `filters.reg` is empty, loading derives the section filters from the chunks.
+        write_file_atomically(&self.vfs, &self.dir_path, "filters.reg", &[])?;
+        write_file_atomically(&self.vfs, &self.dir_path, "indexes.reg", &encode_index_entries(&index_registry, self.config.bloom.words())?)?;
+        self.discard_unsynced()?;
+        self.full_sync()
+    }
+
+    /// Returns the registries to the state committed by the last sync. Entries written to pages
+    /// since then are beyond the restored section ends, and are overwritten by later inserts.
+    fn discard_unsynced(&mut self) -> io::Result<()> {
+        let LoadedRegistries { page_registry, section_registry, index_registry, key_sketch, wal_reader } =
+            load_registries(&self.vfs, &self.dir_path, &self.config, false)?;
+        self.wal = FileWAL::load(wal_reader.into_file())?;
+        self.wal_replay_height = self.wal.height()?;
+        *self.hash_table.book().registry()? = page_registry.with_wal(ConvertWAL::new(self.wal.clone()));
+        *self.hash_table.section_registry() = section_registry.with_wal(ConvertWAL::new(self.wal.clone()));
+        *self.hash_table.index_registry() = index_registry.with_wal(ConvertWAL::new(self.wal.clone()));
+        self.key_sketch = key_sketch.with_wal(ConvertWAL::new(self.wal.clone()));
+        self.hash_table.recover_sequence()
+    }
+}
+
+impl<V: Vfs> ManagedHashTable<V> {
+    /// The sequence number of the latest entry of `key`, `None` if there is none. Requires
+    /// `entry_metadata` to store sequence numbers.
+    pub fn version(&self, key: &[u8]) -> crate::Result<Option<u64>> {
+        if self.config.entry_metadata == EntryMetadataFormat::None {
+            return Err(ManagedHashTableError::InvalidOption {
+                option: "entry_metadata",
+                reason: "versions are the sequence numbers stored with the entries",
+            }.into());
+        }
+        let mut scanner = self.hash_table.scan(hash_table::HashTableScanFilter::Key(key))?;
+        let mut version = None;
+        while let Some(mut entry) = scanner.next()? {
+            version = entry.metadata()?.sequence;
+        }
+        Ok(version)
+    }
+
+    /// The latest value of `key`, to be modified or inserted, see `Entry`.
+    pub fn entry(&mut self, key: &[u8]) -> crate::Result<Entry<'_, V>> {
+        Ok(Entry::new(self, key)?)
+    }
+
+    /// Inserts only if the latest entry of `key` is still at `expected`, as returned by `version`,
+    /// and fails with `ManagedHashTableError::VersionConflict` otherwise. Returns the new version.
+    pub fn insert_if_version(&mut self, key: &[u8], value: &[u8], expected: Option<u64>) -> crate::Result<u64> {
+        let actual = self.version(key)?;
+        if actual != expected {
+            return Err(ManagedHashTableError::VersionConflict { expected, actual }.into());
+        }
+        let sequence = self.insert_inner(key, value)?;
+        self.hooks.insert(&InsertEvent { key, value, sequence });
+        self.apply_sync_policy()?;
+        Ok(sequence.expect("Sequence numbers are stored"))
+    }
+}
+
+impl<V: Vfs> HashTable for ManagedHashTable<V> {
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        let sequence = self.insert_inner(key, value)?;
+        self.hooks.insert(&InsertEvent { key, value, sequence });
+        Ok(self.apply_sync_policy()?)
+    }
+
+    fn scan<'a>(&'a self, filter: hash_table::HashTableScanFilter<'a>) -> crate::Result<impl hash_table::HashTableScanner + 'a> {
         self.hash_table.scan(filter)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use crate::hash_table::{HashTableEntry, HashTableError, HashTableScanFilter, HashTableScanner, ResumableScanner, ScanCursor};
+
+    fn collect_values(hash_table: &ManagedHashTable, key: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(key))?;
+        let mut values = Vec::new();
+        while let Some(mut entry) = scanner.next()? {
+            values.push(entry.read_value_to_vec()?);
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn test_insert_and_reopen() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            hash_table.insert(b"foo", b"bar")?;
+            hash_table.insert(b"test-key", b"test-value")?;
+            hash_table.insert(b"foo", b"baz")?;
+            hash_table.sync()?;
+        }
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        assert_eq!(collect_values(&hash_table, b"foo")?, vec![b"bar".to_vec(), b"baz".to_vec()]);
+        assert_eq!(collect_values(&hash_table, b"test-key")?, vec![b"test-value".to_vec()]);
+        assert!(collect_values(&hash_table, b"missing")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_from_resumes_after_reopen() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut keys = Vec::new();
+        let cursor = {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            for i in 0..10 {
+                hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+            }
+            hash_table.sync()?;
+
+            let mut scanner = hash_table.scan_from(HashTableScanFilter::All, ScanCursor::default())?;
+            for _ in 0..4 {
+                let mut entry = scanner.next()?.expect("entry must exist");
+                keys.push(String::from_utf8(entry.read_key_to_vec()?).unwrap());
+            }
+            serde_json::to_string(&scanner.cursor()).map_err(io::Error::other)?
+        };
+
+        let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        // Only returned if it lands in a section the first scan had not reached yet.
+        hash_table.insert(b"key-late", b"value")?;
+        let cursor: ScanCursor = serde_json::from_str(&cursor).map_err(io::Error::other)?;
+        let mut scanner = hash_table.scan_from(HashTableScanFilter::All, cursor)?;
+        while let Some(mut entry) = scanner.next()? {
+            keys.push(String::from_utf8(entry.read_key_to_vec()?).unwrap());
+        }
+        assert_eq!(scanner.cursor().section_index, test_config().section_count);
+        drop(scanner);
+
+        keys.retain(|key| key != "key-late");
+        keys.sort();
+        let expected = (0..10).map(|i| format!("key-{i}")).collect::<BTreeSet<_>>();
+        assert_eq!(keys, expected.into_iter().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_metadata() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            entry_metadata: EntryMetadataFormat::SequenceAndTimestamp,
+            ..test_config()
+        };
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), config.clone())?;
+            hash_table.insert(b"foo", b"bar")?;
+            hash_table.insert(b"test-key", b"test-value")?;
+            hash_table.sync()?;
+        }
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        hash_table.insert(b"foo", b"baz")?;
+
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(b"foo"))?;
+        let mut metadata = Vec::new();
+        while let Some(mut entry) = scanner.next()? {
+            metadata.push(entry.metadata()?);
+            assert_eq!(entry.read_key_to_vec()?, b"foo");
+        }
+        assert_eq!(metadata.iter().map(|metadata| metadata.sequence).collect::<Vec<_>>(), [Some(0), Some(2)]);
+        assert!(metadata.iter().all(|metadata| metadata.timestamp_micros.is_some_and(|timestamp| timestamp > 0)));
+        drop(scanner);
+
+        let err = ManagedHashTable::open(dir.path(), test_config()).err().unwrap();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::ConfigMismatch { field: "entry_metadata", .. }),
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_double_write() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let journal_path = dir.path().join("pages.dwb");
+        let mut hash_table = ManagedHashTable::builder(dir.path()).section_count(1).double_write(true).open()?;
+        hash_table.insert(b"foo", b"bar")?;
+        hash_table.sync()?;
+        hash_table.insert(b"foo", b"baz")?;
+        // The page holding the first entry was journaled before the second was written into it.
+        assert!(std::fs::metadata(&journal_path)?.len() > 0);
+        hash_table.sync()?;
+        assert_eq!(std::fs::metadata(&journal_path)?.len(), 0);
+        hash_table.insert(b"foo", b"qux")?;
+        drop(hash_table);
+
+        // The journal is restored and emptied, leaving what was synced.
+        let hash_table = ManagedHashTable::builder(dir.path()).open()?;
+        assert_eq!(std::fs::metadata(&journal_path)?.len(), 0);
+        assert_eq!(hash_table.get(b"foo")?, Some(b"baz".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_policy() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            sync_policy: SyncPolicy::SyncEveryNRecords(3),
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        let syncs = hash_table.sync_sequence().sync;
+        hash_table.insert(b"foo", b"bar")?;
+        hash_table.insert(b"foo", b"baz")?;
+        assert!(hash_table.has_unsynced_inserts()?);
+        hash_table.insert(b"foo", b"qux")?;
+        assert!(!hash_table.has_unsynced_inserts()?);
+        assert_eq!(hash_table.sync_sequence().sync, syncs + 1);
+        drop(hash_table);
+
+        let mut hash_table = ManagedHashTable::builder(dir.path()).sync_policy(SyncPolicy::SyncEveryWrite).open()?;
+        hash_table.insert(b"foo", b"quux")?;
+        assert!(!hash_table.has_unsynced_inserts()?);
+        drop(hash_table);
+
+        let err = ManagedHashTable::builder(dir.path()).sync_policy(SyncPolicy::SyncEveryNRecords(0)).open().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let shared = ManagedHashTable::open(dir.path(), test_config())?.into_shared();
+        shared.insert(b"foo", b"corge")?;
+        shared.insert(b"foo", b"grault")?;
+        let syncs = shared.read()?.sync_sequence().sync;
+        shared.sync()?;
+        shared.sync()?;
+        assert_eq!(shared.read()?.sync_sequence().sync, syncs + 1);
+        assert_eq!(collect_values(&*shared.read()?, b"foo")?.len(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_torn_wal_tail() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            hash_table.insert(b"foo", b"bar")?;
+            hash_table.sync()?;
+            hash_table.insert(b"foo", b"baz")?;
+            hash_table.sync()?;
+        }
+        // As if the crash hit the last sync after its height was written but not all its records.
+        let wal_file = std::fs::OpenOptions::new().write(true).open(dir.path().join("events.log"))?;
+        wal_file.set_len(wal_file.metadata()?.len() - 3)?;
+
+        let err = ManagedHashTable::builder(dir.path()).wal_recovery(WALRecovery::Strict).open().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        assert!(hash_table.discarded_wal_bytes() > 3);
+        assert_eq!(collect_values(&hash_table, b"foo")?.first(), Some(&b"bar".to_vec()));
+        drop(hash_table);
+
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        assert_eq!(hash_table.discarded_wal_bytes(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_mismatch() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            hash_table.insert(b"foo", b"bar")?;
+            hash_table.sync()?;
+        }
+
+        let err = ManagedHashTable::open(dir.path(), HashTableConfig { section_count: 8, ..test_config() }).err().unwrap();
+        assert!(matches!(err, crate::Error::ConfigMismatch(_)));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::ConfigMismatch { field: "section_count", on_disk, requested })
+                if on_disk == "4" && requested == "8",
+        ));
+
+        let hash_table = ManagedHashTable::open_with_existing_config(dir.path())?;
+        assert_eq!(collect_values(&hash_table, b"foo")?, vec![b"bar".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store_path = dir.path().join("store");
+        let err = ManagedHashTable::open(&store_path, HashTableConfig { index_chunk_size: 0, ..test_config() }).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::InvalidOption { option: "index_chunk_size", .. }),
+        ));
+        assert!(!store_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_with_existing_config_requires_store() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store_path = dir.path().join("missing");
+        let err = ManagedHashTable::open_with_existing_config(&store_path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(!store_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_size_limits() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            entry_size_limits: EntrySizeLimits {
+                max_key_size: 4,
+                max_value_size: 8,
+            },
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+
+        let err = hash_table.insert(b"too-long", b"value").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<HashTableError>()),
+            Some(HashTableError::KeyTooLarge { size: 8, limit: 4 }),
+        ));
+
+        let err = hash_table.insert(b"key", b"much-too-long").unwrap_err();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<HashTableError>()),
+            Some(HashTableError::ValueTooLarge { size: 13, limit: 8 }),
+        ));
+
+        hash_table.insert(b"key", b"value")?;
+        assert_eq!(collect_values(&hash_table, b"key")?, vec![b"value".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quotas() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            quotas: StoreQuotas {
+                max_pages: Some(2),
+                ..Default::default()
+            },
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config.clone())?;
+        // Each entry takes 64 bytes, a full page.
+        hash_table.insert(b"key", &[1; 53])?;
+        hash_table.insert(b"key", &[2; 53])?;
+        let err = hash_table.insert(b"key", &[3; 53]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::QuotaExceeded { quota: "max_pages", limit: 2, requested: 3 }),
+        ));
+        assert_eq!(collect_values(&hash_table, b"key")?.len(), 2);
+        hash_table.sync()?;
+        drop(hash_table);
+
+        let quotas = StoreQuotas {
+            max_wal_bytes: Some(100),
+            ..Default::default()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), HashTableConfig { quotas, ..config })?;
+        hash_table.insert(b"key", &[3; 53])?;
+        hash_table.insert(b"key", &[4; 53])?;
+        let err = hash_table.insert(b"key", &[5; 53]).unwrap_err();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::QuotaExceeded { quota: "max_wal_bytes", limit: 100, .. }),
+        ));
+        hash_table.full_sync()?;
+        hash_table.insert(b"key", &[5; 53])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_epoch() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let epoch = {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            let epoch = hash_table.epoch();
+            hash_table.insert(b"foo", b"bar")?;
+            hash_table.sync()?;
+            assert_eq!(hash_table.epoch(), epoch);
+            hash_table.full_sync()?;
+            assert_eq!(hash_table.epoch(), epoch + 1);
+            epoch + 1
+        };
+
+        let reader = ManagedHashTable::builder(dir.path()).read_only(true).open()?;
+        assert_eq!(reader.epoch(), epoch);
+        drop(reader);
+        assert!(ManagedHashTable::open(dir.path(), test_config())?.epoch() > epoch);
+        Ok(())
+    }
+
+    #[test]
+    fn test_latest_wins() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            duplicate_keys: DuplicateKeys::LatestWins,
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        for i in 0..30 {
+            hash_table.insert(format!("key-{}", i % 10).as_bytes(), format!("value-{i}").as_bytes())?;
+        }
+        assert_eq!(collect_values(&hash_table, b"key-3")?, [b"value-23".to_vec()]);
+        assert_eq!(hash_table.get(b"key-3")?, Some(b"value-23".to_vec()));
+
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        let mut entries = BTreeSet::new();
+        while let Some(mut entry) = scanner.next()? {
+            entries.insert((entry.read_key_to_vec()?, entry.read_value_to_vec()?));
+        }
+        drop(scanner);
+        assert_eq!(entries, (20..30).map(|i| (format!("key-{}", i % 10).into_bytes(), format!("value-{i}").into_bytes())).collect());
+        hash_table.sync()?;
+        drop(hash_table);
+
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        assert_eq!(collect_values(&hash_table, b"key-3")?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        let stats = hash_table.stats()?;
+        assert_eq!((stats.entry_count, stats.index_chunk_count, stats.bloom_saturation), (0, 0, 0.0));
+        assert_eq!(stats.section_sizes, [0; 4]);
+        assert_eq!(stats.wal_bytes, 0);
+
+        for i in 0..10 {
+            hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+        }
+        let stats = hash_table.stats()?;
+        assert_eq!(stats.entry_count, 10);
+        assert_eq!(stats.key_bytes, 10 * 5);
+        assert_eq!(stats.value_bytes, 10 * 5);
+        assert_eq!(stats.section_sizes.iter().sum::<u64>(), 10 * (8 + 5 + 5));
+        assert!(stats.index_chunk_count > 0 && stats.bloom_saturation > 0.0);
+        assert!(stats.page_count > 0 && stats.wal_bytes > 0);
+        assert_eq!(stats.epoch, hash_table.epoch());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hasher_is_part_of_format() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            hasher: HasherKind::Fnv,
+            ..test_config()
+        };
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), config.clone())?;
+            for i in 0..40 {
+                hash_table.insert(format!("shared-prefix-{i}").as_bytes(), b"value")?;
+            }
+            hash_table.sync()?;
+            // The prefix hasher would put all of these keys into one section.
+            assert!(hash_table.stats()?.section_sizes.iter().all(|&size| size > 0));
+        }
+
+        let err = ManagedHashTable::open(dir.path(), test_config()).err().unwrap();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::ConfigMismatch { field: "hasher", .. }),
+        ));
+
+        let hash_table = ManagedHashTable::open_with_existing_config(dir.path())?;
+        assert_eq!(hash_table.config().hasher, HasherKind::Fnv);
+        assert_eq!(collect_values(&hash_table, b"shared-prefix-7")?, [b"value".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resize_sections() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            entry_metadata: EntryMetadataFormat::Sequence,
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        for i in 0..60 {
+            hash_table.insert(format!("key-{}", i % 20).as_bytes(), format!("value-{i}").as_bytes())?;
+        }
+        let versions = (0..20)
+            .map(|i| hash_table.version(format!("key-{i}").as_bytes()))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let epoch = hash_table.epoch();
+
+        let mut hash_table = hash_table.resize_sections(16)?;
+        assert_eq!(hash_table.config().section_count, 16);
+        assert!(hash_table.epoch() > epoch);
+        assert_eq!(hash_table.stats()?.entry_count, 60);
+        for (i, version) in versions.iter().enumerate() {
+            let key = format!("key-{i}");
+            let expected = [i, i + 20, i + 40].map(|n| format!("value-{n}").into_bytes());
+            assert_eq!(collect_values(&hash_table, key.as_bytes())?, expected);
+            assert_eq!(hash_table.version(key.as_bytes())?, *version);
+        }
+        assert_eq!(hash_table.insert_if_version(b"key-0", b"value-60", versions[0])?, 60);
+        hash_table.sync()?;
+        drop(hash_table);
+
+        let hash_table = ManagedHashTable::open_with_existing_config(dir.path())?;
+        assert_eq!(hash_table.config().section_count, 16);
+        assert_eq!(collect_values(&hash_table, b"key-0")?.last(), Some(&b"value-60".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            entry_metadata: EntryMetadataFormat::Sequence,
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        for i in 0..60 {
+            hash_table.insert(format!("key-{}", i % 20).as_bytes(), format!("value-{i}").as_bytes())?;
+        }
+        let page_count = hash_table.stats()?.page_count;
+
+        let mut hash_table = hash_table.compact()?;
+        let stats = hash_table.stats()?;
+        assert_eq!(stats.entry_count, 20);
+        assert!(stats.page_count < page_count);
+        for i in 0..20 {
+            let key = format!("key-{i}");
+            assert_eq!(collect_values(&hash_table, key.as_bytes())?, [format!("value-{}", i + 40).into_bytes()]);
+            assert_eq!(hash_table.version(key.as_bytes())?, Some(i + 40));
+        }
+        assert!(collect_values(&hash_table, b"key-20")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_compaction() -> io::Result<()> {
+        use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
+
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            auto_compaction: Some(AutoCompaction {
+                dead_bytes_ratio: Some(0.5),
+                wal_bytes: Some(1),
+                ..Default::default()
+            }),
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        let compactions = Arc::new(AtomicU64::new(0));
+        let finished = compactions.clone();
+        hash_table.on_compaction_finish(move |_| {
+            finished.fetch_add(1, Ordering::Relaxed);
+        });
+
+        // The log limit is reached, but there is nothing to drop.
+        for i in 0..20 {
+            hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+        }
+        hash_table.full_sync()?;
+        assert_eq!(compactions.load(Ordering::Relaxed), 0);
+
+        for i in 0..40 {
+            hash_table.insert(format!("key-{}", i % 20).as_bytes(), format!("value-{i}").as_bytes())?;
+        }
+        hash_table.full_sync()?;
+        assert_eq!(compactions.load(Ordering::Relaxed), 1);
+        assert_eq!(hash_table.stats()?.entry_count, 20);
+        assert_eq!(collect_values(&hash_table, b"key-0")?, [b"value-20".to_vec()]);
+
+        hash_table.insert(b"key-0", b"value-40")?;
+        hash_table.sync()?;
+        drop(hash_table);
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        assert_eq!(collect_values(&hash_table, b"key-0")?, [b"value-20".to_vec(), b"value-40".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics() -> io::Result<()> {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        #[derive(Default)]
+        struct Counters {
+            inserts: AtomicU64,
+            key_scans: AtomicU64,
+            entries_scanned: AtomicU64,
+            bloom_checks: AtomicU64,
+            bloom_misses: AtomicU64,
+            full_syncs: AtomicU64,
+        }
+
+        impl HashTableMetrics for Counters {
+            fn insert(&self, _key_size: u32, _value_size: u32) {
+                self.inserts.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn scan(&self, by_key: bool) {
+                if by_key {
+                    self.key_scans.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            fn entry_scanned(&self) {
+                self.entries_scanned.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn section_bloom(&self, may_contain: bool) {
+                self.chunk_bloom(may_contain);
+            }
+
+            fn chunk_bloom(&self, may_contain: bool) {
+                self.bloom_checks.fetch_add(1, Ordering::Relaxed);
+                if !may_contain {
+                    self.bloom_misses.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            fn sync(&self, full: bool, _duration: Duration) {
+                if full {
+                    self.full_syncs.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let dir = tempfile::tempdir()?;
+        let counters = Arc::new(Counters::default());
+        let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        hash_table.set_metrics(counters.clone());
+        for i in 0..20 {
+            hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+        }
+        hash_table.full_sync()?;
+        assert_eq!(collect_values(&hash_table, b"key-3")?.len(), 1);
+        for i in 0..20 {
+            assert!(collect_values(&hash_table, format!("missing-{i}").as_bytes())?.is_empty());
+        }
+
+        assert_eq!(counters.inserts.load(Ordering::Relaxed), 20);
+        assert_eq!(counters.key_scans.load(Ordering::Relaxed), 21);
+        assert!(counters.entries_scanned.load(Ordering::Relaxed) >= 1);
+        assert!(counters.bloom_checks.load(Ordering::Relaxed) >= 21);
+        assert!(counters.bloom_misses.load(Ordering::Relaxed) > 0);
+        assert_eq!(counters.full_syncs.load(Ordering::Relaxed), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_import() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut source = ManagedHashTable::open(dir.path().join("source"), test_config())?;
+        for i in 0..30 {
+            source.insert(format!("key-{}", i % 10).as_bytes(), format!("value-{i}").as_bytes())?;
+        }
+        let mut dump = Vec::new();
+        assert_eq!(source.export(&mut dump)?, 30);
+
+        let config = HashTableConfig {
+            page_size: 128,
+            section_count: 7,
+            hasher: HasherKind::Xxhash32,
+            ..test_config()
+        };
+        let mut target = ManagedHashTable::open(dir.path().join("target"), config)?;
+        assert_eq!(target.import(&mut dump.as_slice())?, 30);
+        target.sync()?;
+        assert_eq!(collect_values(&target, b"key-3")?, [b"value-3".to_vec(), b"value-13".to_vec(), b"value-23".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?.into_shared();
+        for i in 0..20 {
+            hash_table.insert(format!("key-{i}").as_bytes(), b"before")?;
+        }
+        let snapshot = hash_table.read()?.snapshot()?;
+        for i in 0..40 {
+            hash_table.insert(format!("key-{i}").as_bytes(), b"after")?;
+        }
+
+        {
+            let reader = hash_table.read()?;
+            let mut scanner = reader.scan_snapshot(&snapshot, HashTableScanFilter::All)?;
+            let mut count = 0;
+            while let Some(mut entry) = scanner.next()? {
+                assert_eq!(entry.read_value_to_vec()?, b"before");
+                count += 1;
+            }
+            assert_eq!(count, 20);
+
+            let mut scanner = reader.scan_snapshot(&snapshot, HashTableScanFilter::Key(b"key-30"))?;
+            assert!(scanner.next()?.is_none());
+        }
+
+        let resized = hash_table.try_unwrap().map_err(|_| io::Error::other("Handle still shared"))?.resize_sections(8)?;
+        assert_eq!(resized.scan_snapshot(&snapshot, HashTableScanFilter::All).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidInput));
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_checksum() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            entry_metadata: EntryMetadataFormat::SequenceAndTimestamp,
+            entry_checksum: EntryChecksum::Crc32,
+            ..test_config()
+        };
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), config.clone())?;
+            for i in 0..10 {
+                hash_table.insert(format!("key-{i}").as_bytes(), format!("value-{i}").as_bytes())?;
+            }
+            hash_table.sync()?;
+        }
+
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        assert_eq!(collect_values(&hash_table, b"key-3")?, [b"value-3".to_vec()]);
+        assert_eq!(hash_table.version(b"key-9")?, Some(9));
+        assert_eq!(hash_table.insert_if_version(b"key-9", b"value-10", Some(9))?, 10);
+        assert_eq!(hash_table.stats()?.entry_count, 11);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wide_bloom_filters() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            bloom: BloomConfig { bits: 256, probes: 3 },
+            ..test_config()
+        };
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), config.clone())?;
+            for i in 0..100 {
+                hash_table.insert(format!("key-{i}").as_bytes(), format!("value-{i}").as_bytes())?;
+            }
+            // Left to the write-ahead log, so that reopening replays the wide filters from it.
+            hash_table.sync()?;
+            let stats = hash_table.stats()?;
+            assert!(stats.bloom_saturation > 0.0 && stats.bloom_saturation < 1.0);
+        }
+
+        let hash_table = ManagedHashTable::open(dir.path(), config.clone())?;
+        for i in 0..100 {
+            assert_eq!(collect_values(&hash_table, format!("key-{i}").as_bytes())?, [format!("value-{i}").into_bytes()]);
+        }
+        assert!(collect_values(&hash_table, b"key-100")?.is_empty());
+        drop(hash_table);
+
+        let err = ManagedHashTable::open(dir.path(), test_config()).err().unwrap();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::ConfigMismatch { field: "bloom", .. }),
+        ));
+
+        let invalid = HashTableConfig {
+            bloom: BloomConfig { bits: 100, probes: 3 },
+            ..test_config()
+        };
+        let err = ManagedHashTable::open(tempfile::tempdir()?.path(), invalid).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_refresh_follows_writer() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut writer = ManagedHashTable::open(dir.path(), test_config())?;
+        writer.insert(b"foo", b"bar")?;
+        writer.sync()?;
+
+        let mut reader = ManagedHashTable::builder(dir.path()).read_only(true).open()?;
+        assert_eq!(collect_values(&reader, b"foo")?, vec![b"bar".to_vec()]);
+        assert!(!reader.refresh()?);
+
+        // Unsynced inserts stay invisible, synced ones are picked up from the log.
+        writer.insert(b"foo", b"baz")?;
+        assert!(!reader.refresh()?);
+        writer.insert(b"test-key", &[7; 100])?;
+        writer.sync()?;
+        assert!(reader.refresh()?);
+        assert_eq!(reader.sync_sequence(), writer.sync_sequence());
+        assert_eq!(collect_values(&reader, b"foo")?, vec![b"bar".to_vec(), b"baz".to_vec()]);
+        assert_eq!(collect_values(&reader, b"test-key")?, vec![vec![7; 100]]);
+
+        // A checkpoint clears the log, after which the registries are reloaded.
+        writer.full_sync()?;
+        writer.insert(b"foo", b"qux")?;
+        writer.insert(b"sample-key", b"value")?;
+        writer.sync()?;
+        assert!(reader.refresh()?);
+        assert_eq!(collect_values(&reader, b"foo")?, vec![b"bar".to_vec(), b"baz".to_vec(), b"qux".to_vec()]);
+        assert_eq!(collect_values(&reader, b"sample-key")?, vec![b"value".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hooks() -> io::Result<()> {
+        use std::sync::{Arc, Mutex};
+
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            entry_metadata: EntryMetadataFormat::Sequence,
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let insert_log = log.clone();
+        hash_table.on_insert(move |event| {
+            insert_log.lock().unwrap().push(format!("insert {} {:?}", String::from_utf8_lossy(event.key), event.sequence));
+        });
+        let sync_log = log.clone();
+        hash_table.on_sync(move |event| sync_log.lock().unwrap().push(format!("sync {}", event.full)));
+        let start_log = log.clone();
+        hash_table.on_compaction_start(move |event| start_log.lock().unwrap().push(format!("compaction start {:?}", event.entry_count)));
+        let finish_log = log.clone();
+        hash_table.on_compaction_finish(move |event| finish_log.lock().unwrap().push(format!("compaction finish {:?}", event.entry_count)));
+
+        hash_table.insert(b"foo", b"bar")?;
+        hash_table.insert(b"foo", b"baz")?;
+        hash_table.sync()?;
+        hash_table.full_sync()?;
+        assert_eq!(*log.lock().unwrap(), ["insert foo Some(0)", "insert foo Some(1)", "sync false", "sync true"]);
+
+        log.lock().unwrap().clear();
+        let mut hash_table = hash_table.compact()?;
+        hash_table.insert(b"bar", b"qux")?;
+        assert_eq!(*log.lock().unwrap(), ["compaction start None", "sync true", "compaction finish Some(1)", "insert bar Some(2)"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_approx_key_count() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            for i in 0..1000 {
+                hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+            }
+            hash_table.full_sync()?;
+            // Duplicates are not counted again; these are only in the write-ahead log when reopening.
+            for i in 0..2000 {
+                hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+            }
+            hash_table.sync()?;
+        }
+
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        let count = hash_table.approx_key_count();
+        assert!((1900..=2100).contains(&count), "{count}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_indexes() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            for i in 0..50 {
+                hash_table.insert(format!("key-{i}").as_bytes(), format!("value-{i}").as_bytes())?;
+            }
+            hash_table.full_sync()?;
+        }
+        std::fs::write(dir.path().join("indexes.reg"), [])?;
+        std::fs::write(dir.path().join("filters.reg"), [])?;
+
+        let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        assert!(collect_values(&hash_table, b"key-7")?.is_empty());
+        hash_table.rebuild_indexes()?;
+        assert_eq!(collect_values(&hash_table, b"key-7")?, [b"value-7".to_vec()]);
+        drop(hash_table);
+
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        for i in 0..50 {
+            assert_eq!(collect_values(&hash_table, format!("key-{i}").as_bytes())?, [format!("value-{i}").into_bytes()]);
+        }
+        assert!(collect_values(&hash_table, b"missing")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_writer_with_waiting_reader() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut writer = ManagedHashTable::open(dir.path(), test_config())?;
+        let err = ManagedHashTable::open(dir.path(), test_config()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::Locked { .. }),
+        ));
+
+        let mut reader = ManagedHashTable::builder(dir.path()).read_only(true).open()?;
+        assert!(!reader.wait_for_sync(Duration::from_millis(1), Duration::from_millis(5))?);
+        let syncing_writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            writer.insert(b"foo", b"bar")?;
+            writer.sync()?;
+            Ok::<_, io::Error>(writer)
+        });
+        assert!(reader.wait_for_sync(Duration::from_millis(1), Duration::from_secs(10))?);
+        assert_eq!(collect_values(&reader, b"foo")?, vec![b"bar".to_vec()]);
+
+        drop(syncing_writer.join().unwrap()?);
+        ManagedHashTable::open(dir.path(), test_config())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_wal() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        let mut dump = Vec::new();
+        assert_eq!(hash_table.dump_wal(&mut dump)?, 0);
+        hash_table.insert(b"foo", b"bar")?;
+        hash_table.sync()?;
+        hash_table.insert(b"unsynced", b"value")?;
+
+        let count = hash_table.dump_wal(&mut dump)?;
+        let dump = String::from_utf8(dump).unwrap();
+        assert_eq!(dump.lines().count() as u64, count);
+        assert!(dump.lines().any(|line| line.starts_with("PageEvent(Assigned(")));
+        assert!(dump.lines().any(|line| line.starts_with("SectionEvent(Updated(")));
+
+        hash_table.full_sync()?;
+        assert_eq!(hash_table.dump_wal(&mut Vec::new())?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        let count = |value: &[u8]| u64::from_le_bytes(value.try_into().unwrap());
+        for _ in 0..3 {
+            hash_table.entry(b"counter")?
+                .and_modify(|value| *value = (count(value) + 1).to_le_bytes().to_vec())?
+                .or_insert(&1u64.to_le_bytes())?;
+        }
+        assert_eq!(hash_table.get(b"counter")?.map(|value| count(&value)), Some(3));
+
+        let entry = hash_table.entry(b"lazy")?;
+        assert_eq!((entry.key(), entry.value()), (&b"lazy"[..], None));
+        assert_eq!(entry.or_insert_with(|| b"made".to_vec())?, b"made");
+        assert_eq!(hash_table.entry(b"lazy")?.or_insert_with(|| unreachable!())?, b"made");
+        hash_table.entry(b"lazy")?.insert(b"replaced")?;
+        hash_table.sync()?;
+        drop(hash_table);
+
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        assert_eq!(hash_table.get(b"lazy")?, Some(b"replaced".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            quotas: StoreQuotas {
+                max_pages: Some(4),
+                ..Default::default()
+            },
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config.clone())?;
+        hash_table.insert(b"foo", b"bar")?;
+
+        let mut transaction = hash_table.begin_transaction()?;
+        transaction.insert(b"foo", b"baz")?;
+        let savepoint = transaction.savepoint();
+        transaction.insert(b"foo", b"undone")?;
+        let inner_savepoint = transaction.savepoint();
+        transaction.insert(b"foo", b"undone-too")?;
+        transaction.rollback_to(savepoint)?;
+        transaction.insert(b"foo", b"undone-again")?;
+        transaction.insert(b"foo", b"undone-again")?;
+        assert_eq!(transaction.rollback_to(inner_savepoint).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        transaction.rollback_to(savepoint)?;
+        transaction.insert(b"test-key", b"test-value")?;
+        assert_eq!(transaction.len(), 2);
+        transaction.commit()?;
+
+        let mut transaction = hash_table.begin_transaction()?;
+        transaction.insert(b"foo", b"rolled-back")?;
+        transaction.rollback();
+
+        // The second insert exceeds the page quota, so the first one is undone as well.
+        let mut transaction = hash_table.begin_transaction()?;
+        transaction.insert(b"foo", b"qux")?;
+        transaction.insert(b"sample-key", &[1; 200])?;
+        let err = transaction.commit().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+        assert_eq!(collect_values(&hash_table, b"foo")?, vec![b"bar".to_vec(), b"baz".to_vec()]);
+        assert!(collect_values(&hash_table, b"sample-key")?.is_empty());
+
+        hash_table.insert(b"foo", b"quux")?;
+        hash_table.sync()?;
+        drop(hash_table);
+
+        let hash_table = ManagedHashTable::open(dir.path(), config)?;
+        assert_eq!(collect_values(&hash_table, b"foo")?, vec![b"bar".to_vec(), b"baz".to_vec(), b"quux".to_vec()]);
+        assert_eq!(collect_values(&hash_table, b"test-key")?, vec![b"test-value".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_if_version() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = HashTableConfig {
+            entry_metadata: EntryMetadataFormat::Sequence,
+            ..test_config()
+        };
+        let mut hash_table = ManagedHashTable::open(dir.path(), config)?;
+        hash_table.insert(b"other", b"value")?;
+        assert_eq!(hash_table.version(b"foo")?, None);
+
+        let version = hash_table.insert_if_version(b"foo", b"bar", None)?;
+        assert_eq!(hash_table.version(b"foo")?, Some(version));
+        hash_table.insert(b"foo", b"concurrent")?;
+
+        let err = hash_table.insert_if_version(b"foo", b"baz", Some(version)).unwrap_err();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::VersionConflict { expected: Some(1), actual: Some(2) }),
+        ));
+        hash_table.insert_if_version(b"foo", b"baz", Some(2))?;
+        assert_eq!(collect_values(&hash_table, b"foo")?, vec![b"bar".to_vec(), b"concurrent".to_vec(), b"baz".to_vec()]);
+
+        let mut hash_table = ManagedHashTable::open(tempfile::tempdir()?.path(), test_config())?;
+        assert_eq!(hash_table.insert_if_version(b"foo", b"bar", None).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_entry_size_is_rejected() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            hash_table.insert(b"key", b"value")?;
+            hash_table.full_sync()?;
+        }
+        {
+            use std::io::{Seek, SeekFrom, Write};
+            let mut pages_file = std::fs::OpenOptions::new().write(true).open(dir.path().join("pages.dat"))?;
+            pages_file.seek(SeekFrom::Start(4))?;
+            pages_file.write_all(&u32::MAX.to_le_bytes())?;
+        }
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        let err = scanner.next().err().expect("corrupt entry must be reported");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<HashTableError>()),
+            Some(HashTableError::EntryOutOfBounds { offset: 0, key_size: 3, value_size: u32::MAX, .. }),
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_with_recovery_skips_corrupt_chunk() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let value = [7u8; 20];
+        {
+            let mut hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+            for i in 0..6 {
+                hash_table.insert(format!("key-{i}").as_bytes(), &value)?;
+            }
+            hash_table.full_sync()?;
+        }
+        {
+            use std::io::{Seek, SeekFrom, Write};
+            let mut pages_file = std::fs::OpenOptions::new().write(true).open(dir.path().join("pages.dat"))?;
+            pages_file.seek(SeekFrom::Start(4))?;
+            pages_file.write_all(&u32::MAX.to_le_bytes())?;
+        }
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?;
+
+        let mut corrupt_ranges = Vec::new();
+        let mut keys = Vec::new();
+        {
+            let mut scanner = hash_table.scan_with_recovery(HashTableScanFilter::All, |range| corrupt_ranges.push(range))?;
+            while let Some(mut entry) = scanner.next()? {
+                keys.push(String::from_utf8(entry.read_key_to_vec()?).unwrap());
+            }
+        }
+
+        // Each entry takes 33 bytes, so the second index chunk starts with the third entry.
+        assert_eq!(keys, ["key-2", "key-3", "key-4", "key-5"]);
+        assert_eq!(corrupt_ranges.len(), 1);
+        assert_eq!((corrupt_ranges[0].start_offset, corrupt_ranges[0].end_offset), (0, 66));
+
+        let report = serde_json::to_value(corrupt_ranges[0]).map_err(io::Error::other)?;
+        assert_eq!(report["start_offset"], 0);
+        assert_eq!(report["end_offset"], 66);
+        Ok(())
+    }
+}
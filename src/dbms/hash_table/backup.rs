@@ -0,0 +1,108 @@
+use std::{io, path::Path};
+
+use crate::vfs::{Vfs, VfsFile};
+
+use super::{ManagedHashTable, SharedHashTable, rewrite::STORE_FILES};
+
+/// Copied last, as the pages only grow and the registries copied before never refer to the pages
+/// allocated since.
+const PAGES_FILE: &str = "pages.dat";
+
+/// Marks a complete backup, so it is copied after everything else.
+const HEADER_FILE: &str = "header.json";
+
+fn copy_file<V: Vfs>(vfs: &V, from: &Path, to: &Path) -> io::Result<()> {
+    let mut source = vfs.open_read_only(from)?;
+    let mut target = vfs.open(to)?;
+    target.set_len(0)?;
+    io::copy(&mut source, &mut target)?;
+    target.sync_all()
+}
+
+/// Checkpoints the table into its files and copies all of them but the pages and the header,
+/// returning the epoch of the backup.
+fn begin_backup<V: Vfs>(hash_table: &mut ManagedHashTable<V>, backup_dir: &Path) -> io::Result<u64> {
+    hash_table.check_writable()?;
+    if backup_dir == hash_table.dir_path {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Backup directory is the store itself"));
+    }
+    if hash_table.vfs.exists(&backup_dir.join(HEADER_FILE))? {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Backup directory already holds a store"));
+    }
+    hash_table.full_sync()?;
+    hash_table.vfs.create_dir_all(backup_dir)?;
+    for file_name in STORE_FILES.into_iter().filter(|&file_name| file_name != PAGES_FILE && file_name != HEADER_FILE) {
+        copy_file(&hash_table.vfs, &hash_table.dir_path.join(file_name), &backup_dir.join(file_name))?;
+    }
+    Ok(hash_table.epoch())
+}
+
+fn finish_backup<V: Vfs>(vfs: &V, dir_path: &Path, backup_dir: &Path) -> io::Result<()> {
+    copy_file(vfs, &dir_path.join(PAGES_FILE), &backup_dir.join(PAGES_FILE))?;
+    copy_file(vfs, &dir_path.join(HEADER_FILE), &backup_dir.join(HEADER_FILE))?;
+    vfs.sync_dir(backup_dir)
+}
+
+impl<V: Vfs> ManagedHashTable<V> {
+    /// Copies the store into `backup_dir`, which can be opened like any other store, as of a
+    /// `full_sync` made first. Returns the epoch the backup is at. Fails if `backup_dir` holds a
+    /// store already.
+    ///
+    /// Blocks inserts until the copy is done; see `SharedHashTable::backup_to` for one that lets
+    /// them continue while the pages are copied.
+    pub fn backup_to(&mut self, backup_dir: impl AsRef<Path>) -> crate::Result<u64> {
+        let epoch = begin_backup(self, backup_dir.as_ref())?;
+        finish_backup(&self.vfs, &self.dir_path, backup_dir.as_ref())?;
+        Ok(epoch)
+    }
+}
+
+impl<V: Vfs + Clone> SharedHashTable<V> {
+    /// Like `ManagedHashTable::backup_to`, but only holds the table exclusively for the
+    /// `full_sync` and while the registries and the write-ahead log are copied; inserts continue
+    /// while the pages are.
+    pub fn backup_to(&self, backup_dir: impl AsRef<Path>) -> crate::Result<u64> {
+        let (epoch, vfs, dir_path) = {
+            let mut hash_table = self.write()?;
+            let epoch = begin_backup(&mut hash_table, backup_dir.as_ref())?;
+            (epoch, hash_table.vfs.clone(), hash_table.dir_path.clone())
+        };
+        finish_backup(&vfs, &dir_path, backup_dir.as_ref())?;
+        Ok(epoch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dbms::test_config, hash_table::HashTable};
+
+    #[test]
+    fn test_backup_to() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let backup_dir = tempfile::tempdir()?;
+        let mut hash_table = ManagedHashTable::open(dir.path().join("store"), test_config())?;
+        for i in 0..50 {
+            hash_table.insert(format!("key-{i}").as_bytes(), format!("value-{i}").as_bytes())?;
+        }
+        let epoch = hash_table.backup_to(backup_dir.path().join("first"))?;
+        assert_eq!(epoch, hash_table.epoch());
+        let err = hash_table.backup_to(backup_dir.path().join("first")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        let hash_table = hash_table.into_shared();
+        let second_epoch = hash_table.backup_to(backup_dir.path().join("second"))?;
+        hash_table.insert(b"key-0", b"after")?;
+        hash_table.sync()?;
+
+        for (name, epoch) in [("first", epoch), ("second", second_epoch)] {
+            let mut backup = ManagedHashTable::open(backup_dir.path().join(name), test_config())?;
+            // Opening for writing checkpoints once more.
+            assert_eq!(backup.epoch(), epoch + 1);
+            assert_eq!(backup.get(b"key-0")?, Some(b"value-0".to_vec()));
+            assert_eq!(backup.get(b"key-49")?, Some(b"value-49".to_vec()));
+            assert_eq!(backup.stats()?.entry_count, 50);
+        }
+        Ok(())
+    }
+}
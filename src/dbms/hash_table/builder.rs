@@ -0,0 +1,231 @@
+use std::path::{Path, PathBuf};
+
+use crate::{book::SectionIndex, hash_table::{book::{BloomConfig, DuplicateKeys, EntryChecksum, EntryMetadataFormat, EntrySizeLimits, IndexChunkSize}, hasher_kind::HasherKind}, pager::PageSize, vfs::{StdFs, Vfs}};
+
+use super::{AutoCompaction, BackgroundSync, HashTableConfig, ManagedHashTable, ManagedHashTableError, OpenOptions, StoreQuotas, SyncPolicy, WALRecovery};
+
+/// Opens a `ManagedHashTable` with only the options that matter to the caller.
+///
+/// Format options that are not set are taken from the existing store, or default when the
+/// store is created; set ones must match the existing store.
+///
+/// ```no_run
+/// # use datastore::dbms::ManagedHashTable;
+/// let hash_table = ManagedHashTable::builder("data/store")
+///     .page_size(64 * 1024)
+///     .section_count(256)
+///     .open()?;
+/// # Ok::<(), datastore::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct ManagedHashTableBuilder<V = StdFs> {
+    vfs: V,
+    dir_path: PathBuf,
+    options: OpenOptions,
+}
+
+impl ManagedHashTableBuilder {
+    pub fn new(dir_path: impl AsRef<Path>) -> Self {
+        Self {
+            vfs: StdFs,
+            dir_path: dir_path.as_ref().to_path_buf(),
+            options: OpenOptions::default(),
+        }
+    }
+}
+
+impl<V: Vfs> ManagedHashTableBuilder<V> {
+    /// Sets every config field at once.
+    pub fn config(mut self, config: HashTableConfig) -> Self {
+        self.options = OpenOptions {
+            create_if_missing: self.options.create_if_missing,
+            read_only: self.options.read_only,
+            ..OpenOptions::from_config(config)
+        };
+        self
+    }
+
+    pub fn page_size(mut self, page_size: PageSize) -> Self {
+        self.options.page_size = Some(page_size);
+        self
+    }
+
+    pub fn section_count(mut self, section_count: SectionIndex) -> Self {
+        self.options.section_count = Some(section_count);
+        self
+    }
+
+    pub fn index_chunk_size(mut self, index_chunk_size: IndexChunkSize) -> Self {
+        self.options.index_chunk_size = Some(index_chunk_size);
+        self
+    }
+
+    pub fn hasher(mut self, hasher: HasherKind) -> Self {
+        self.options.hasher = Some(hasher);
+        self
+    }
+
+    pub fn bloom(mut self, bloom: BloomConfig) -> Self {
+        self.options.bloom = Some(bloom);
+        self
+    }
+
+    pub fn entry_size_limits(mut self, entry_size_limits: EntrySizeLimits) -> Self {
+        self.options.entry_size_limits = Some(entry_size_limits);
+        self
+    }
+
+    pub fn wal_recovery(mut self, wal_recovery: WALRecovery) -> Self {
+        self.options.wal_recovery = Some(wal_recovery);
+        self
+    }
+
+    pub fn entry_metadata(mut self, entry_metadata: EntryMetadataFormat) -> Self {
+        self.options.entry_metadata = Some(entry_metadata);
+        self
+    }
+
+    pub fn entry_checksum(mut self, entry_checksum: EntryChecksum) -> Self {
+        self.options.entry_checksum = Some(entry_checksum);
+        self
+    }
+
+    pub fn quotas(mut self, quotas: StoreQuotas) -> Self {
+        self.options.quotas = Some(quotas);
+        self
+    }
+
+    pub fn duplicate_keys(mut self, duplicate_keys: DuplicateKeys) -> Self {
+        self.options.duplicate_keys = Some(duplicate_keys);
+        self
+    }
+
+    pub fn sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.options.sync_policy = Some(sync_policy);
+        self
+    }
+
+    pub fn background_sync(mut self, background_sync: BackgroundSync) -> Self {
+        self.options.background_sync = Some(Some(background_sync));
+        self
+    }
+
+    pub fn auto_compaction(mut self, auto_compaction: AutoCompaction) -> Self {
+        self.options.auto_compaction = Some(Some(auto_compaction));
+        self
+    }
+
+    pub fn double_write(mut self, double_write: bool) -> Self {
+        self.options.double_write = Some(double_write);
+        self
+    }
+
+    pub fn page_cache_pages(mut self, page_cache_pages: usize) -> Self {
+        self.options.page_cache_pages = Some(page_cache_pages);
+        self
+    }
+
+    /// Opens the store without modifying any of its files; `insert` and `sync` fail with
+    /// `PermissionDenied`. Implies `create_if_missing(false)`.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.options.read_only = read_only;
+        self
+    }
+
+    /// Whether a missing store is created, `true` by default unless opening read-only.
+    pub fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.options.create_if_missing = Some(create_if_missing);
+        self
+    }
+
+    /// Stores the files on `vfs` instead of the operating system's file system.
+    pub fn vfs<W: Vfs>(self, vfs: W) -> ManagedHashTableBuilder<W> {
+        ManagedHashTableBuilder {
+            vfs,
+            dir_path: self.dir_path,
+            options: self.options,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ManagedHashTableError> {
+        self.options.apply_to(HashTableConfig::default()).validate()?;
+        if self.options.read_only && self.options.create_if_missing == Some(true) {
+            return Err(ManagedHashTableError::InvalidOption {
+                option: "create_if_missing",
+                reason: "a store opened read-only cannot be created",
+            });
+        }
+        Ok(())
+    }
+
+    pub fn open(self) -> crate::Result<ManagedHashTable<V>> {
+        self.validate()?;
+        Ok(ManagedHashTable::open_inner(self.vfs, &self.dir_path, self.options)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use crate::hash_table::{HashTable, HashTableEntry, HashTableScanFilter, HashTableScanner};
+
+    #[test]
+    fn test_builder_uses_config_of_existing_store() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        {
+            let mut hash_table = ManagedHashTable::builder(dir.path())
+                .page_size(64)
+                .section_count(4)
+                .index_chunk_size(64)
+                .open()?;
+            hash_table.insert(b"foo", b"bar")?;
+            hash_table.sync()?;
+        }
+
+        let hash_table = ManagedHashTable::builder(dir.path()).section_count(4).open()?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(b"foo"))?;
+        let value = scanner.next()?.expect("entry must survive reopening").read_value_to_vec()?;
+        assert_eq!(value, b"bar");
+        drop(scanner);
+        drop(hash_table);
+
+        let err = ManagedHashTable::builder(dir.path()).page_size(128).open().err().unwrap();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::ConfigMismatch { field: "page_size", .. }),
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_options() {
+        let err = ManagedHashTable::builder("unused").section_count(0).open().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+            Some(ManagedHashTableError::InvalidOption { option: "section_count", .. }),
+        ));
+
+        let err = ManagedHashTable::builder("unused").read_only(true).create_if_missing(true).validate().unwrap_err();
+        assert!(matches!(err, ManagedHashTableError::InvalidOption { option: "create_if_missing", .. }));
+    }
+
+    #[test]
+    fn test_read_only() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let err = ManagedHashTable::builder(dir.path().join("missing")).read_only(true).open().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let mut writer = ManagedHashTable::builder(dir.path()).page_size(64).section_count(4).index_chunk_size(64).open()?;
+        writer.insert(b"foo", b"bar")?;
+        writer.sync()?;
+
+        let mut reader = ManagedHashTable::builder(dir.path()).read_only(true).open()?;
+        assert!(reader.is_read_only());
+        assert!(reader.scan(HashTableScanFilter::Key(b"foo"))?.next()?.is_some());
+        assert_eq!(reader.insert(b"foo", b"baz").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(reader.sync().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        Ok(())
+    }
+}
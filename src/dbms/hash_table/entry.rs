@@ -0,0 +1,62 @@
+use std::io;
+
+use crate::{hash_table::HashTable, vfs::Vfs};
+
+use super::ManagedHashTable;
+
+/// A key with its latest value, for read-modify-write in the manner of the entry API of
+/// `std::collections::HashMap`, see [`ManagedHashTable::entry`].
+///
+/// The entry borrows the table exclusively, so no insert of this process comes in between
+/// reading the value and writing it, and the store's lock keeps other writers out.
+pub struct Entry<'a, V: Vfs> {
+    hash_table: &'a mut ManagedHashTable<V>,
+    key: Vec<u8>,
+    /// Latest value of the key, `None` if there is none.
+    value: Option<Vec<u8>>,
+}
+
+impl<'a, V: Vfs> Entry<'a, V> {
+    pub(super) fn new(hash_table: &'a mut ManagedHashTable<V>, key: &[u8]) -> io::Result<Self> {
+        let value = hash_table.get(key)?;
+        Ok(Self { hash_table, key: key.to_vec(), value })
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// The latest value, `None` if the key has none.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.value.as_deref()
+    }
+
+    /// Inserts the value as changed by `modify` if the key has one.
+    pub fn and_modify(mut self, modify: impl FnOnce(&mut Vec<u8>)) -> crate::Result<Self> {
+        if let Some(value) = &mut self.value {
+            modify(value);
+            self.hash_table.insert(&self.key, value)?;
+        }
+        Ok(self)
+    }
+
+    /// The latest value, inserting `default` first if the key has none.
+    pub fn or_insert(self, default: &[u8]) -> crate::Result<Vec<u8>> {
+        self.or_insert_with(|| default.to_vec())
+    }
+
+    /// The latest value, inserting the result of `default` first if the key has none.
+    pub fn or_insert_with(self, default: impl FnOnce() -> Vec<u8>) -> crate::Result<Vec<u8>> {
+        if let Some(value) = self.value {
+            return Ok(value);
+        }
+        let value = default();
+        self.hash_table.insert(&self.key, &value)?;
+        Ok(value)
+    }
+
+    /// Inserts `value` whether or not the key has one.
+    pub fn insert(self, value: &[u8]) -> crate::Result<()> {
+        self.hash_table.insert(&self.key, value)
+    }
+}
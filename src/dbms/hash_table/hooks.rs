@@ -0,0 +1,92 @@
+use std::sync::{Mutex, PoisonError};
+
+use crate::{book::SectionIndex, dbms::SyncSequence, hash_table::book::DuplicateKeys};
+
+/// Passed to the `on_insert` hooks after an entry was inserted.
+#[derive(Clone, Copy, Debug)]
+pub struct InsertEvent<'a> {
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+    /// The sequence number stored with the entry, if the store keeps them.
+    pub sequence: Option<u64>,
+}
+
+/// Passed to the `on_sync` hooks after a `sync` or `full_sync` completed.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncEvent {
+    /// Whether it was a `full_sync`, which also saved the registries and cleared the write-ahead log.
+    pub full: bool,
+    pub sync_sequence: SyncSequence,
+}
+
+/// Passed to the `on_compaction_start` and `on_compaction_finish` hooks around the rewrite made by
+/// `compact` and `resize_sections`.
+#[derive(Clone, Copy, Debug)]
+pub struct CompactionEvent {
+    /// `LatestWins` for `compact`, which keeps only the latest entry of every key, `KeepAll` for
+    /// `resize_sections`.
+    pub duplicate_keys: DuplicateKeys,
+    /// Number of sections of the rewritten store.
+    pub section_count: SectionIndex,
+    /// Number of entries copied into the rewritten store, `None` for `on_compaction_start`.
+    pub entry_count: Option<u64>,
+}
+
+/// Behind a mutex only to keep the store `Sync`, as the hooks are not; they are always reached
+/// through `&mut`, so it is never contended.
+#[derive(Default)]
+pub(super) struct Hooks(Mutex<HookLists>);
+
+#[derive(Default)]
+struct HookLists {
+    on_insert: Vec<Box<dyn FnMut(&InsertEvent) + Send>>,
+    on_sync: Vec<Box<dyn FnMut(&SyncEvent) + Send>>,
+    on_compaction_start: Vec<Box<dyn FnMut(&CompactionEvent) + Send>>,
+    on_compaction_finish: Vec<Box<dyn FnMut(&CompactionEvent) + Send>>,
+}
+
+impl Hooks {
+    fn lists(&mut self) -> &mut HookLists {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(super) fn add_insert(&mut self, hook: Box<dyn FnMut(&InsertEvent) + Send>) {
+        self.lists().on_insert.push(hook);
+    }
+
+    pub(super) fn add_sync(&mut self, hook: Box<dyn FnMut(&SyncEvent) + Send>) {
+        self.lists().on_sync.push(hook);
+    }
+
+    pub(super) fn add_compaction_start(&mut self, hook: Box<dyn FnMut(&CompactionEvent) + Send>) {
+        self.lists().on_compaction_start.push(hook);
+    }
+
+    pub(super) fn add_compaction_finish(&mut self, hook: Box<dyn FnMut(&CompactionEvent) + Send>) {
+        self.lists().on_compaction_finish.push(hook);
+    }
+
+    pub(super) fn insert(&mut self, event: &InsertEvent) {
+        for hook in &mut self.lists().on_insert {
+            hook(event);
+        }
+    }
+
+    pub(super) fn sync(&mut self, event: &SyncEvent) {
+        for hook in &mut self.lists().on_sync {
+            hook(event);
+        }
+    }
+
+    pub(super) fn compaction_start(&mut self, event: &CompactionEvent) {
+        for hook in &mut self.lists().on_compaction_start {
+            hook(event);
+        }
+    }
+
+    pub(super) fn compaction_finish(&mut self, event: &CompactionEvent) {
+        for hook in &mut self.lists().on_compaction_finish {
+            hook(event);
+        }
+    }
+}
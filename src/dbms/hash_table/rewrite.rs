@@ -0,0 +1,162 @@
+use std::{io, path::Path};
+
+use crate::{book::SectionIndex, hash_table::book::DuplicateKeys, vfs::{Vfs, VfsFile}};
+
+use super::{CompactionEvent, HashTableConfig, ManagedHashTable, ManagedHashTableError, OpenOptions, write_file_atomically};
+
+/// Directory inside a store in which `resize_sections` and `compact` build the rewritten store.
+const REWRITE_DIR: &str = "rewrite";
+
+/// Created in `REWRITE_DIR` once the rewritten store is complete. From then on its files replace
+/// the store's, which the next writable open finishes if it was interrupted.
+const REWRITE_COMPLETE: &str = "complete";
+
+/// The files of a store, the header last.
+pub(super) const STORE_FILES: [&str; 9] = [
+    "events.log",
+    "pages.dat",
+    "pages.reg",
+    "sections.reg",
+    "indexes.reg",
+    "filters.reg",
+    "keys.hll",
+    "sync.seq",
+    "header.json",
+];
+
+/// Moves the files of a completed rewrite into place. Read-only opens fail until a writer did so,
+/// as the store may consist of files of both versions.
+pub(super) fn finish_interrupted_rewrite<V: Vfs>(vfs: &V, dir_path: &Path, read_only: bool) -> io::Result<()> {
+    let rewrite_dir = dir_path.join(REWRITE_DIR);
+    if !vfs.exists(&rewrite_dir.join(REWRITE_COMPLETE))? {
+        return Ok(());
+    }
+    if read_only {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "Store is being rewritten; open it for writing to finish"));
+    }
+    let lock_file = vfs.open(&dir_path.join("store.lock"))?;
+    lock_file.try_lock().map_err(|err| match err.kind() {
+        io::ErrorKind::WouldBlock => ManagedHashTableError::Locked { dir_path: dir_path.to_path_buf() }.into(),
+        _ => err,
+    })?;
+
+    for file_name in STORE_FILES {
+        let rewritten_path = rewrite_dir.join(file_name);
+        if vfs.exists(&rewritten_path)? {
+            vfs.rename(&rewritten_path, &dir_path.join(file_name))?;
+        }
+    }
+    vfs.sync_dir(dir_path)?;
+    vfs.remove_file(&rewrite_dir.join(REWRITE_COMPLETE))?;
+    vfs.sync_dir(&rewrite_dir)
+}
+
+impl<V: Vfs> ManagedHashTable<V> {
+    /// Rewrites the entries into `section_count` sections and returns the store reopened with
+    /// them, e.g. once sections grew long. Blocks until every entry is copied, shadowed duplicates
+    /// and metadata included.
+    ///
+    /// The resized store is built next to the current one and replaces it once complete, so a
+    /// failure or crash before that leaves the store as it was. Instances opened read-only have
+    /// to be reopened afterwards.
+    pub fn resize_sections(mut self, section_count: SectionIndex) -> crate::Result<Self> {
+        self.check_writable()?;
+        if section_count == self.config.section_count {
+            return Ok(self);
+        }
+        let config = HashTableConfig {
+            section_count,
+            ..self.config.clone()
+        };
+        config.validate()?;
+        self.rewrite(config, DuplicateKeys::KeepAll)?;
+        Ok(self)
+    }
+
+    /// Rewrites the store keeping only the latest entry of every key, with its metadata, and
+    /// returns it reopened. The index chunks and bloom filters are rebuilt for the remaining
+    /// entries, and the pages of the dropped ones are released as `pages.dat` is rewritten.
+    ///
+    /// Builds and replaces the store like `resize_sections`.
+    pub fn compact(mut self) -> crate::Result<Self> {
+        self.check_writable()?;
+        let config = self.config.clone();
+        self.rewrite(config, DuplicateKeys::LatestWins)?;
+        Ok(self)
+    }
+
+    /// Copies the entries into a new store with `config` and replaces this one with it, reopened
+    /// in place. Should that fail, the table is left read-only, as its files were replaced.
+    pub(super) fn rewrite(&mut self, config: HashTableConfig, duplicate_keys: DuplicateKeys) -> io::Result<()> {
+        let mut event = CompactionEvent {
+            duplicate_keys,
+            section_count: config.section_count,
+            entry_count: None,
+        };
+        self.hooks.compaction_start(&event);
+        self.full_sync_inner()?;
+
+        let rewrite_dir = self.dir_path.join(REWRITE_DIR);
+        // Left behind by a rewrite that did not complete, the double-write journal included, as
+        // its images belong to the pages removed here.
+        for file_name in STORE_FILES.into_iter().chain(["pages.dwb"]) {
+            let stale_path = rewrite_dir.join(file_name);
+            if self.vfs.exists(&stale_path)? {
+                self.vfs.remove_file(&stale_path)?;
+            }
+        }
+
+        let mut rewritten = ManagedHashTable::open_with_vfs(&self.vfs, &rewrite_dir, config.clone())?;
+        let mut entry_count = 0;
+        self.hash_table.for_each_entry(duplicate_keys, |key, value, metadata| {
+            rewritten.hash_table.insert_with_metadata(key, value, metadata)?;
+            entry_count += 1;
+            rewritten.key_sketch.insert(key)
+        })?;
+        // Continues the epoch, which the following `full_sync` advances.
+        rewritten.sync_sequence.checkpoint = self.sync_sequence.checkpoint;
+        rewritten.full_sync_inner()?;
+        drop(rewritten);
+        write_file_atomically(&self.vfs, &rewrite_dir, REWRITE_COMPLETE, &[])?;
+
+        // Released for the reopen, which moves the rewritten files into place; the other fields
+        // are only replaced once it succeeded.
+        self._lock_file = None;
+        self.read_only = true;
+        let reopened = ManagedHashTable::open_inner(&self.vfs, &self.dir_path, OpenOptions::from_config(config))?;
+        let ManagedHashTable {
+            vfs: _,
+            dir_path: _,
+            config,
+            mut hash_table,
+            key_sketch,
+            wal,
+            discarded_wal_bytes,
+            read_only,
+            sync_file,
+            sync_sequence,
+            wal_replay_height,
+            unsynced_inserts,
+            last_sync,
+            hooks: _,
+            _lock_file,
+        } = reopened;
+        hash_table.set_metrics(self.hash_table.metrics().cloned());
+        self.config = config;
+        self.hash_table = hash_table;
+        self.key_sketch = key_sketch;
+        self.wal = wal;
+        self.discarded_wal_bytes = discarded_wal_bytes;
+        self.read_only = read_only;
+        self.sync_file = sync_file;
+        self.sync_sequence = sync_sequence;
+        self.wal_replay_height = wal_replay_height;
+        self.unsynced_inserts = unsynced_inserts;
+        self.last_sync = last_sync;
+        self._lock_file = _lock_file;
+
+        event.entry_count = Some(entry_count);
+        self.hooks.compaction_finish(&event);
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use std::{io, sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak, mpsc}, thread};
+
+use crate::{hash_table::HashTable, vfs::{StdFs, Vfs}};
+use crate::PoisonedLockError;
+
+use super::{BackgroundSync, ManagedHashTable};
+
+/// A `ManagedHashTable` shared between threads, see [`ManagedHashTable::into_shared`].
+///
+/// Inserts and syncs take the table exclusively, one at a time. Scans and lookups only share
+/// it, so any number of them run at once, but they wait for a running insert and hold off the
+/// next one until they are done.
+pub struct SharedHashTable<V: Vfs = StdFs> {
+    /// Dropped first, so the table is dropped by the last handle rather than the sync thread.
+    background_sync: Option<Arc<BackgroundSyncThread>>,
+    hash_table: Arc<RwLock<ManagedHashTable<V>>>,
+}
+
+impl<V: Vfs> Clone for SharedHashTable<V> {
+    fn clone(&self) -> Self {
+        Self {
+            background_sync: self.background_sync.clone(),
+            hash_table: self.hash_table.clone(),
+        }
+    }
+}
+
+/// Stops and joins the thread of `BackgroundSync` once dropped with the last handle.
+struct BackgroundSyncThread {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+    /// The failure that stopped the thread, until taken by `take_background_error`.
+    error: Arc<Mutex<Option<io::Error>>>,
+}
+
+impl Drop for BackgroundSyncThread {
+    fn drop(&mut self) {
+        self.stop = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<V: Vfs> ManagedHashTable<V>
+where
+    ManagedHashTable<V>: Send + Sync + 'static,
+{
+    /// Wraps the table into a cloneable handle, starting the thread of `background_sync` if it
+    /// is configured.
+    pub fn into_shared(self) -> SharedHashTable<V> {
+        let background_sync = self.config.background_sync;
+        let hash_table = Arc::new(RwLock::new(self));
+        let background_sync = background_sync.map(|background_sync| {
+            let (stop, stopped) = mpsc::channel();
+            let error = Arc::new(Mutex::new(None));
+            let (weak, thread_error) = (Arc::downgrade(&hash_table), error.clone());
+            let thread = thread::spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(background_sync.interval) {
+                    if let Err(err) = background_sync_tick(&weak, background_sync) {
+                        if let Ok(mut error) = thread_error.lock() {
+                            *error = Some(err);
+                        }
+                        return;
+                    }
+                }
+            });
+            Arc::new(BackgroundSyncThread { stop: Some(stop), thread: Some(thread), error })
+        });
+        SharedHashTable { background_sync, hash_table }
+    }
+}
+
+fn background_sync_tick<V: Vfs>(hash_table: &Weak<RwLock<ManagedHashTable<V>>>, background_sync: BackgroundSync) -> io::Result<()> {
+    let Some(hash_table) = hash_table.upgrade() else {
+        return Ok(());
+    };
+    let mut hash_table = hash_table.write().map_err(|_| io::Error::other(PoisonedLockError))?;
+    if !hash_table.has_unsynced_inserts()? {
+        return Ok(());
+    }
+    let wal_bytes = hash_table.wal_bytes()?;
+    if background_sync.full_sync_wal_bytes.is_some_and(|limit| wal_bytes >= limit) {
+        Ok(hash_table.full_sync()?)
+    } else {
+        Ok(hash_table.sync()?)
+    }
+}
+
+impl<V: Vfs> SharedHashTable<V> {
+    /// Shares the table until the guard is dropped, e.g. to `scan` it.
+    pub fn read(&self) -> crate::Result<RwLockReadGuard<'_, ManagedHashTable<V>>> {
+        Ok(self.hash_table.read().map_err(|_| io::Error::other(PoisonedLockError))?)
+    }
+
+    /// Takes the table exclusively until the guard is dropped, e.g. to run a transaction.
+    pub fn write(&self) -> crate::Result<RwLockWriteGuard<'_, ManagedHashTable<V>>> {
+        Ok(self.hash_table.write().map_err(|_| io::Error::other(PoisonedLockError))?)
+    }
+
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        self.write()?.insert(key, value)
+    }
+
+    /// The value inserted last for `key`, `None` if there is none.
+    pub fn get(&self, key: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+        self.read()?.get(key)
+    }
+
+    /// Syncs unless another handle's sync covered every insert already, so concurrent writers
+    /// that each sync after their inserts share the flushes of the one syncing first.
+    pub fn sync(&self) -> crate::Result<()> {
+        let mut hash_table = self.write()?;
+        if !hash_table.has_unsynced_inserts()? {
+            return Ok(());
+        }
+        hash_table.sync()
+    }
+
+    pub fn full_sync(&self) -> crate::Result<()> {
+        self.write()?.full_sync()
+    }
+
+    /// The error that stopped the `BackgroundSync` thread, if any. It is not restarted.
+    pub fn take_background_error(&self) -> Option<io::Error> {
+        self.background_sync.as_ref()?.error.lock().ok()?.take()
+    }
+
+    /// The table back, if this is its last handle. Stops the `BackgroundSync` thread.
+    pub fn try_unwrap(self) -> Result<ManagedHashTable<V>, Self> {
+        let Self { background_sync, hash_table } = self;
+        // Every handle holds the thread, which itself only holds the table while syncing.
+        if background_sync.as_ref().is_some_and(|background_sync| Arc::strong_count(background_sync) > 1) {
+            return Err(Self { background_sync, hash_table });
+        }
+        drop(background_sync);
+        match Arc::try_unwrap(hash_table) {
+            Ok(hash_table) => Ok(hash_table.into_inner().unwrap_or_else(|err| err.into_inner())),
+            Err(hash_table) => Err(Self { background_sync: None, hash_table }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{dbms::test_config, hash_table::{HashTableScanFilter, HashTableScanner}};
+
+    #[test]
+    fn test_shared_hash_table() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let hash_table = ManagedHashTable::open(dir.path(), test_config())?.into_shared();
+
+        thread::scope(|scope| {
+            let mut threads = Vec::new();
+            for writer in 0..4 {
+                let hash_table = hash_table.clone();
+                threads.push(scope.spawn(move || -> io::Result<()> {
+                    for i in 0..50 {
+                        hash_table.insert(format!("key-{writer}-{i}").as_bytes(), b"value")?;
+                    }
+                    Ok(())
+                }));
+            }
+            for _ in 0..2 {
+                let hash_table = hash_table.clone();
+                threads.push(scope.spawn(move || -> io::Result<()> {
+                    for _ in 0..10 {
+                        let hash_table = hash_table.read()?;
+                        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+                        while scanner.next()?.is_some() {}
+                    }
+                    Ok(())
+                }));
+            }
+            threads.into_iter().try_for_each(|thread| thread.join().unwrap())
+        })?;
+        hash_table.sync()?;
+
+        assert_eq!(hash_table.get(b"key-3-49")?, Some(b"value".to_vec()));
+        let mut hash_table = hash_table.try_unwrap().map_err(|_| io::Error::other("Handle still shared"))?;
+        assert_eq!(hash_table.stats()?.entry_count, 200);
+        Ok(())
+    }
+
+    #[test]
+    fn test_background_sync() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let background_sync = BackgroundSync {
+            interval: Duration::from_millis(5),
+            full_sync_wal_bytes: None,
+        };
+        let hash_table = ManagedHashTable::builder(dir.path())
+            .section_count(4)
+            .background_sync(background_sync)
+            .open()?
+            .into_shared();
+        let epoch = hash_table.read()?.epoch();
+        hash_table.insert(b"foo", b"bar")?;
+        wait_until(|| Ok(!hash_table.read()?.has_unsynced_inserts()?))?;
+        assert_eq!(hash_table.read()?.epoch(), epoch);
+        drop(hash_table);
+
+        let hash_table = ManagedHashTable::builder(dir.path())
+            .background_sync(BackgroundSync { full_sync_wal_bytes: Some(4096), ..background_sync })
+            .open()?
+            .into_shared();
+        let epoch = hash_table.read()?.epoch();
+        // Idle ticks neither sync nor checkpoint.
+        let idle = hash_table.read()?.sync_sequence();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(hash_table.read()?.sync_sequence(), idle);
+
+        hash_table.insert(b"foo", b"baz")?;
+        wait_until(|| Ok(!hash_table.read()?.has_unsynced_inserts()?))?;
+        assert_eq!(hash_table.read()?.epoch(), epoch);
+
+        for i in 0..200 {
+            hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+        }
+        wait_until(|| Ok(hash_table.read()?.epoch() > epoch && !hash_table.read()?.has_unsynced_inserts()?))?;
+        let idle = hash_table.read()?.sync_sequence();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(hash_table.read()?.sync_sequence(), idle);
+        assert!(hash_table.take_background_error().is_none());
+        let hash_table = hash_table.try_unwrap().map_err(|_| io::Error::other("Handle still shared"))?;
+        assert_eq!(hash_table.get(b"foo")?, Some(b"baz".to_vec()));
+        Ok(())
+    }
+
+    fn wait_until(mut condition: impl FnMut() -> io::Result<bool>) -> io::Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition()? {
+            if Instant::now() > deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Condition not reached"));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use crate::vfs::Vfs;
+
+use super::ManagedHashTable;
+
+/// How full and how skewed a store is, see `ManagedHashTable::stats`.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HashTableStats {
+    pub entry_count: u64,
+    pub key_bytes: u64,
+    pub value_bytes: u64,
+    /// Bytes used by each section, including entry headers and metadata.
+    pub section_sizes: Vec<u64>,
+    pub index_chunk_count: u64,
+    /// Share of the bits set in the bloom filters of the index chunks, from 0 to 1. Keyed scans
+    /// skip fewer chunks the closer it gets to 1.
+    pub bloom_saturation: f64,
+    /// Pages allocated in `pages.dat`.
+    pub page_count: u64,
+    /// Pages released for reuse, included in `page_count`.
+    #[serde(default)]
+    pub free_page_count: u64,
+    /// Bytes of events in the write-ahead log, its header not counted.
+    pub wal_bytes: u64,
+    /// See `ManagedHashTable::epoch`.
+    pub epoch: u64,
+}
+
+impl<V: Vfs> ManagedHashTable<V> {
+    /// Collects the store's statistics. Reads the header of every entry to count them; everything
+    /// else is known without reading.
+    pub fn stats(&mut self) -> crate::Result<HashTableStats> {
+        let entry_stats = self.hash_table.entry_stats()?;
+        let (index_chunk_count, set_bits) = self.hash_table.index_registry()
+            .headers()
+            .fold((0u64, 0u64), |(count, set_bits), header| (count + 1, set_bits + header.bloom_filter.count_ones() as u64));
+        Ok(HashTableStats {
+            entry_count: entry_stats.entry_count,
+            key_bytes: entry_stats.key_bytes,
+            value_bytes: entry_stats.value_bytes,
+            section_sizes: entry_stats.section_sizes,
+            index_chunk_count,
+            bloom_saturation: if index_chunk_count == 0 { 0.0 } else { set_bits as f64 / (index_chunk_count * self.config.bloom.bits as u64) as f64 },
+            page_count: self.hash_table.book().registry()?.page_count() as u64,
+            free_page_count: self.hash_table.book().registry()?.free_page_count() as u64,
+            wal_bytes: self.wal_bytes()?,
+            epoch: self.epoch(),
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use std::io;
+
+use crate::vfs::Vfs;
+
+use super::{InsertEvent, ManagedHashTable};
+
+/// Inserts staged in memory that are applied and synced together by `commit`, see
+/// [`ManagedHashTable::begin_transaction`].
+///
+/// Recovery only finds a transaction's entries if the `sync` completing its commit was reached,
+/// as the write-ahead log height written by that sync is the commit marker. Dropping the
+/// transaction without committing it rolls it back.
+pub struct Transaction<'a, V: Vfs> {
+    hash_table: &'a mut ManagedHashTable<V>,
+    staged: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Valid savepoints with the number of inserts staged before each, oldest first.
+    savepoints: Vec<(Savepoint, usize)>,
+    next_savepoint_id: u64,
+}
+
+/// A point within a `Transaction`, see `Transaction::savepoint`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Savepoint {
+    id: u64,
+}
+
+impl<'a, V: Vfs> Transaction<'a, V> {
+    pub(super) fn new(hash_table: &'a mut ManagedHashTable<V>) -> Self {
+        Self {
+            hash_table,
+            staged: Vec::new(),
+            savepoints: Vec::new(),
+            next_savepoint_id: 0,
+        }
+    }
+
+    /// Stages an insert, failing right away if the entry exceeds the size limits.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        self.hash_table.hash_table.limits().check(key, value)?;
+        self.staged.push((key.to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    /// Marks the current point, to which `rollback_to` can later return.
+    pub fn savepoint(&mut self) -> Savepoint {
+        let savepoint = Savepoint { id: self.next_savepoint_id };
+        self.next_savepoint_id += 1;
+        self.savepoints.push((savepoint, self.staged.len()));
+        savepoint
+    }
+
+    /// Discards the inserts staged after `savepoint`, which stays valid. Savepoints taken after it
+    /// become invalid.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> crate::Result<()> {
+        let position = self.savepoints
+            .iter()
+            .position(|&(valid, _)| valid == savepoint)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Savepoint was rolled back past"))?;
+        let staged_count = self.savepoints[position].1;
+        self.savepoints.truncate(position + 1);
+        self.staged.truncate(staged_count);
+        Ok(())
+    }
+
+    /// Number of staged inserts.
+    pub fn len(&self) -> usize {
+        self.staged.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// Applies the staged inserts and syncs them. If any of them fails, none are kept and the
+    /// store is back at its state when the transaction began.
+    pub fn commit(self) -> crate::Result<()> {
+        let Self { hash_table, staged, .. } = self;
+        let result = (|| {
+            let sequences = staged
+                .iter()
+                .map(|(key, value)| hash_table.insert_inner(key, value))
+                .collect::<io::Result<Vec<_>>>()?;
+            hash_table.sync()?;
+            Ok(sequences)
+        })();
+        let sequences = match result {
+            Ok(sequences) => sequences,
+            Err(err) => {
+                hash_table.discard_unsynced()?;
+                return Err(err);
+            },
+        };
+        for ((key, value), sequence) in staged.iter().zip(sequences) {
+            hash_table.hooks.insert(&InsertEvent { key, value, sequence });
+        }
+        Ok(())
+    }
+
+    /// Discards the staged inserts, same as dropping the transaction.
+    pub fn rollback(self) {}
+}
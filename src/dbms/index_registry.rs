@@ -1,13 +1,19 @@
 use core::slice;
-use std::{cmp::Ordering, collections::{BTreeMap, BTreeSet}, fs::File, io::{self, Read, Seek}, ops::Bound};
+use std::{cmp::Ordering, collections::{BTreeMap, BTreeSet}, fs::File, io::{self, Read}, ops::Bound};
 
-use crate::{dbms::wal::WriteAheadLog, hash_table::book::{IndexHeader, IndexKey, IndexRegistry}};
+use crate::{vfs::VfsFile, book::SectionIndex, dbms::wal::WriteAheadLog, hash_table::book::{BloomFilter, IndexHeader, IndexKey, IndexRegistry, MAX_BLOOM_WORDS}};
 
-pub struct ManagedIndexRegistry<WAL> {
-    file: File,
+pub struct ManagedIndexRegistry<WAL, F = File> {
+    file: F,
     cache: Vec<(IndexKey, IndexHeader)>,
     map: BTreeMap<IndexKey, usize>,
     hot: BTreeSet<usize>,
+    /// Words of each bloom filter stored, see `BloomConfig::words`.
+    bloom_words: usize,
+    /// Union of the bloom filters of each section's chunks, kept in their own file.
+    filters_file: F,
+    section_filters: Vec<BloomFilter>,
+    hot_sections: BTreeSet<SectionIndex>,
     wal: Option<WAL>,
 }
 
@@ -28,7 +34,20 @@ impl IndexEvent {
                 let cache_idx = u32::from_le_bytes(cache_idx_buffer);
 
                 let key = read_index_key(reader)?;
-                let header = read_index_header(reader)?;
+                let header = read_index_header(reader, 1)?;
+                Ok(IndexEvent::Updated(cache_idx, key, header))
+            }
+            2 => {
+                let mut buffer = [0u8; 5];
+                reader.read_exact(&mut buffer)?;
+                let cache_idx = u32::from_le_bytes(buffer[0..4].try_into().unwrap());
+                let bloom_words = buffer[4] as usize;
+                if bloom_words > MAX_BLOOM_WORDS {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Bloom filter too wide"));
+                }
+
+                let key = read_index_key(reader)?;
+                let header = read_index_header(reader, bloom_words)?;
                 Ok(IndexEvent::Updated(cache_idx, key, header))
             }
             _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown IndexEvent type")),
@@ -37,11 +56,20 @@ impl IndexEvent {
 
     pub fn write(&self, writer: &mut impl io::Write) -> io::Result<()> {
         match self {
+            // Only the words up to the last one with bits set are written, in the original
+            // single-word form when that is enough.
             IndexEvent::Updated(cache_idx, key, header) => {
-                writer.write_all(&[1u8])?;
-                writer.write_all(&cache_idx.to_le_bytes())?;
+                let bloom_words = header.bloom_filter.0.iter().rposition(|&word| word != 0).map_or(1, |last| last + 1);
+                if bloom_words == 1 {
+                    writer.write_all(&[1u8])?;
+                    writer.write_all(&cache_idx.to_le_bytes())?;
+                } else {
+                    writer.write_all(&[2u8])?;
+                    writer.write_all(&cache_idx.to_le_bytes())?;
+                    writer.write_all(&[bloom_words as u8])?;
+                }
                 write_index_key(writer, key)?;
-                write_index_header(writer, header)?;
+                write_index_header(writer, header, bloom_words)?;
             }
         }
         Ok(())
@@ -69,14 +97,28 @@ fn write_index_key(writer: &mut impl io::Write, key: &IndexKey) -> io::Result<()
     Ok(())
 }
 
-const INDEX_HEADER_SIZE: usize = 16;
+fn read_bloom_filter(reader: &mut impl Read, bloom_words: usize) -> io::Result<BloomFilter> {
+    let mut bloom_filter = BloomFilter::default();
+    let mut buffer = [0u8; 8];
+    for word in &mut bloom_filter.0[..bloom_words] {
+        reader.read_exact(&mut buffer)?;
+        *word = u64::from_le_bytes(buffer);
+    }
+    Ok(bloom_filter)
+}
+
+fn write_bloom_filter(writer: &mut impl io::Write, bloom_filter: &BloomFilter, bloom_words: usize) -> io::Result<()> {
+    for word in &bloom_filter.0[..bloom_words] {
+        writer.write_all(&word.to_le_bytes())?;
+    }
+    Ok(())
+}
 
-fn read_index_header(reader: &mut impl Read) -> io::Result<IndexHeader> {
-    let mut buffer = [0u8; INDEX_HEADER_SIZE];
+fn read_index_header(reader: &mut impl Read, bloom_words: usize) -> io::Result<IndexHeader> {
+    let bloom_filter = read_bloom_filter(reader, bloom_words)?;
+    let mut buffer = [0u8; 8];
     reader.read_exact(&mut buffer)?;
-
-    let bloom_filter = u64::from_le_bytes(buffer[0..8].try_into().unwrap());
-    let first_entry_offset = u64::from_le_bytes(buffer[8..16].try_into().unwrap());
+    let first_entry_offset = u64::from_le_bytes(buffer);
 
     Ok(IndexHeader {
         bloom_filter,
@@ -84,86 +126,148 @@ fn read_index_header(reader: &mut impl Read) -> io::Result<IndexHeader> {
     })
 }
 
-fn write_index_header(writer: &mut impl io::Write, header: &IndexHeader) -> io::Result<()> {
-    writer.write_all(&header.bloom_filter.to_le_bytes())?;
+fn write_index_header(writer: &mut impl io::Write, header: &IndexHeader, bloom_words: usize) -> io::Result<()> {
+    write_bloom_filter(writer, &header.bloom_filter, bloom_words)?;
     writer.write_all(&header.first_entry_offset.to_le_bytes())?;
     Ok(())
 }
 
-const ENTRY_SIZE: usize = INDEX_KEY_SIZE + INDEX_HEADER_SIZE;
+fn entry_size(bloom_words: usize) -> usize {
+    INDEX_KEY_SIZE + bloom_words * 8 + 8
+}
 
-fn read_index_entry(reader: &mut impl Read) -> io::Result<(IndexKey, IndexHeader)> {
+fn read_index_entry(reader: &mut impl Read, bloom_words: usize) -> io::Result<(IndexKey, IndexHeader)> {
     let key = read_index_key(reader)?;
-    let header = read_index_header(reader)?;
+    let header = read_index_header(reader, bloom_words)?;
     Ok((key, header))
 }
 
-fn write_index_entry(writer: &mut impl io::Write, key: &IndexKey, header: &IndexHeader) -> io::Result<()> {
+fn write_index_entry(writer: &mut impl io::Write, key: &IndexKey, header: &IndexHeader, bloom_words: usize) -> io::Result<()> {
     write_index_key(writer, key)?;
-    write_index_header(writer, header)?;
+    write_index_header(writer, header, bloom_words)?;
     Ok(())
 }
 
-impl<WAL> ManagedIndexRegistry<WAL> {
+/// The contents of an index registry file holding `entries`, with bloom filters of `bloom_words` words.
+pub fn encode_index_entries<'a>(entries: impl IntoIterator<Item = (&'a IndexKey, &'a IndexHeader)>, bloom_words: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for (key, header) in entries {
+        write_index_entry(&mut bytes, key, header, bloom_words)?;
+    }
+    Ok(bytes)
+}
+
+impl<WAL, F: VfsFile> ManagedIndexRegistry<WAL, F> {
     pub fn apply(&mut self, event: IndexEvent) -> io::Result<()> {
         match event {
             IndexEvent::Updated(cache_idx, key, header) => {
                 match self.cache.len().cmp(&(cache_idx as usize)) {
                     Ordering::Less => return Err(io::Error::new(io::ErrorKind::InvalidData, "Out of order index event")),
-                    Ordering::Equal => self.cache.push((key.clone(), header)),
-                    Ordering::Greater => self.cache[cache_idx as usize] = (key.clone(), header),
+                    Ordering::Equal => self.cache.push((key, header)),
+                    Ordering::Greater => self.cache[cache_idx as usize] = (key, header),
                 }
-                self.map.insert(key.clone(), cache_idx as usize);
+                self.map.insert(key, cache_idx as usize);
                 self.hot.insert(cache_idx as usize);
+                let section_filter = self.section_filters.get_mut(key.section_index as usize)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Section index out of bounds"))?;
+                let merged = section_filter.union(&header.bloom_filter);
+                if merged != *section_filter {
+                    *section_filter = merged;
+                    self.hot_sections.insert(key.section_index);
+                }
             },
         }
         Ok(())
     }
 
+    pub fn headers(&self) -> impl Iterator<Item = &IndexHeader> {
+        self.cache.iter().map(|(_, header)| header)
+    }
+
     pub fn with_wal(mut self, wal: WAL) -> Self {
         self.wal = Some(wal);
         self
     }
 
-    pub fn load(mut file: File) -> io::Result<Self> {
-        let count = file.metadata()?.len() as usize / ENTRY_SIZE;
+    /// Loads the chunk headers from `file` and the section filters from `filters_file`, with bloom
+    /// filters of `bloom_words` words. The section filters are derived from the chunks instead if
+    /// `filters_file` does not hold one per section.
+    pub fn load(mut file: F, mut filters_file: F, section_count: SectionIndex, bloom_words: usize) -> io::Result<Self> {
+        let count = file.len()? as usize / entry_size(bloom_words);
         file.seek(io::SeekFrom::Start(0))?;
         let cache = (0..count)
-            .map(|_| read_index_entry(&mut file))
+            .map(|_| read_index_entry(&mut file, bloom_words))
             .collect::<io::Result<Vec<_>>>()?;
         let map = cache
             .iter()
             .enumerate()
-            .map(|(i, (key, _))| (key.clone(), i))
+            .map(|(i, (key, _))| (*key, i))
             .collect();
-        Ok(Self { file, cache, map, hot: BTreeSet::new(), wal: None })
+
+        let section_filter_size = bloom_words as u64 * 8;
+        let (section_filters, hot_sections) = if filters_file.len()? == section_count as u64 * section_filter_size {
+            let mut buffer = vec![0u8; (section_count as u64 * section_filter_size) as usize];
+            filters_file.seek(io::SeekFrom::Start(0))?;
+            filters_file.read_exact(&mut buffer)?;
+            let section_filters = buffer
+                .chunks_exact(section_filter_size as usize)
+                .map(|mut chunk| read_bloom_filter(&mut chunk, bloom_words))
+                .collect::<io::Result<Vec<_>>>()?;
+            (section_filters, BTreeSet::new())
+        } else {
+            let mut section_filters = vec![BloomFilter::default(); section_count as usize];
+            for (key, header) in &cache {
+                let section_filter = section_filters.get_mut(key.section_index as usize)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Section index out of bounds"))?;
+                *section_filter = section_filter.union(&header.bloom_filter);
+            }
+            (section_filters, (0..section_count).collect())
+        };
+
+        Ok(Self { file, cache, map, hot: BTreeSet::new(), bloom_words, filters_file, section_filters, hot_sections, wal: None })
     }
 
     pub fn save(&mut self) -> io::Result<()> {
         for cache_idx in self.hot.iter() {
             let (key, header) = &self.cache[*cache_idx];
-            self.file.seek(io::SeekFrom::Start(*cache_idx as u64 * ENTRY_SIZE as u64))?;
-            write_index_entry(&mut self.file, key, header)?;
+            self.file.seek(io::SeekFrom::Start((*cache_idx * entry_size(self.bloom_words)) as u64))?;
+            write_index_entry(&mut self.file, key, header, self.bloom_words)?;
         }
         self.file.sync_all()?;
         self.hot.clear();
+
+        if self.hot_sections.is_empty() {
+            return Ok(());
+        }
+        let section_filter_size = self.bloom_words as u64 * 8;
+        let size = self.section_filters.len() as u64 * section_filter_size;
+        if self.filters_file.len()? != size {
+            self.filters_file.set_len(size)?;
+        }
+        for &section_index in self.hot_sections.iter() {
+            let section_filter = &self.section_filters[section_index as usize];
+            self.filters_file.seek(io::SeekFrom::Start(section_index as u64 * section_filter_size))?;
+            write_bloom_filter(&mut self.filters_file, section_filter, self.bloom_words)?;
+        }
+        self.filters_file.sync_all()?;
+        self.hot_sections.clear();
         Ok(())
     }
 }
 
 // TODO: make IndexKey and IndexHeader assigned types for further optimization on resolve methods
-impl<WAL: WriteAheadLog<Event=IndexEvent>> IndexRegistry for ManagedIndexRegistry<WAL> {
+impl<WAL: WriteAheadLog<Event=IndexEvent>, F: VfsFile> IndexRegistry for ManagedIndexRegistry<WAL, F> {
     fn try_resolve_index(&self, index_key: &IndexKey) -> io::Result<Option<IndexHeader>> {
         if let Some(&index) = self.map.get(index_key) {
             let (_, header) = &self.cache[index];
-            Ok(Some(header.clone()))
+            Ok(Some(*header))
         } else {
             Ok(None)
         }
     }
 
     fn try_resolve_next_index(&self, index_key: &IndexKey) -> io::Result<Option<IndexHeader>> {
-        let Some(index) = self.map.range((Bound::Excluded(index_key.clone()), Bound::Excluded(IndexKey { 
+        let Some(index) = self.map.range((Bound::Excluded(*index_key), Bound::Excluded(IndexKey { 
             section_index: index_key.section_index + 1, 
             index_chunk: 0,
         })))
@@ -171,27 +275,31 @@ impl<WAL: WriteAheadLog<Event=IndexEvent>> IndexRegistry for ManagedIndexRegistr
             return Ok(None);
         };
         let (_, header) = &self.cache[*index.1];
-        Ok(Some(header.clone()))
+        Ok(Some(*header))
+    }
+
+    fn section_bloom_filter(&self, section_index: SectionIndex) -> io::Result<Option<BloomFilter>> {
+        Ok(self.section_filters.get(section_index as usize).copied())
     }
 
-    fn update_index_bloom_filter(&mut self, index_key: &IndexKey, entry_offset: u64, bloom_bit: u64) -> io::Result<()> {
+    fn update_index_bloom_filter(&mut self, index_key: &IndexKey, entry_offset: u64, bloom_bits: &BloomFilter) -> io::Result<()> {
         let event = if let Some(&cache_idx) = self.map.get(index_key) {
             let header = &mut self.cache[cache_idx].1;
             let old_bloom_filter = header.bloom_filter;
-            let new_bloom_filter = old_bloom_filter | bloom_bit;
+            let new_bloom_filter = old_bloom_filter.union(bloom_bits);
             if new_bloom_filter == old_bloom_filter {
                 return Ok(());
             }
-            let mut index_header = header.clone();
+            let mut index_header = *header;
             index_header.bloom_filter = new_bloom_filter;
-            IndexEvent::Updated(cache_idx as u32, index_key.clone(), index_header)
+            IndexEvent::Updated(cache_idx as u32, *index_key, index_header)
         } else {
             let cache_idx = self.cache.len();
             let index_header = IndexHeader {
-                bloom_filter: bloom_bit,
+                bloom_filter: *bloom_bits,
                 first_entry_offset: entry_offset,
             };
-            IndexEvent::Updated(cache_idx as u32, index_key.clone(), index_header)
+            IndexEvent::Updated(cache_idx as u32, *index_key, index_header)
         };
         self.wal.record(event.clone())?;
         self.apply(event)?;
@@ -1,18 +1,47 @@
-use std::{cmp::Ordering, collections::BTreeMap, fs::File, io::{self, Read, Seek}, slice};
+use std::{cmp::Ordering, collections::{BTreeMap, BTreeSet}, fs::File, io::{self, Read}, slice};
 
-use crate::{book::pager::{PageHeader, PageKey, PageRegistry}, dbms::wal::WriteAheadLog, pager::PageIndex};
+use crate::{vfs::VfsFile, book::{SectionIndex, SectionPageIndex, pager::{PageHeader, PageKey, PageRegistry}}, dbms::wal::WriteAheadLog, pager::PageIndex};
 
-pub struct ManagedPageRegistry<WAL> {
-    file: File,
+/// Stored in place of the key of a released pager page, so the free list persists with the file.
+const RELEASED_KEY: PageKey = PageKey { section_index: u32::MAX, section_page_index: u32::MAX };
+
+pub struct ManagedPageRegistry<WAL, F = File> {
+    file: F,
     cache: Vec<PageKey>,
     map: BTreeMap<PageKey, PageIndex>,
+    /// Released pager pages, reused lowest first.
+    free: BTreeSet<PageIndex>,
+    /// Pager pages released since the last save, reused only once saved, so the log replayed after
+    /// a crash never releases and reassigns the same page.
+    released: BTreeSet<PageIndex>,
     hot: Vec<(PageKey, PageIndex)>,
     wal: Option<WAL>,
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum PageRegistryError {
+    #[error("Page assignment to pager page {pager_page_index} is out of order, next free page is {next_page_index}")]
+    OutOfOrder { pager_page_index: PageIndex, next_page_index: PageIndex },
+    #[error("Pager page {pager_page_index} is assigned to {existing:?} and cannot also be assigned to {requested:?}")]
+    ConflictingPage { pager_page_index: PageIndex, existing: PageKey, requested: PageKey },
+    #[error("{key:?} is assigned to pager page {existing} and cannot also be assigned to pager page {requested}")]
+    DuplicateKey { key: PageKey, existing: PageIndex, requested: PageIndex },
+    #[error("{key:?} cannot release pager page {pager_page_index}, as it is not assigned to it")]
+    NotAssigned { key: PageKey, pager_page_index: PageIndex },
+}
+
+impl From<PageRegistryError> for io::Error {
+    fn from(err: PageRegistryError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum PageEvent {
     Assigned(PageKey, PageIndex),
+    Released(PageKey, PageIndex),
+    /// Releases the pages of a section from a section page index on.
+    Truncated(SectionIndex, SectionPageIndex),
 }
 
 impl PageEvent {
@@ -28,6 +57,17 @@ impl PageEvent {
                 let pager_page_index = u32::from_le_bytes(index_buffer);
                 Ok(PageEvent::Assigned(key, pager_page_index))
             }
+            2 => {
+                let key = read_page_key(reader)?;
+                let mut index_buffer = [0u8; 4];
+                reader.read_exact(&mut index_buffer)?;
+                let pager_page_index = u32::from_le_bytes(index_buffer);
+                Ok(PageEvent::Released(key, pager_page_index))
+            }
+            3 => {
+                let key = read_page_key(reader)?;
+                Ok(PageEvent::Truncated(key.section_index, key.section_page_index))
+            }
             _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown PageEvent type")),
         }
     }
@@ -39,6 +79,15 @@ impl PageEvent {
                 write_page_key(writer, key)?;
                 writer.write_all(&pager_page_index.to_le_bytes())?;
             }
+            PageEvent::Released(key, pager_page_index) => {
+                writer.write_all(&[2u8])?;
+                write_page_key(writer, key)?;
+                writer.write_all(&pager_page_index.to_le_bytes())?;
+            }
+            &PageEvent::Truncated(section_index, section_page_index) => {
+                writer.write_all(&[3u8])?;
+                write_page_key(writer, &PageKey { section_index, section_page_index })?;
+            }
         }
         Ok(())
     }
@@ -65,18 +114,54 @@ fn write_page_key(writer: &mut impl io::Write, key: &PageKey) -> io::Result<()>
     Ok(())
 }
 
-impl<WAL> ManagedPageRegistry<WAL> {
+impl<WAL, F: VfsFile> ManagedPageRegistry<WAL, F> {
     pub fn apply(&mut self, event: PageEvent) -> io::Result<()> {
         match event {
             PageEvent::Assigned(key, pager_page_index) => {
+                if let Some(&existing) = self.map.get(&key) && existing != pager_page_index {
+                    return Err(PageRegistryError::DuplicateKey { key, existing, requested: pager_page_index }.into());
+                }
                 match self.cache.len().cmp(&(pager_page_index as usize)) {
-                    Ordering::Less => return Err(io::Error::new(io::ErrorKind::InvalidData, "Out of order page event")),
-                    Ordering::Equal => self.cache.push(key.clone()),
-                    Ordering::Greater => self.cache[pager_page_index as usize] = key.clone(),
+                    Ordering::Less => return Err(PageRegistryError::OutOfOrder {
+                        pager_page_index,
+                        next_page_index: self.cache.len() as PageIndex,
+                    }.into()),
+                    Ordering::Equal => self.cache.push(key),
+                    Ordering::Greater => {
+                        // Replaying an assignment that was already saved is fine, re-pointing a page is not.
+                        let existing = self.cache[pager_page_index as usize];
+                        if existing == RELEASED_KEY && self.free.remove(&pager_page_index) {
+                            self.cache[pager_page_index as usize] = key;
+                        } else if existing != key {
+                            return Err(PageRegistryError::ConflictingPage { pager_page_index, existing, requested: key }.into());
+                        }
+                    },
                 }
-                self.map.insert(key.clone(), pager_page_index);
+                self.map.insert(key, pager_page_index);
                 self.hot.push((key, pager_page_index));
             }
+            PageEvent::Released(key, pager_page_index) => {
+                match self.cache.get(pager_page_index as usize) {
+                    // Replaying a release that was already saved.
+                    Some(&RELEASED_KEY) => {},
+                    Some(&existing) if existing == key => {
+                        self.map.remove(&key);
+                        self.cache[pager_page_index as usize] = RELEASED_KEY;
+                        self.released.insert(pager_page_index);
+                        self.hot.push((RELEASED_KEY, pager_page_index));
+                    },
+                    _ => return Err(PageRegistryError::NotAssigned { key, pager_page_index }.into()),
+                }
+            }
+            // Pages already released when replayed are not in the map any more.
+            PageEvent::Truncated(section_index, section_page_index) => {
+                let start = PageKey { section_index, section_page_index };
+                let end = PageKey { section_index, section_page_index: SectionPageIndex::MAX };
+                let released: Vec<_> = self.map.range(start..=end).map(|(key, index)| (*key, *index)).collect();
+                for (key, pager_page_index) in released {
+                    self.apply(PageEvent::Released(key, pager_page_index))?;
+                }
+            }
         }
         Ok(())
     }
@@ -86,18 +171,34 @@ impl<WAL> ManagedPageRegistry<WAL> {
         self
     }
 
-    pub fn load(mut file: File) -> io::Result<Self> {
-        let count = file.metadata()?.len() as usize / ENTRY_SIZE;
+    pub fn load(mut file: F) -> io::Result<Self> {
+        let count = file.len()? as usize / ENTRY_SIZE;
         file.seek(io::SeekFrom::Start(0))?;
         let cache = (0..count)
             .map(|_| read_page_key(&mut file))
             .collect::<io::Result<Vec<_>>>()?;
-        let map = cache
-            .iter()
-            .enumerate()
-            .map(|(i, key)| (key.clone(), i as PageIndex))
-            .collect();
-        Ok(Self { file, cache, map, hot: Vec::new(), wal: None })
+        let (mut map, mut free) = (BTreeMap::new(), BTreeSet::new());
+        for (pager_page_index, key) in cache.iter().enumerate() {
+            let pager_page_index = pager_page_index as PageIndex;
+            if *key == RELEASED_KEY {
+                free.insert(pager_page_index);
+                continue;
+            }
+            if let Some(existing) = map.insert(*key, pager_page_index) {
+                return Err(PageRegistryError::DuplicateKey { key: *key, existing, requested: pager_page_index }.into());
+            }
+        }
+        Ok(Self { file, cache, map, free, released: BTreeSet::new(), hot: Vec::new(), wal: None })
+    }
+
+    /// Number of pager pages assigned so far, released ones included.
+    pub fn page_count(&self) -> PageIndex {
+        self.cache.len() as PageIndex
+    }
+
+    /// Number of released pager pages not reused yet.
+    pub fn free_page_count(&self) -> PageIndex {
+        (self.free.len() + self.released.len()) as PageIndex
     }
 
     pub fn save(&mut self) -> io::Result<()> {
@@ -107,11 +208,12 @@ impl<WAL> ManagedPageRegistry<WAL> {
         }
         self.file.sync_all()?;
         self.hot.clear();
+        self.free.append(&mut self.released);
         Ok(())
     }
 }
 
-impl<WAL: WriteAheadLog<Event=PageEvent>> PageRegistry for ManagedPageRegistry<WAL> {
+impl<WAL: WriteAheadLog<Event=PageEvent>, F: VfsFile> PageRegistry for ManagedPageRegistry<WAL, F> {
     fn try_resolve_page(&self, key: &PageKey) -> io::Result<Option<PageHeader>> {
         if let Some(&pager_page_index) = self.map.get(key) {
             Ok(Some(PageHeader {
@@ -131,12 +233,156 @@ impl<WAL: WriteAheadLog<Event=PageEvent>> PageRegistry for ManagedPageRegistry<W
                 pager_page_index,
             });
         }
-        let pager_page_index = self.cache.len() as PageIndex;
-        let event = PageEvent::Assigned(key.clone(), pager_page_index);
+        let pager_page_index = self.free.first().copied().unwrap_or(self.cache.len() as PageIndex);
+        let event = PageEvent::Assigned(*key, pager_page_index);
         self.wal.record(event.clone())?;
         self.apply(event)?;
         Ok(PageHeader {
             pager_page_index,
         })
     }
+
+    fn release_page(&mut self, key: &PageKey) -> io::Result<()> {
+        let Some(&pager_page_index) = self.map.get(key) else {
+            return Ok(());
+        };
+        let event = PageEvent::Released(*key, pager_page_index);
+        self.wal.record(event.clone())?;
+        self.apply(event)
+    }
+
+    fn release_section_pages(&mut self, section_index: SectionIndex, section_page_index: SectionPageIndex) -> io::Result<()> {
+        let start = PageKey { section_index, section_page_index };
+        let end = PageKey { section_index, section_page_index: SectionPageIndex::MAX };
+        if self.map.range(start..=end).next().is_none() {
+            return Ok(());
+        }
+        let event = PageEvent::Truncated(section_index, section_page_index);
+        self.wal.record(event.clone())?;
+        self.apply(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::wal::NoWal;
+    use tempfile::tempfile;
+
+    fn key(section_index: u32, section_page_index: u32) -> PageKey {
+        PageKey { section_index, section_page_index }
+    }
+
+    fn registry_error(err: io::Error) -> PageRegistryError {
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        *err.into_inner().unwrap().downcast::<PageRegistryError>().unwrap()
+    }
+
+    #[test]
+    fn test_replayed_assignment_is_accepted() -> io::Result<()> {
+        let mut registry = ManagedPageRegistry::<()>::load(tempfile()?)?;
+        registry.apply(PageEvent::Assigned(key(0, 0), 0))?;
+        registry.apply(PageEvent::Assigned(key(1, 0), 1))?;
+        registry.apply(PageEvent::Assigned(key(0, 0), 0))?;
+        assert_eq!(registry.map.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_assignments_are_rejected() -> io::Result<()> {
+        let mut registry = ManagedPageRegistry::<()>::load(tempfile()?)?;
+        registry.apply(PageEvent::Assigned(key(0, 0), 0))?;
+        registry.apply(PageEvent::Assigned(key(1, 0), 1))?;
+
+        let err = registry.apply(PageEvent::Assigned(key(2, 0), 0)).unwrap_err();
+        assert!(matches!(registry_error(err), PageRegistryError::ConflictingPage { pager_page_index: 0, .. }));
+
+        let err = registry.apply(PageEvent::Assigned(key(1, 0), 0)).unwrap_err();
+        assert!(matches!(registry_error(err), PageRegistryError::DuplicateKey { existing: 1, requested: 0, .. }));
+
+        let err = registry.apply(PageEvent::Assigned(key(3, 0), 5)).unwrap_err();
+        assert!(matches!(registry_error(err), PageRegistryError::OutOfOrder { pager_page_index: 5, next_page_index: 2 }));
+
+        assert_eq!(registry.cache, vec![key(0, 0), key(1, 0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_released_pages_are_reused() -> io::Result<()> {
+        let file = tempfile()?;
+        let mut registry = ManagedPageRegistry::<NoWal<PageEvent>>::load(file.try_clone()?)?;
+        for section_index in 0..3 {
+            registry.resolve_page(&key(section_index, 0))?;
+        }
+        registry.release_page(&key(1, 0))?;
+        registry.release_page(&key(0, 0))?;
+        assert!(registry.try_resolve_page(&key(1, 0))?.is_none());
+        assert_eq!(registry.free_page_count(), 2);
+        // Not reused before they are saved.
+        assert_eq!(registry.resolve_page(&key(4, 0))?.pager_page_index, 3);
+        registry.save()?;
+
+        // The free list is kept in the file.
+        let mut registry = ManagedPageRegistry::<NoWal<PageEvent>>::load(file)?;
+        assert_eq!(registry.free_page_count(), 2);
+        assert_eq!(registry.resolve_page(&key(5, 0))?.pager_page_index, 0);
+        assert_eq!(registry.resolve_page(&key(5, 1))?.pager_page_index, 1);
+        assert_eq!(registry.resolve_page(&key(5, 2))?.pager_page_index, 4);
+        assert_eq!(registry.page_count(), 5);
+
+        // Replaying a saved release is fine, releasing another key's page is not.
+        registry.apply(PageEvent::Released(key(2, 0), 2))?;
+        registry.apply(PageEvent::Released(key(2, 0), 2))?;
+        let err = registry.apply(PageEvent::Released(key(2, 0), 0)).unwrap_err();
+        assert!(matches!(registry_error(err), PageRegistryError::NotAssigned { pager_page_index: 0, .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_section_truncate() -> io::Result<()> {
+        use crate::{book::{Book, Section, pager::PagerBook}, pager::memory::MemoryPager};
+        use std::io::{Seek, SeekFrom, Write};
+
+        let registry = ManagedPageRegistry::<NoWal<PageEvent>>::load(tempfile()?)?;
+        let mut book = PagerBook::new(MemoryPager::new(16), registry).with_page_cache(4);
+        let mut section = book.section(1);
+        section.write_all(&[7u8; 64])?;
+        book.section(2).write_all(&[8u8; 16])?;
+        let mut buffer = [0u8; 64];
+        section.rewind()?;
+        section.read_exact(&mut buffer)?;
+
+        section.truncate(20)?;
+        section.rewind()?;
+        section.read_exact(&mut buffer)?;
+        assert_eq!(buffer[..20], [7u8; 20]);
+        assert_eq!(buffer[20..], [0u8; 44]);
+        assert_eq!(book.registry()?.free_page_count(), 2);
+
+        // Replaying the truncation releases nothing more, and other sections keep their pages.
+        book.registry()?.apply(PageEvent::Truncated(1, 2))?;
+        assert_eq!(book.registry()?.free_page_count(), 2);
+        assert!(book.registry()?.try_resolve_page(&key(2, 0))?.is_some());
+
+        // Writing past the end again assigns fresh pages.
+        let mut section = book.section(1);
+        section.seek(SeekFrom::Start(48))?;
+        section.write_all(&[9u8; 4])?;
+        section.rewind()?;
+        section.read_exact(&mut buffer)?;
+        assert_eq!(buffer[..20], [7u8; 20]);
+        assert_eq!(buffer[20..48], [0u8; 28]);
+        assert_eq!(buffer[48..52], [9u8; 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_keys_in_file_are_rejected() -> io::Result<()> {
+        let mut file = tempfile()?;
+        write_page_key(&mut file, &key(0, 0))?;
+        write_page_key(&mut file, &key(0, 0))?;
+        let err = ManagedPageRegistry::<()>::load(file).err().unwrap();
+        assert!(matches!(registry_error(err), PageRegistryError::DuplicateKey { existing: 0, requested: 1, .. }));
+        Ok(())
+    }
 }
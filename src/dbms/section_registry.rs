@@ -1,11 +1,13 @@
 use core::slice;
-use std::{collections::{BTreeSet}, fs::File, io::{self, Read, Seek}};
+use std::{collections::BTreeSet, fs::File, io::{self, Read}, ops::Range};
 
-use crate::{book::SectionIndex, dbms::wal::WriteAheadLog, hash_table::book::{SectionHeader, SectionRegistry}};
+use crate::{vfs::VfsFile, book::SectionIndex, dbms::wal::WriteAheadLog, hash_table::book::{SectionHeader, SectionRegistry}};
 
-pub struct ManagedSectionRegistry<WAL> {
-    file: File,
+pub struct ManagedSectionRegistry<WAL, F = File> {
+    file: F,
     cache: Vec<SectionHeader>,
+    /// Sections with an end offset past zero.
+    non_empty: BTreeSet<SectionIndex>,
     hot: BTreeSet<SectionIndex>,
     wal: Option<WAL>,
 }
@@ -62,13 +64,18 @@ fn write_section_header(writer: &mut impl io::Write, header: &SectionHeader) ->
     Ok(())
 }
 
-impl<WAL> ManagedSectionRegistry<WAL> {
+impl<WAL, F: VfsFile> ManagedSectionRegistry<WAL, F> {
     pub fn apply(&mut self, event: SectionEvent) -> io::Result<()> {
         match event {
             SectionEvent::Updated(section_index, header) => {
                 if self.cache.len() <= section_index as usize {
                     return Err(io::Error::new(io::ErrorKind::InvalidData, "Section index out of bounds"));
                 }
+                if header.end_offset > 0 {
+                    self.non_empty.insert(section_index);
+                } else {
+                    self.non_empty.remove(&section_index);
+                }
                 self.cache[section_index as usize] = header.clone();
                 self.hot.insert(section_index);
             }
@@ -81,15 +88,18 @@ impl<WAL> ManagedSectionRegistry<WAL> {
         self
     }
 
-    pub fn load(mut file: File, section_count: SectionIndex) -> io::Result<Self> {
+    pub fn load(mut file: F, section_count: SectionIndex) -> io::Result<Self> {
         let size = section_count as u64 * ENTRY_SIZE as u64;
-        file.set_len(size)?;
+        if file.len()? != size {
+            file.set_len(size)?;
+        }
 
         file.seek(io::SeekFrom::Start(0))?;
         let cache = (0..section_count)
             .map(|_| read_section_header(&mut file))
             .collect::<io::Result<Vec<_>>>()?;
-        Ok(Self { file, cache, hot: BTreeSet::new(), wal: None })
+        let non_empty = (0..section_count).filter(|&section_index| cache[section_index as usize].end_offset > 0).collect();
+        Ok(Self { file, cache, non_empty, hot: BTreeSet::new(), wal: None })
     }
 
     pub fn save(&mut self) -> io::Result<()> {
@@ -104,7 +114,7 @@ impl<WAL> ManagedSectionRegistry<WAL> {
     }
 }
 
-impl<WAL: WriteAheadLog<Event=SectionEvent>> SectionRegistry for ManagedSectionRegistry<WAL> {
+impl<WAL: WriteAheadLog<Event=SectionEvent>, F: VfsFile> SectionRegistry for ManagedSectionRegistry<WAL, F> {
     fn resolve_section(&self, section_index: SectionIndex) -> io::Result<SectionHeader> {
         self.cache.get(section_index as usize)
             .cloned()
@@ -125,4 +135,32 @@ impl<WAL: WriteAheadLog<Event=SectionEvent>> SectionRegistry for ManagedSectionR
         self.wal.record(event.clone())?;
         self.apply(event)
     }
+
+    fn iter_non_empty(&self, sections: Range<SectionIndex>) -> impl Iterator<Item = io::Result<SectionIndex>> + '_ {
+        self.non_empty.range(sections).map(|&section_index| Ok(section_index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbms::wal::NoWal;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test_iter_non_empty() -> io::Result<()> {
+        let file = tempfile()?;
+        let mut registry = ManagedSectionRegistry::<NoWal<SectionEvent>>::load(file.try_clone()?, 1000)?;
+        for section_index in [3, 500, 999] {
+            registry.update_section_end_offset(section_index, 10)?;
+        }
+        let non_empty = |registry: &ManagedSectionRegistry<NoWal<SectionEvent>>, sections| registry.iter_non_empty(sections).collect::<io::Result<Vec<_>>>();
+        assert_eq!(non_empty(&registry, 0..1000)?, [3, 500, 999]);
+        assert_eq!(non_empty(&registry, 4..999)?, [500]);
+        registry.save()?;
+
+        let registry = ManagedSectionRegistry::<NoWal<SectionEvent>>::load(file, 1000)?;
+        assert_eq!(non_empty(&registry, 0..1000)?, [3, 500, 999]);
+        Ok(())
+    }
 }
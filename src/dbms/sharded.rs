@@ -0,0 +1,246 @@
+use std::{io::{self, Read}, path::{Path, PathBuf}};
+
+use crate::{hash_table::{HashTable, fnv_hasher::{FNV_OFFSET_BASIS, fmix32, fnv1a}, HashTableEntry, HashTableScanFilter, HashTableScanner}, vfs::{StdFs, Vfs}};
+
+use super::{HashTableConfig, ManagedHashTable, hash_table::write_file_atomically};
+
+pub type ShardIndex = u32;
+
+/// Errors raised by `ShardedHashTable` itself, converted into `io::Error`s and from there into `crate::Error`s.
+#[derive(Debug, thiserror::Error)]
+pub enum ShardedHashTableError {
+    #[error("At least one shard directory is required")]
+    NoShards,
+    /// The directory was created as a different shard, e.g. because the directories were reordered.
+    #[error("{} holds shard {found_index} of {found_count}, but was opened as shard {expected_index} of {expected_count}", dir_path.display())]
+    ShardMismatch {
+        dir_path: PathBuf,
+        expected_index: ShardIndex,
+        expected_count: ShardIndex,
+        found_index: ShardIndex,
+        found_count: ShardIndex,
+    },
+}
+
+impl From<ShardedHashTableError> for io::Error {
+    fn from(err: ShardedHashTableError) -> Self {
+        let kind = match err {
+            ShardedHashTableError::NoShards => io::ErrorKind::InvalidInput,
+            ShardedHashTableError::ShardMismatch { .. } => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+impl From<ShardedHashTableError> for crate::Error {
+    fn from(err: ShardedHashTableError) -> Self {
+        io::Error::from(err).into()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ShardHeader {
+    shard_index: ShardIndex,
+    shard_count: ShardIndex,
+}
+
+/// Partitions keys across independent `ManagedHashTable`s, one per directory, e.g. on different disks.
+///
+/// Keys are routed by a hash of the whole key that is independent of the one selecting sections,
+/// so every shard still spreads its keys over all of its sections. Each directory records which
+/// shard it holds; the directories have to be passed in the same order on every open.
+pub struct ShardedHashTable<V: Vfs = StdFs> {
+    shards: Vec<ManagedHashTable<V>>,
+}
+
+impl ShardedHashTable {
+    pub fn open<P: AsRef<Path>>(dir_paths: impl IntoIterator<Item = P>, config: HashTableConfig) -> crate::Result<Self> {
+        Self::open_with_vfs(StdFs, dir_paths, config)
+    }
+}
+
+impl<V: Vfs + Clone> ShardedHashTable<V> {
+    pub fn open_with_vfs<P: AsRef<Path>>(
+        vfs: V,
+        dir_paths: impl IntoIterator<Item = P>,
+        config: HashTableConfig,
+    ) -> crate::Result<Self> {
+        let dir_paths = dir_paths.into_iter().map(|dir_path| dir_path.as_ref().to_path_buf()).collect::<Vec<_>>();
+        if dir_paths.is_empty() {
+            return Err(ShardedHashTableError::NoShards.into());
+        }
+        let shard_count = ShardIndex::try_from(dir_paths.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many shards"))?;
+
+        let shards = dir_paths
+            .iter()
+            .zip(0..)
+            .map(|(dir_path, shard_index)| {
+                let shard = ManagedHashTable::open_with_vfs(vfs.clone(), dir_path, config.clone())?;
+                check_shard_header(&vfs, dir_path, shard_index, shard_count)?;
+                Ok(shard)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self { shards })
+    }
+}
+
+fn check_shard_header<V: Vfs>(vfs: &V, dir_path: &Path, shard_index: ShardIndex, shard_count: ShardIndex) -> io::Result<()> {
+    let header_path = dir_path.join("shard.json");
+    if !vfs.exists(&header_path)? {
+        let header_bytes = serde_json::to_vec_pretty(&ShardHeader { shard_index, shard_count })
+            .map_err(|err| io::Error::other(format!("Failed to write shard metadata: {}", err)))?;
+        return write_file_atomically(vfs, dir_path, "shard.json", &header_bytes);
+    }
+
+    let mut header_bytes = Vec::new();
+    vfs.open_read_only(&header_path)?.read_to_end(&mut header_bytes)?;
+    let header: ShardHeader = serde_json::from_slice(&header_bytes)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse shard metadata: {}", err)))?;
+    if header.shard_index != shard_index || header.shard_count != shard_count {
+        return Err(ShardedHashTableError::ShardMismatch {
+            dir_path: dir_path.to_path_buf(),
+            expected_index: shard_index,
+            expected_count: shard_count,
+            found_index: header.shard_index,
+            found_count: header.shard_count,
+        }.into());
+    }
+    Ok(())
+}
+
+impl<V: Vfs> ShardedHashTable<V> {
+    pub fn shard_count(&self) -> ShardIndex {
+        self.shards.len() as ShardIndex
+    }
+
+    pub fn shard(&self, shard_index: ShardIndex) -> Option<&ManagedHashTable<V>> {
+        self.shards.get(shard_index as usize)
+    }
+
+    pub fn shard_mut(&mut self, shard_index: ShardIndex) -> Option<&mut ManagedHashTable<V>> {
+        self.shards.get_mut(shard_index as usize)
+    }
+
+    /// The shard `key` is stored in.
+    pub fn shard_index(&self, key: &[u8]) -> ShardIndex {
+        fmix32(fnv1a(FNV_OFFSET_BASIS, key)) % self.shard_count()
+    }
+
+    /// Sum of the shards' `ManagedHashTable::approx_key_count`, as every key lives in one shard.
+    pub fn approx_key_count(&self) -> u64 {
+        self.shards.iter().map(ManagedHashTable::approx_key_count).sum()
+    }
+
+    pub fn sync(&mut self) -> crate::Result<()> {
+        self.shards.iter_mut().try_for_each(ManagedHashTable::sync)
+    }
+
+    pub fn full_sync(&mut self) -> crate::Result<()> {
+        self.shards.iter_mut().try_for_each(ManagedHashTable::full_sync)
+    }
+}
+
+impl<V: Vfs> HashTable for ShardedHashTable<V> {
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        let shard_index = self.shard_index(key);
+        self.shards[shard_index as usize].insert(key, value)
+    }
+
+    /// Entries of one key come in the order of inserts; `HashTableScanFilter::All` yields the shards one after another.
+    fn scan<'a>(&'a self, filter: HashTableScanFilter<'a>) -> crate::Result<impl HashTableScanner + 'a> {
+        let scanners = match filter {
+            HashTableScanFilter::Key(key) => vec![self.shards[self.shard_index(key) as usize].scan(filter)?],
+            HashTableScanFilter::All => self.shards
+                .iter()
+                .map(|shard| shard.scan(HashTableScanFilter::All))
+                .collect::<crate::Result<Vec<_>>>()?,
+        };
+        Ok(ShardedScanner { scanners, current: 0 })
+    }
+}
+
+struct ShardedScanner<S> {
+    scanners: Vec<S>,
+    current: usize,
+}
+
+impl<S: HashTableScanner> HashTableScanner for ShardedScanner<S> {
+    fn next(&mut self) -> crate::Result<Option<impl HashTableEntry + use<S>>> {
+        while let Some(scanner) = self.scanners.get_mut(self.current) {
+            if let Some(entry) = scanner.next()? {
+                return Ok(Some(entry));
+            }
+            self.current += 1;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dbms::test_config, hash_table::hasher_kind::HasherKind};
+
+    #[test]
+    fn test_shards_use_all_sections() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir_paths = (0..2).map(|i| dir.path().join(format!("shard-{i}"))).collect::<Vec<_>>();
+        let config = HashTableConfig {
+            hasher: HasherKind::Fnv,
+            section_count: 8,
+            ..test_config()
+        };
+        let mut hash_table = ShardedHashTable::open(&dir_paths, config)?;
+        for i in 0..200 {
+            hash_table.insert(format!("key-{i}").as_bytes(), b"value")?;
+        }
+        for shard_index in 0..2 {
+            let stats = hash_table.shard_mut(shard_index).unwrap().stats()?;
+            assert!(stats.section_sizes.iter().all(|size| *size > 0), "{:?}", stats.section_sizes);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_routes_and_aggregates() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir_paths = (0..3).map(|i| dir.path().join(format!("shard-{i}"))).collect::<Vec<_>>();
+        {
+            let mut hash_table = ShardedHashTable::open(&dir_paths, test_config())?;
+            for i in 0..30 {
+                hash_table.insert(format!("key-{i}").as_bytes(), format!("value-{i}").as_bytes())?;
+            }
+            hash_table.insert(b"key-7", b"value-7b")?;
+            hash_table.sync()?;
+        }
+
+        let hash_table = ShardedHashTable::open(&dir_paths, test_config())?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(b"key-7"))?;
+        let mut values = Vec::new();
+        while let Some(mut entry) = scanner.next()? {
+            values.push(entry.read_value_to_vec()?);
+        }
+        assert_eq!(values, [b"value-7".to_vec(), b"value-7b".to_vec()]);
+        drop(scanner);
+
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        let mut count = 0;
+        while scanner.next()?.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 31);
+        drop(scanner);
+        assert!((0..3).all(|i| hash_table.shard(i).unwrap().scan(HashTableScanFilter::All).unwrap().next().unwrap().is_some()));
+        drop(hash_table);
+
+        let mut reordered = dir_paths.clone();
+        reordered.swap(0, 1);
+        let err = ShardedHashTable::open(&reordered, test_config()).err().unwrap();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<ShardedHashTableError>()),
+            Some(ShardedHashTableError::ShardMismatch { expected_index: 0, found_index: 1, .. }),
+        ));
+        Ok(())
+    }
+}
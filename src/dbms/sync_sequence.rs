@@ -0,0 +1,39 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Progress of a store's writer, kept in `sync.seq` so its readers can tell what changed
+/// without rereading the registries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncSequence {
+    /// Grows with every `full_sync`, which rewrites the registries and clears the write-ahead log.
+    /// This is the store's epoch, see `ManagedHashTable::epoch`.
+    pub checkpoint: u64,
+    /// Grows with every `sync`.
+    pub sync: u64,
+}
+
+impl SyncSequence {
+    /// Reads the counters, treating a missing or torn file as zero.
+    pub(crate) fn read(file: &mut (impl Read + Seek)) -> io::Result<Self> {
+        let mut buffer = [0u8; 16];
+        file.seek(SeekFrom::Start(0))?;
+        match file.read_exact(&mut buffer) {
+            Ok(()) => Ok(Self {
+                checkpoint: u64::from_le_bytes(buffer[0..8].try_into().unwrap()),
+                sync: u64::from_le_bytes(buffer[8..16].try_into().unwrap()),
+            }),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Overwrites the counters in place without syncing; the writer syncs them only when the
+    /// checkpoint changes.
+    pub(crate) fn write(&self, file: &mut (impl Write + Seek)) -> io::Result<()> {
+        let mut buffer = [0u8; 16];
+        buffer[0..8].copy_from_slice(&self.checkpoint.to_le_bytes());
+        buffer[8..16].copy_from_slice(&self.sync.to_le_bytes());
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&buffer)
+    }
+}
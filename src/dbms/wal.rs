@@ -1,4 +1,7 @@
-use std::{cmp::Ordering, fs::File, io::{self, Read, Seek, Write}, marker::PhantomData, sync::{Arc, Mutex}};
+use std::{cmp::Ordering, fs::File, io, marker::PhantomData, sync::{Arc, Mutex}};
+
+use crate::{crc32::Crc32, vfs::VfsFile};
+use crate::PoisonedLockError;
 
 pub trait WriteAheadLog {
     type Event;
@@ -18,6 +21,19 @@ impl<WAL: WriteAheadLog> WriteAheadLog for Option<WAL> {
     }
 }
 
+/// Discards the events, for the tests of the registries that do not replay them.
+#[cfg(test)]
+pub(crate) struct NoWal<Event>(PhantomData<Event>);
+
+#[cfg(test)]
+impl<Event> WriteAheadLog for NoWal<Event> {
+    type Event = Event;
+
+    fn record(&self, _event: Event) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 pub struct ConvertWAL<EventInto, WAL> {
     wal: WAL,
     _marker: PhantomData<EventInto>,
@@ -49,71 +65,270 @@ pub trait SerializableEvent: Sized {
     fn read(reader: &mut impl io::Read) -> io::Result<Self>;
 }
 
-struct FileWALInner {
-    file: File,
+/// Set in the stored height of logs whose records are framed by their length and CRC32. Logs
+/// written before records were framed are read and appended to as they are, until `clear` starts
+/// them over.
+const FRAMED_FLAG: u64 = 1 << 63;
+
+/// Size of the length and CRC32 preceding every framed record.
+const FRAME_HEADER_SIZE: u64 = 8;
+
+/// Splits a stored height into the height and whether the records are framed.
+fn decode_height(stored: u64) -> (u64, bool) {
+    (stored & !FRAMED_FLAG, stored & FRAMED_FLAG != 0)
+}
+
+fn encode_height(height: u64, framed: bool) -> [u8; 8] {
+    (if framed { height | FRAMED_FLAG } else { height }).to_le_bytes()
+}
+
+fn write_record(writer: &mut impl io::Write, event: &impl SerializableEvent, framed: bool) -> io::Result<()> {
+    if !framed {
+        return event.write(writer);
+    }
+    let mut payload = Vec::new();
+    event.write(&mut payload)?;
+    let size = u32::try_from(payload.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "WAL record is too large"))?;
+    let mut crc = Crc32::new();
+    crc.update(&payload);
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE as usize + payload.len());
+    frame.extend_from_slice(&size.to_le_bytes());
+    frame.extend_from_slice(&crc.finalize().to_le_bytes());
+    frame.extend_from_slice(&payload);
+    writer.write_all(&frame)
+}
+
+/// Reads a record ending at or before `height`, given that `reader` is at `offset`. Records cut
+/// short by the end of the file or by `height` fail with `UnexpectedEof`, corrupted framed records
+/// with `InvalidData`.
+fn read_record<Event: SerializableEvent>(reader: &mut impl io::Read, framed: bool, offset: u64, height: u64) -> io::Result<(Event, u64)> {
+    if !framed {
+        let mut reader = CountingReader { reader, count: 0 };
+        let event = Event::read(&mut reader)?;
+        return Ok((event, offset + reader.count));
+    }
+    let mut header = [0u8; FRAME_HEADER_SIZE as usize];
+    reader.read_exact(&mut header)?;
+    let size = u32::from_le_bytes(header[0..4].try_into().unwrap()) as u64;
+    let end_offset = offset + FRAME_HEADER_SIZE + size;
+    if end_offset > height {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "WAL record exceeds height"));
+    }
+    let mut payload = vec![0u8; size as usize];
+    reader.read_exact(&mut payload)?;
+    let mut crc = Crc32::new();
+    crc.update(&payload);
+    if crc.finalize() != u32::from_le_bytes(header[4..8].try_into().unwrap()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "WAL record checksum mismatch"));
+    }
+    let mut payload_reader = payload.as_slice();
+    let event = Event::read(&mut payload_reader).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if !payload_reader.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "WAL record has trailing bytes"));
+    }
+    Ok((event, end_offset))
+}
+
+struct CountingReader<'a, R> {
+    reader: &'a mut R,
+    count: u64,
+}
+
+impl<R: io::Read> io::Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.reader.read(buf)?;
+        self.count += size as u64;
+        Ok(size)
+    }
+}
+
+struct FileWALInner<F> {
+    file: F,
     height: u64,
+    framed: bool,
 }
 
-#[derive(Clone)]
-pub struct FileWAL<Event> {
-    inner: Arc<Mutex<FileWALInner>>,
+pub struct FileWAL<Event, F = File> {
+    inner: Arc<Mutex<FileWALInner<F>>>,
     _marker: PhantomData<Event>,
 }
 
-impl<Event> FileWAL<Event> {
-    pub fn load(mut file: File) -> io::Result<Self> {
-        let len = file.metadata()?.len();
+impl<Event, F> Clone for FileWAL<Event, F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Event, F: VfsFile> FileWAL<Event, F> {
+    pub fn load(mut file: F) -> io::Result<Self> {
+        let len = file.len()?;
         file.seek(io::SeekFrom::Start(0))?;
-        let height = if len == 0 {
-            file.write_all(&8u64.to_le_bytes())?;
-            8
+        let (height, framed) = if len == 0 {
+            file.write_all(&encode_height(8, true))?;
+            (8, true)
         } else {
             let mut buffer = [0u8; 8];
             file.read_exact(&mut buffer).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to read WAL height"))?;
-            let height = u64::from_le_bytes(buffer);
+            let (height, framed) = decode_height(u64::from_le_bytes(buffer));
             if height < 8 || height > len {
                 return Err(io::Error::new(io::ErrorKind::InvalidData, "WAL height is invalid"));
             }
-            height
+            (height, framed)
         };
 
         Ok(Self {
             inner: Arc::new(Mutex::new(FileWALInner {
                 file,
                 height,
+                framed,
             })),
             _marker: PhantomData,
         })
     }
 
+    /// A log over a file that must not be modified, e.g. of a store opened read-only.
+    /// Nothing is read or written until an event is recorded.
+    pub fn read_only(file: F) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(FileWALInner {
+                file,
+                height: 8,
+                framed: true,
+            })),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Size of the log in bytes, including the records not synced yet.
+    pub fn height(&self) -> io::Result<u64> {
+        let inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        Ok(inner.height)
+    }
+
     pub fn sync(&self) -> io::Result<()> {
-        let mut inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
-        let height = inner.height;
+        let mut inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let stored_height = encode_height(inner.height, inner.framed);
         inner.file.seek(io::SeekFrom::Start(0))?;
-        inner.file.write_all(&height.to_le_bytes())?;
+        inner.file.write_all(&stored_height)?;
         inner.file.sync_all()
     }
 
     pub fn clear(&self) -> io::Result<()> {
-        let mut inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
+        let mut inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
         inner.file.seek(io::SeekFrom::Start(0))?;
-        inner.file.write_all(&8u64.to_le_bytes())?;
+        inner.file.write_all(&encode_height(8, true))?;
         inner.height = 8;
+        inner.framed = true;
         Ok(())
     }
 }
 
-impl<Event> WriteAheadLog for FileWAL<Event>
+impl<Event: SerializableEvent, F: VfsFile> FileWAL<Event, F> {
+    /// Iterates over the records committed by the last `sync` that start at or after `from_offset`,
+    /// which is `0` for the beginning of the log or the `end_offset` of a record returned earlier.
+    /// Records committed after this call are returned by the next `tail` from where this one ended.
+    ///
+    /// `clear` starts the log over, invalidating all offsets. It is reported as
+    /// `WALTailError::Cleared` as long as the log is still shorter than `from_offset`; a consumer
+    /// that can fall behind by more than that has to detect it otherwise, e.g. with the
+    /// `SyncSequence` of a store.
+    pub fn tail(&self, from_offset: u64) -> io::Result<WALTail<Event, F>> {
+        let (committed_height, framed) = {
+            let mut inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+            let mut buffer = [0u8; 8];
+            inner.file.seek(io::SeekFrom::Start(0))?;
+            match inner.file.read_exact(&mut buffer) {
+                Ok(()) => decode_height(u64::from_le_bytes(buffer)),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => (8, true),
+                Err(err) => return Err(err),
+            }
+        };
+        let offset = from_offset.max(8);
+        if offset > committed_height {
+            return Err(WALTailError::Cleared { from_offset, committed_height }.into());
+        }
+        Ok(WALTail {
+            wal: self.clone(),
+            offset,
+            committed_height,
+            framed,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WALTailError {
+    #[error("WAL was cleared, offset {from_offset} is beyond its committed height {committed_height}")]
+    Cleared { from_offset: u64, committed_height: u64 },
+}
+
+impl From<WALTailError> for io::Error {
+    fn from(err: WALTailError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// A record returned by `FileWAL::tail`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WALRecord<Event> {
+    pub offset: u64,
+    pub end_offset: u64,
+    pub event: Event,
+}
+
+pub struct WALTail<Event, F = File> {
+    wal: FileWAL<Event, F>,
+    offset: u64,
+    committed_height: u64,
+    framed: bool,
+}
+
+impl<Event, F> WALTail<Event, F> {
+    /// Where the next `tail` has to continue once this one is exhausted.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl<Event: SerializableEvent, F: VfsFile> Iterator for WALTail<Event, F> {
+    type Item = io::Result<WALRecord<Event>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.committed_height {
+            return None;
+        }
+        let result = (|| {
+            let mut inner = self.wal.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+            inner.file.seek(io::SeekFrom::Start(self.offset))?;
+            let (event, end_offset) = read_record(&mut inner.file, self.framed, self.offset, self.committed_height)?;
+            if end_offset > self.committed_height {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "WAL record exceeds height"));
+            }
+            Ok(WALRecord { offset: self.offset, end_offset, event })
+        })();
+        match &result {
+            Ok(record) => self.offset = record.end_offset,
+            // Stop after an error instead of reading garbage from the same offset again.
+            Err(_) => self.offset = self.committed_height,
+        }
+        Some(result)
+    }
+}
+
+impl<Event, F: VfsFile> WriteAheadLog for FileWAL<Event, F>
 where
     Event: SerializableEvent,
 {
     type Event = Event;
 
     fn record(&self, event: Self::Event) -> io::Result<()> {
-        let mut inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
-        let height = inner.height;
+        let mut inner = self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let (height, framed) = (inner.height, inner.framed);
         inner.file.seek(io::SeekFrom::Start(height))?;
-        event.write(&mut inner.file)?;
+        write_record(&mut inner.file, &event, framed)?;
         inner.height = inner.file.stream_position()?;
         Ok(())
     }
@@ -125,46 +340,132 @@ pub trait WALReader {
     fn read_next(&mut self) -> io::Result<Option<Self::Event>>;
 }
 
-pub struct FileWALReader<Event> {
+/// How `FileWALReader` treats a log whose tail cannot be read completely,
+/// e.g. after a crash in the middle of `record` or `sync`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WALRecovery {
+    /// Fail without discarding anything: with `UnexpectedEof` if a record is cut short by the
+    /// end of the log or by the height, with `InvalidData` if the height or a record is corrupted.
+    Strict,
+    /// Treat a record cut short by the end of the log as uncommitted and truncate it. A corrupted
+    /// record, even the last one, still fails with an `InvalidData` error, as it may have been
+    /// committed.
+    #[default]
+    TruncateTail,
+}
+
+pub struct FileWALReader<Event, F = File> {
     height: Option<u64>,
-    file: File,
+    framed: bool,
+    file: F,
+    recovery: WALRecovery,
+    repair: bool,
+    discarded_bytes: u64,
     _marker: PhantomData<Event>,
 }
 
-impl<Event> FileWALReader<Event>
+impl<Event, F: VfsFile> FileWALReader<Event, F>
 {
-    pub fn new(mut file: File) -> io::Result<Self> {
-        let len = file.metadata()?.len();
+    pub fn new(file: F, recovery: WALRecovery) -> io::Result<Self> {
+        Self::open(file, recovery, true)
+    }
+
+    /// Like `new`, but a torn tail is only skipped and never truncated from the file.
+    pub fn new_read_only(file: F, recovery: WALRecovery) -> io::Result<Self> {
+        Self::open(file, recovery, false)
+    }
+
+    fn open(file: F, recovery: WALRecovery, repair: bool) -> io::Result<Self> {
+        let len = file.len()?;
+        let mut reader = Self {
+            height: None,
+            framed: true,
+            file,
+            recovery,
+            repair,
+            discarded_bytes: 0,
+            _marker: PhantomData,
+        };
         if len == 0 {
-            return Ok(Self {
-                height: None,
-                file,
-                _marker: PhantomData,
-            })
+            return Ok(reader);
         };
 
-        file.seek(io::SeekFrom::Start(0))?;
+        reader.file.seek(io::SeekFrom::Start(0))?;
         let mut buffer = [0u8; 8];
-        file.read_exact(&mut buffer).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to read WAL height"))?;
-        let height = u64::from_le_bytes(buffer);
+        if let Err(err) = reader.file.read_exact(&mut buffer) {
+            if recovery == WALRecovery::Strict || err.kind() != io::ErrorKind::UnexpectedEof {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Failed to read WAL height"));
+            }
+            // The height itself was torn, so no record can have been committed.
+            if repair {
+                reader.file.set_len(0)?;
+            }
+            reader.discarded_bytes = len;
+            return Ok(reader);
+        }
+        let (height, framed) = decode_height(u64::from_le_bytes(buffer));
+        reader.framed = framed;
 
-        if height < 8 || height > len {
+        if height < 8 {
             return Err(io::Error::new(io::ErrorKind::InvalidData, "WAL height is invalid"));
         }
+        if height > len {
+            if recovery == WALRecovery::Strict {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "WAL height is invalid"));
+            }
+            reader.height = Some(height);
+            reader.truncate(len)?;
+            return Ok(reader);
+        }
 
-        Ok(Self {
-            height: Some(height),
-            file,
-            _marker: PhantomData,
-        })
+        reader.height = Some(height);
+        Ok(reader)
     }
 
-    pub fn into_file(self) -> File {
+    /// End of the committed records, `None` if the log has never been written.
+    pub fn height(&self) -> Option<u64> {
+        self.height
+    }
+
+    /// Continues reading at `offset`, which must be where a record read earlier ended.
+    pub fn skip_to(&mut self, offset: u64) -> io::Result<()> {
+        if self.height.is_some_and(|height| offset > height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "WAL offset exceeds height"));
+        }
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        Ok(())
+    }
+
+    /// Number of bytes beyond the last completely readable record that were discarded so far.
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded_bytes
+    }
+
+    pub fn into_file(self) -> F {
         self.file
     }
+
+    fn truncate(&mut self, valid_height: u64) -> io::Result<()> {
+        let Some(height) = self.height else {
+            return Ok(());
+        };
+        self.discarded_bytes += height.saturating_sub(valid_height);
+        self.height = Some(valid_height);
+        if !self.repair {
+            return Ok(());
+        }
+        let position = self.file.stream_position()?;
+        self.file.set_len(valid_height)?;
+        self.file.seek(io::SeekFrom::Start(0))?;
+        self.file.write_all(&encode_height(valid_height, self.framed))?;
+        self.file.sync_data()?;
+        self.file.seek(io::SeekFrom::Start(position.min(valid_height)))?;
+        Ok(())
+    }
 }
 
-impl<Event> WALReader for FileWALReader<Event>
+impl<Event, F: VfsFile> WALReader for FileWALReader<Event, F>
 where
     Event: SerializableEvent,
 {
@@ -174,15 +475,254 @@ where
         let Some(height) = self.height else {
             return Ok(None);
         };
-        match self.file.stream_position()?.cmp(&height) {
+        let record_offset = self.file.stream_position()?;
+        match record_offset.cmp(&height) {
             Ordering::Equal => Ok(None),
             Ordering::Greater => {
                 Err(io::Error::new(io::ErrorKind::InvalidData, "WAL reader position exceeded height"))
             },
             Ordering::Less => {
-                let event = Event::read(&mut self.file)?;
-                Ok(Some(event))
+                let result = read_record(&mut self.file, self.framed, record_offset, height).and_then(|(event, end_offset)| {
+                    if end_offset > height {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "WAL record exceeds height"));
+                    }
+                    Ok(event)
+                });
+                match result {
+                    Ok(event) => Ok(Some(event)),
+                    Err(err) if self.recovery == WALRecovery::TruncateTail && is_torn_record(&err) => {
+                        self.truncate(record_offset)?;
+                        Ok(None)
+                    },
+                    Err(err) => Err(err),
+                }
             },
         }
     }
 }
+
+/// Whether the record was cut short by the end of the log, as opposed to corrupted before it,
+/// which would drop the committed records after it if it was truncated.
+fn is_torn_record(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::UnexpectedEof
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, Write};
+    use tempfile::tempfile;
+
+    #[derive(Debug, PartialEq)]
+    struct TestEvent(u32);
+
+    impl SerializableEvent for TestEvent {
+        fn write(&self, writer: &mut impl io::Write) -> io::Result<()> {
+            writer.write_all(&self.0.to_le_bytes())
+        }
+
+        fn read(reader: &mut impl io::Read) -> io::Result<Self> {
+            let mut buffer = [0u8; 4];
+            reader.read_exact(&mut buffer)?;
+            Ok(TestEvent(u32::from_le_bytes(buffer)))
+        }
+    }
+
+    fn write_events(file: &File, events: &[u32]) -> io::Result<()> {
+        let wal = FileWAL::<TestEvent>::load(file.try_clone()?)?;
+        for event in events {
+            wal.record(TestEvent(*event))?;
+        }
+        wal.sync()
+    }
+
+    /// A `TestEvent` with its frame.
+    const RECORD_SIZE: u64 = FRAME_HEADER_SIZE + 4;
+
+    fn read_events(reader: &mut FileWALReader<TestEvent>) -> io::Result<Vec<u32>> {
+        let mut events = Vec::new();
+        while let Some(TestEvent(event)) = reader.read_next()? {
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn test_tail_follows_commits() -> io::Result<()> {
+        let file = tempfile()?;
+        let wal = FileWAL::<TestEvent>::load(file)?;
+        wal.record(TestEvent(1))?;
+        wal.record(TestEvent(2))?;
+        wal.sync()?;
+        wal.record(TestEvent(3))?;
+
+        let records = wal.tail(0)?.collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(records, [
+            WALRecord { offset: 8, end_offset: 20, event: TestEvent(1) },
+            WALRecord { offset: 20, end_offset: 32, event: TestEvent(2) },
+        ]);
+        let mut tail = wal.tail(32)?;
+        assert!(tail.next().is_none());
+
+        wal.sync()?;
+        let events = wal.tail(tail.offset())?.map(|record| record.map(|record| record.event)).collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(events, [TestEvent(3)]);
+
+        wal.clear()?;
+        wal.sync()?;
+        let err = wal.tail(32).err().unwrap();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<WALTailError>()),
+            Some(WALTailError::Cleared { from_offset: 32, committed_height: 8 }),
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> io::Result<()> {
+        let file = tempfile()?;
+        write_events(&file, &[1, 2, 3])?;
+        let mut reader = FileWALReader::<TestEvent>::new(file, WALRecovery::default())?;
+        assert_eq!(read_events(&mut reader)?, vec![1, 2, 3]);
+        assert_eq!(reader.discarded_bytes(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_torn_tail_is_truncated() -> io::Result<()> {
+        let file = tempfile()?;
+        write_events(&file, &[1, 2, 3])?;
+        // Cut the last record in half, as if the crash happened while it was being written.
+        file.set_len(8 + RECORD_SIZE * 2 + 6)?;
+
+        let mut reader = FileWALReader::<TestEvent>::new(file.try_clone()?, WALRecovery::default())?;
+        assert_eq!(read_events(&mut reader)?, vec![1, 2]);
+        assert_eq!(reader.discarded_bytes(), RECORD_SIZE);
+        assert_eq!(file.metadata()?.len(), 8 + RECORD_SIZE * 2);
+
+        let wal = FileWAL::<TestEvent>::load(reader.into_file())?;
+        wal.record(TestEvent(4))?;
+        wal.sync()?;
+        let mut reader = FileWALReader::<TestEvent>::new(file, WALRecovery::default())?;
+        assert_eq!(read_events(&mut reader)?, vec![1, 2, 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_reader_skips_torn_tail() -> io::Result<()> {
+        let file = tempfile()?;
+        write_events(&file, &[1, 2, 3])?;
+        file.set_len(8 + RECORD_SIZE * 2 + 6)?;
+
+        let mut reader = FileWALReader::<TestEvent>::new_read_only(file.try_clone()?, WALRecovery::default())?;
+        assert_eq!(read_events(&mut reader)?, vec![1, 2]);
+        assert_eq!(reader.discarded_bytes(), RECORD_SIZE);
+        assert_eq!(file.metadata()?.len(), 8 + RECORD_SIZE * 2 + 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_torn_tail() -> io::Result<()> {
+        let file = tempfile()?;
+        write_events(&file, &[1, 2])?;
+        file.set_len(8 + RECORD_SIZE + 6)?;
+
+        let err = FileWALReader::<TestEvent>::new(file.try_clone()?, WALRecovery::Strict).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        assert_eq!(file.metadata()?.len(), 8 + RECORD_SIZE + 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupted_record_is_detected() -> io::Result<()> {
+        let file = tempfile()?;
+        write_events(&file, &[1, 2, 3])?;
+        // Flip a bit in the payload of the second record.
+        let offset = 8 + RECORD_SIZE + FRAME_HEADER_SIZE;
+        let mut byte = [0u8; 1];
+        (&file).seek(io::SeekFrom::Start(offset))?;
+        (&file).read_exact(&mut byte)?;
+        (&file).seek(io::SeekFrom::Start(offset))?;
+        (&file).write_all(&[byte[0] ^ 4])?;
+
+        let mut reader = FileWALReader::<TestEvent>::new_read_only(file.try_clone()?, WALRecovery::Strict)?;
+        assert_eq!(reader.read_next()?, Some(TestEvent(1)));
+        assert_eq!(reader.read_next().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut reader = FileWALReader::<TestEvent>::new(file.try_clone()?, WALRecovery::default())?;
+        assert_eq!(reader.read_next()?, Some(TestEvent(1)));
+        assert_eq!(reader.read_next().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(file.metadata()?.len(), 8 + RECORD_SIZE * 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupted_last_record_is_not_truncated() -> io::Result<()> {
+        let file = tempfile()?;
+        write_events(&file, &[1, 2, 3])?;
+        let offset = 8 + RECORD_SIZE * 2 + FRAME_HEADER_SIZE;
+        (&file).seek(io::SeekFrom::Start(offset))?;
+        (&file).write_all(&[0xff])?;
+
+        let mut reader = FileWALReader::<TestEvent>::new(file.try_clone()?, WALRecovery::default())?;
+        assert_eq!(reader.read_next()?, Some(TestEvent(1)));
+        assert_eq!(reader.read_next()?, Some(TestEvent(2)));
+        assert_eq!(reader.read_next().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(reader.discarded_bytes(), 0);
+        assert_eq!(file.metadata()?.len(), 8 + RECORD_SIZE * 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corruption_before_committed_records_is_not_truncated() -> io::Result<()> {
+        let file = tempfile()?;
+        write_events(&file, &[1, 2, 3])?;
+        // Garble the length of the first record, so it no longer ends where the second starts.
+        (&file).seek(io::SeekFrom::Start(8))?;
+        (&file).write_all(&2u32.to_le_bytes())?;
+
+        let mut reader = FileWALReader::<TestEvent>::new(file.try_clone()?, WALRecovery::default())?;
+        assert_eq!(reader.read_next().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(reader.discarded_bytes(), 0);
+        let mut reader = FileWALReader::<TestEvent>::new(file, WALRecovery::default())?;
+        assert!(read_events(&mut reader).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unframed_log_is_read_until_cleared() -> io::Result<()> {
+        let file = tempfile()?;
+        // A log written before records were framed.
+        (&file).write_all(&16u64.to_le_bytes())?;
+        (&file).write_all(&1u32.to_le_bytes())?;
+        (&file).write_all(&2u32.to_le_bytes())?;
+
+        let wal = FileWAL::<TestEvent>::load(file.try_clone()?)?;
+        wal.record(TestEvent(3))?;
+        wal.sync()?;
+        let mut reader = FileWALReader::<TestEvent>::new(file.try_clone()?, WALRecovery::Strict)?;
+        assert_eq!(read_events(&mut reader)?, vec![1, 2, 3]);
+        assert_eq!(file.metadata()?.len(), 20);
+
+        wal.clear()?;
+        wal.record(TestEvent(4))?;
+        wal.sync()?;
+        let mut reader = FileWALReader::<TestEvent>::new(file, WALRecovery::Strict)?;
+        assert_eq!(read_events(&mut reader)?, vec![4]);
+        assert_eq!(reader.height(), Some(8 + RECORD_SIZE));
+        Ok(())
+    }
+
+    #[test]
+    fn test_torn_height_is_discarded() -> io::Result<()> {
+        let file = tempfile()?;
+        (&file).write_all(&[8, 0, 0])?;
+
+        let mut reader = FileWALReader::<TestEvent>::new(file.try_clone()?, WALRecovery::default())?;
+        assert_eq!(read_events(&mut reader)?, Vec::<u32>::new());
+        assert_eq!(reader.discarded_bytes(), 3);
+        assert_eq!(file.metadata()?.len(), 0);
+        Ok(())
+    }
+}
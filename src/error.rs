@@ -0,0 +1,135 @@
+use std::io;
+
+/// An error of this crate classified by what the caller can do about it, e.g. retry an `Io`
+/// error but not a `Corruption`. The `HashTable` and `HashTableScanner` traits and the tables of
+/// `dbms` return it. Pagers, books, sections and entries return `io::Result`, as they are built on
+/// `Read`, `Write` and `Seek`; convert their errors with `Error::from` where the class matters.
+/// Every variant keeps the original error, which converts back with `io::Error::from`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Stored data does not decode or does not match its checksum.
+    #[error("Corrupt data: {0}")]
+    Corruption(io::Error),
+    /// A store or shard was created with a different config than the one it was opened with.
+    #[error("Config mismatch: {0}")]
+    ConfigMismatch(io::Error),
+    /// A thread panicked while holding a lock of a pager, book or table.
+    #[error("Lock poisoned: {0}")]
+    LockPoisoned(io::Error),
+    /// A quota, the memory budget or the storage ran out.
+    #[error("Capacity exceeded: {0}")]
+    Capacity(io::Error),
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Inner error of the `io::Error`s returned when a thread panicked while holding a lock.
+#[derive(Debug, thiserror::Error)]
+#[error("Lock poisoned")]
+pub struct PoisonedLockError;
+
+impl Error {
+    /// The kind of the original error.
+    pub fn kind(&self) -> io::ErrorKind {
+        self.io_error().kind()
+    }
+
+    /// The inner error of the original error, as with `io::Error::get_ref`.
+    pub fn get_ref(&self) -> Option<&(dyn std::error::Error + Send + Sync + 'static)> {
+        self.io_error().get_ref()
+    }
+
+    fn io_error(&self) -> &io::Error {
+        match self {
+            Error::Corruption(err)
+            | Error::ConfigMismatch(err)
+            | Error::LockPoisoned(err)
+            | Error::Capacity(err)
+            | Error::Io(err) => err,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        if is_config_mismatch(&err) {
+            return Error::ConfigMismatch(err);
+        }
+        if err.get_ref().is_some_and(|inner| inner.is::<PoisonedLockError>()) {
+            return Error::LockPoisoned(err);
+        }
+        match err.kind() {
+            io::ErrorKind::InvalidData => Error::Corruption(err),
+            io::ErrorKind::QuotaExceeded
+            | io::ErrorKind::OutOfMemory
+            | io::ErrorKind::StorageFull
+            | io::ErrorKind::FileTooLarge => Error::Capacity(err),
+            _ => Error::Io(err),
+        }
+    }
+}
+
+#[cfg(feature = "dbms")]
+fn is_config_mismatch(err: &io::Error) -> bool {
+    use crate::dbms::{ManagedHashTableError, ShardedHashTableError};
+
+    let Some(inner) = err.get_ref() else {
+        return false;
+    };
+    matches!(inner.downcast_ref(), Some(ManagedHashTableError::ConfigMismatch { .. }))
+        || matches!(inner.downcast_ref(), Some(ShardedHashTableError::ShardMismatch { .. }))
+}
+
+#[cfg(not(feature = "dbms"))]
+fn is_config_mismatch(_err: &io::Error) -> bool {
+    false
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Corruption(err)
+            | Error::ConfigMismatch(err)
+            | Error::LockPoisoned(err)
+            | Error::Capacity(err)
+            | Error::Io(err) => err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_table::HashTableError;
+
+    #[test]
+    fn test_error_classes() {
+        let corrupt = io::Error::from(HashTableError::ChecksumMismatch { section_index: 0, offset: 8 });
+        assert!(matches!(Error::from(corrupt), Error::Corruption(_)));
+        let poisoned = io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError);
+        assert!(matches!(Error::from(poisoned), Error::LockPoisoned(_)));
+        assert!(matches!(Error::from(io::Error::other(PoisonedLockError)), Error::LockPoisoned(_)));
+        // Only the type counts, not the message.
+        assert!(matches!(Error::from(io::Error::other("Lock poisoned")), Error::Io(_)));
+        let full = io::Error::new(io::ErrorKind::OutOfMemory, "Memory pager is full");
+        assert!(matches!(Error::from(full), Error::Capacity(_)));
+
+        let transient = Error::from(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+        assert!(matches!(transient, Error::Io(_)));
+        assert_eq!(transient.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(io::Error::from(transient).kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[cfg(feature = "dbms")]
+    #[test]
+    fn test_config_mismatch() {
+        use crate::dbms::ManagedHashTableError;
+
+        let err = io::Error::from(ManagedHashTableError::ConfigMismatch { field: "page_size", on_disk: "64".into(), requested: "128".into() });
+        let err = Error::from(err);
+        assert!(matches!(err, Error::ConfigMismatch(_)));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+    }
+}
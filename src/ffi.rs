@@ -0,0 +1,366 @@
+//! C ABI over `ManagedHashTable`, declared in `include/datastore.h`.
+//!
+//! Ownership rules:
+//! - Handles returned by `datastore_open` and `datastore_scan_open` are owned by the caller and
+//!   must be released exactly once with `datastore_close` and `datastore_scan_close`.
+//!   A scan must not be used after its table is closed. Calls modifying the table between
+//!   `datastore_scan_next` calls are allowed; the scan resumes where it stopped.
+//! - Buffers returned through `out_*` pointers are owned by the caller and must be released
+//!   with `datastore_buffer_free`, passing the returned length.
+//! - Input pointers are only borrowed for the duration of the call.
+//! - Handles may be used from any thread, but not from several threads at once.
+
+use std::{cell::RefCell, ffi::{CStr, CString, c_char, c_int}, io, panic::{AssertUnwindSafe, catch_unwind}, ptr, slice};
+
+use crate::{dbms::ManagedHashTable, hash_table::{HashTable, HashTableEntry, HashTableScanFilter, ResumableScanner, ScanCursor}};
+
+pub const DATASTORE_OK: c_int = 0;
+pub const DATASTORE_NOT_FOUND: c_int = 1;
+pub const DATASTORE_END: c_int = 2;
+pub const DATASTORE_INVALID_ARGUMENT: c_int = -1;
+pub const DATASTORE_IO_ERROR: c_int = -2;
+pub const DATASTORE_UNSUPPORTED: c_int = -3;
+pub const DATASTORE_PANIC: c_int = -4;
+
+pub struct DatastoreHashTable {
+    hash_table: ManagedHashTable,
+    /// Bumped by every call taking the table mutably, so open scans drop their scanner, which
+    /// borrows the table, instead of reading through it again.
+    generation: u64,
+}
+
+pub struct DatastoreScan {
+    table: *mut DatastoreHashTable,
+    cursor: ScanCursor,
+    /// The live scanner and the generation of the table it was created at. It is only recreated
+    /// from `cursor` after the table was modified, as recreating it may rescan whole sections.
+    scanner: Option<(u64, Box<dyn EntryScanner>)>,
+}
+
+/// `ResumableScanner` returning owned entries, so that it can be boxed.
+trait EntryScanner {
+    fn next_entry(&mut self) -> io::Result<Option<(Vec<u8>, Vec<u8>)>>;
+    fn cursor(&self) -> ScanCursor;
+}
+
+impl<S: ResumableScanner> EntryScanner for S {
+    fn next_entry(&mut self) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let Some(mut entry) = self.next()? else {
+            return Ok(None);
+        };
+        Ok(Some((entry.read_key_to_vec()?, entry.read_value_to_vec()?)))
+    }
+
+    fn cursor(&self) -> ScanCursor {
+        ResumableScanner::cursor(self)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', " ")).ok();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+}
+
+/// Runs `f`, turning errors and panics into status codes and recording their message.
+fn guard(f: impl FnOnce() -> io::Result<c_int>) -> c_int {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(status)) => status,
+        Ok(Err(err)) => {
+            let status = match err.kind() {
+                io::ErrorKind::InvalidInput => DATASTORE_INVALID_ARGUMENT,
+                io::ErrorKind::Unsupported => DATASTORE_UNSUPPORTED,
+                _ => DATASTORE_IO_ERROR,
+            };
+            set_last_error(err);
+            status
+        },
+        Err(_) => {
+            set_last_error("Panicked");
+            DATASTORE_PANIC
+        },
+    }
+}
+
+fn invalid_argument(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+/// # Safety
+/// `data` must be null with `len` zero, or point to `len` readable bytes.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> io::Result<&'a [u8]> {
+    if data.is_null() {
+        if len != 0 {
+            return Err(invalid_argument("Null buffer with non-zero length"));
+        }
+        return Ok(&[]);
+    }
+    Ok(unsafe { slice::from_raw_parts(data, len) })
+}
+
+/// # Safety
+/// `table` must be null or a live handle returned by `datastore_open`.
+unsafe fn table<'a>(table: *mut DatastoreHashTable) -> io::Result<&'a mut DatastoreHashTable> {
+    unsafe { table.as_mut() }.ok_or_else(|| invalid_argument("Null table handle"))
+}
+
+/// # Safety
+/// `out_data` and `out_len` must be valid for writes.
+unsafe fn write_buffer(buffer: Vec<u8>, out_data: *mut *mut u8, out_len: *mut usize) {
+    let buffer = buffer.into_boxed_slice();
+    unsafe {
+        *out_len = buffer.len();
+        *out_data = Box::into_raw(buffer) as *mut u8;
+    }
+}
+
+/// Message of the last failed call on this thread, or null. Valid until the next call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn datastore_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Opens the store in `path`, creating it with the default config if it does not exist.
+///
+/// # Safety
+/// `path` must be a NUL-terminated string and `out_table` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_open(path: *const c_char, out_table: *mut *mut DatastoreHashTable) -> c_int {
+    guard(|| {
+        if path.is_null() || out_table.is_null() {
+            return Err(invalid_argument("Null argument"));
+        }
+        let path = unsafe { CStr::from_ptr(path) }.to_str().map_err(|_| invalid_argument("Path is not UTF-8"))?;
+        let hash_table = ManagedHashTable::builder(path).open()?;
+        unsafe { *out_table = Box::into_raw(Box::new(DatastoreHashTable { hash_table, generation: 0 })) };
+        Ok(DATASTORE_OK)
+    })
+}
+
+/// Closes the store. Unsynced inserts are lost.
+///
+/// # Safety
+/// `table` must be null or a handle returned by `datastore_open` that was not closed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_close(table: *mut DatastoreHashTable) {
+    if !table.is_null() {
+        drop(unsafe { Box::from_raw(table) });
+    }
+}
+
+/// # Safety
+/// See the module documentation; `key` and `value` must point to `key_len` and `value_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_put(
+    table: *mut DatastoreHashTable,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> c_int {
+    guard(|| {
+        let table = unsafe { self::table(table) }?;
+        let (key, value) = unsafe { (bytes(key, key_len)?, bytes(value, value_len)?) };
+        table.generation += 1;
+        table.hash_table.insert(key, value)?;
+        Ok(DATASTORE_OK)
+    })
+}
+
+/// Finds the value inserted last for `key`. Returns `DATASTORE_NOT_FOUND` if there is none.
+///
+/// # Safety
+/// See the module documentation; `out_value` and `out_value_len` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_get(
+    table: *mut DatastoreHashTable,
+    key: *const u8,
+    key_len: usize,
+    out_value: *mut *mut u8,
+    out_value_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let table = unsafe { self::table(table) }?;
+        let key = unsafe { bytes(key, key_len) }?;
+        if out_value.is_null() || out_value_len.is_null() {
+            return Err(invalid_argument("Null output pointer"));
+        }
+        let Some(value) = table.hash_table.get(key)? else {
+            return Ok(DATASTORE_NOT_FOUND);
+        };
+        unsafe { write_buffer(value, out_value, out_value_len) };
+        Ok(DATASTORE_OK)
+    })
+}
+
+/// Entries cannot be deleted from a `ManagedHashTable`; always returns `DATASTORE_UNSUPPORTED`.
+///
+/// # Safety
+/// See the module documentation.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_delete(table: *mut DatastoreHashTable, key: *const u8, key_len: usize) -> c_int {
+    guard(|| {
+        unsafe { self::table(table)?; bytes(key, key_len)?; }
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Deleting entries is not supported"))
+    })
+}
+
+/// Makes all inserts durable.
+///
+/// # Safety
+/// See the module documentation.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_sync(table: *mut DatastoreHashTable) -> c_int {
+    guard(|| {
+        let table = unsafe { self::table(table) }?;
+        table.generation += 1;
+        table.hash_table.sync()?;
+        Ok(DATASTORE_OK)
+    })
+}
+
+/// Starts a scan over all entries.
+///
+/// # Safety
+/// See the module documentation; `out_scan` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_scan_open(table: *mut DatastoreHashTable, out_scan: *mut *mut DatastoreScan) -> c_int {
+    guard(|| {
+        unsafe { self::table(table) }?;
+        if out_scan.is_null() {
+            return Err(invalid_argument("Null output pointer"));
+        }
+        let scan = DatastoreScan {
+            table,
+            cursor: ScanCursor::default(),
+            scanner: None,
+        };
+        unsafe { *out_scan = Box::into_raw(Box::new(scan)) };
+        Ok(DATASTORE_OK)
+    })
+}
+
+/// Returns the next entry, or `DATASTORE_END` once all entries were returned.
+///
+/// # Safety
+/// See the module documentation; all output pointers must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_scan_next(
+    scan: *mut DatastoreScan,
+    out_key: *mut *mut u8,
+    out_key_len: *mut usize,
+    out_value: *mut *mut u8,
+    out_value_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let scan = unsafe { scan.as_mut() }.ok_or_else(|| invalid_argument("Null scan handle"))?;
+        if out_key.is_null() || out_key_len.is_null() || out_value.is_null() || out_value_len.is_null() {
+            return Err(invalid_argument("Null output pointer"));
+        }
+        // The table outlives the scan, and the scanner is not used once the table was modified,
+        // so the borrow may be extended to the scan's lifetime.
+        let table: &'static DatastoreHashTable = unsafe { scan.table.as_ref() }.ok_or_else(|| invalid_argument("Null table handle"))?;
+        if scan.scanner.as_ref().is_some_and(|(generation, _)| *generation != table.generation) {
+            scan.scanner = None;
+        }
+        let (_, scanner) = match &mut scan.scanner {
+            Some(scanner) => scanner,
+            None => {
+                let scanner = table.hash_table.scan_from(HashTableScanFilter::All, scan.cursor)?;
+                scan.scanner.insert((table.generation, Box::new(scanner)))
+            },
+        };
+        let entry = scanner.next_entry()?;
+        scan.cursor = scanner.cursor();
+        let Some((key, value)) = entry else {
+            return Ok(DATASTORE_END);
+        };
+        unsafe {
+            write_buffer(key, out_key, out_key_len);
+            write_buffer(value, out_value, out_value_len);
+        }
+        Ok(DATASTORE_OK)
+    })
+}
+
+/// # Safety
+/// `scan` must be null or a handle returned by `datastore_scan_open` that was not closed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_scan_close(scan: *mut DatastoreScan) {
+    if !scan.is_null() {
+        drop(unsafe { Box::from_raw(scan) });
+    }
+}
+
+/// # Safety
+/// `data` must be null or a buffer returned by this library with its length `len`, not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn datastore_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn take_buffer(data: *mut u8, len: usize) -> Vec<u8> {
+        let buffer = unsafe { slice::from_raw_parts(data, len) }.to_vec();
+        unsafe { datastore_buffer_free(data, len) };
+        buffer
+    }
+
+    #[test]
+    fn test_put_get_scan() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = CString::new(dir.path().to_str().unwrap()).unwrap();
+        unsafe {
+            let mut table = ptr::null_mut();
+            assert_eq!(datastore_open(path.as_ptr(), &mut table), DATASTORE_OK);
+            assert_eq!(datastore_put(table, b"foo".as_ptr(), 3, b"bar".as_ptr(), 3), DATASTORE_OK);
+            assert_eq!(datastore_put(table, b"foo".as_ptr(), 3, b"baz".as_ptr(), 3), DATASTORE_OK);
+            assert_eq!(datastore_sync(table), DATASTORE_OK);
+
+            let (mut value, mut value_len) = (ptr::null_mut(), 0);
+            assert_eq!(datastore_get(table, b"foo".as_ptr(), 3, &mut value, &mut value_len), DATASTORE_OK);
+            assert_eq!(take_buffer(value, value_len), b"baz");
+            assert_eq!(datastore_get(table, b"bar".as_ptr(), 3, &mut value, &mut value_len), DATASTORE_NOT_FOUND);
+
+            assert_eq!(datastore_delete(table, b"foo".as_ptr(), 3), DATASTORE_UNSUPPORTED);
+            assert!(!datastore_last_error_message().is_null());
+            assert_eq!(datastore_put(table, ptr::null(), 1, ptr::null(), 0), DATASTORE_INVALID_ARGUMENT);
+
+            let mut scan = ptr::null_mut();
+            assert_eq!(datastore_scan_open(table, &mut scan), DATASTORE_OK);
+            let mut values = Vec::new();
+            let (mut key, mut key_len) = (ptr::null_mut(), 0);
+            while datastore_scan_next(scan, &mut key, &mut key_len, &mut value, &mut value_len) == DATASTORE_OK {
+                assert_eq!(take_buffer(key, key_len), b"foo");
+                values.push(take_buffer(value, value_len));
+            }
+            assert_eq!(values, [b"bar", b"baz"]);
+            datastore_scan_close(scan);
+
+            // Inserts between calls drop the live scanner, which resumes from its cursor.
+            assert_eq!(datastore_put(table, b"a".as_ptr(), 1, b"1".as_ptr(), 1), DATASTORE_OK);
+            assert_eq!(datastore_scan_open(table, &mut scan), DATASTORE_OK);
+            let mut count = 0;
+            while datastore_scan_next(scan, &mut key, &mut key_len, &mut value, &mut value_len) == DATASTORE_OK {
+                take_buffer(key, key_len);
+                take_buffer(value, value_len);
+                if count == 0 {
+                    assert_eq!(datastore_put(table, b"b".as_ptr(), 1, b"2".as_ptr(), 1), DATASTORE_OK);
+                }
+                count += 1;
+            }
+            assert!((3..=4).contains(&count));
+            datastore_scan_close(scan);
+            datastore_close(table);
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,50 @@
 use std::io::{self, Read};
 
+use crate::book::SectionIndex;
+
 pub mod book;
+pub mod dump;
+pub mod memory;
+pub mod metrics;
 pub mod prefix_hasher;
+pub mod fnv_hasher;
+pub mod hasher_kind;
+pub mod xxhash;
+
+pub use memory::MemoryHashTable;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HashTableError {
+    #[error("Key size {size} exceeds the limit of {limit} bytes")]
+    KeyTooLarge { size: usize, limit: u32 },
+    #[error("Value size {size} exceeds the limit of {limit} bytes")]
+    ValueTooLarge { size: usize, limit: u32 },
+    #[error("Entry at offset {offset} overflows the section address space")]
+    EntryOffsetOverflow { offset: u64 },
+    #[error("Entry at offset {offset} with key size {key_size} and value size {value_size} exceeds section end {section_end}")]
+    EntryOutOfBounds { offset: u64, key_size: u32, value_size: u32, section_end: u64 },
+    #[error("Entry at offset {offset} of section {section_index} does not match its checksum")]
+    ChecksumMismatch { section_index: SectionIndex, offset: u64 },
+}
+
+impl From<HashTableError> for io::Error {
+    fn from(err: HashTableError) -> Self {
+        let kind = match err {
+            HashTableError::KeyTooLarge { .. }
+            | HashTableError::ValueTooLarge { .. }
+            | HashTableError::EntryOffsetOverflow { .. } => io::ErrorKind::InvalidInput,
+            HashTableError::EntryOutOfBounds { .. }
+            | HashTableError::ChecksumMismatch { .. } => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+impl From<HashTableError> for crate::Error {
+    fn from(err: HashTableError) -> Self {
+        io::Error::from(err).into()
+    }
+}
 
 pub enum HashTableScanFilter<'key> {
     Key(&'key [u8]),
@@ -9,8 +52,18 @@ pub enum HashTableScanFilter<'key> {
 }
 
 pub trait HashTable {
-    fn insert(&mut self, key: &[u8], value: &[u8]) -> io::Result<()>;
-    fn scan<'a>(&'a self, filter: HashTableScanFilter<'a>) -> io::Result<impl HashTableScanner + 'a>;
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> crate::Result<()>;
+    fn scan<'a>(&'a self, filter: HashTableScanFilter<'a>) -> crate::Result<impl HashTableScanner + 'a>;
+
+    /// The value inserted last for `key`, `None` if there is none.
+    fn get(&self, key: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+        let mut scanner = self.scan(HashTableScanFilter::Key(key))?;
+        let mut latest = None;
+        while let Some(entry) = scanner.next()? {
+            latest = Some(entry);
+        }
+        Ok(latest.map(|mut entry| entry.read_value_to_vec()).transpose()?)
+    }
 }
 
 pub type Hash = u32;
@@ -33,13 +86,86 @@ impl<H: SliceHasherBuilder> SliceHasherBuilder for &H {
     }
 }
 
+/// Information recorded alongside an entry when it was inserted, if the table records it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntryMetadata {
+    /// Position of the insert among all inserts into the table, starting at zero.
+    pub sequence: Option<u64>,
+    /// Wall-clock time of the insert in microseconds since the Unix epoch, if the target has a clock.
+    pub timestamp_micros: Option<u64>,
+}
+
 pub trait HashTableEntry {
     fn key_size(&self) -> u32;
     fn value_size(&self) -> u32;
     fn key(&mut self) -> io::Result<impl Read + '_>;
     fn value(&mut self) -> io::Result<impl Read + '_>;
+
+    fn metadata(&mut self) -> io::Result<EntryMetadata> {
+        Ok(EntryMetadata::default())
+    }
+
+    /// Checks the entry against its stored checksum, if the table keeps them. Reading the value to
+    /// its end checks it as well, but entries whose value is not read completely are only checked
+    /// by this.
+    fn verify(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reads the whole key into `buffer`, replacing its contents.
+    fn read_key_into(&mut self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        buffer.clear();
+        buffer.reserve(self.key_size() as usize);
+        self.key()?.read_to_end(buffer)?;
+        Ok(())
+    }
+
+    /// Reads the whole value into `buffer`, replacing its contents.
+    fn read_value_into(&mut self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        buffer.clear();
+        buffer.reserve(self.value_size() as usize);
+        self.value()?.read_to_end(buffer)?;
+        Ok(())
+    }
+
+    fn read_key_to_vec(&mut self) -> io::Result<Vec<u8>> {
+        let mut key = Vec::new();
+        self.read_key_into(&mut key)?;
+        Ok(key)
+    }
+
+    fn read_value_to_vec(&mut self) -> io::Result<Vec<u8>> {
+        let mut value = Vec::new();
+        self.read_value_into(&mut value)?;
+        Ok(value)
+    }
 }
 
 pub trait HashTableScanner {
-    fn next(&mut self) -> io::Result<Option<impl HashTableEntry + use<Self>>>;
+    fn next(&mut self) -> crate::Result<Option<impl HashTableEntry + use<Self>>>;
+}
+
+/// The position of a scan between two entries, from which it can be resumed, e.g. after a restart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanCursor {
+    pub section_index: SectionIndex,
+    pub offset: u64,
+    /// End of the section when the scan reached it. Entries appended to the section later are not
+    /// returned when the scan is resumed; sections after it are scanned up to their end as usual.
+    pub section_end: Option<u64>,
+}
+
+/// The end of every section when it was taken, see [`book::BookHashTable::snapshot`]. Scans over
+/// it skip the entries inserted afterwards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashTableSnapshot {
+    section_ends: Vec<u64>,
+}
+
+pub trait ResumableScanner: HashTableScanner {
+    /// The cursor right after the last entry returned by `next`.
+    fn cursor(&self) -> ScanCursor;
 }
@@ -1,6 +1,8 @@
-use std::{cmp::Ordering, io::{self, Read, Seek, SeekFrom, Write}, mem::replace};
+use std::{cmp::Ordering, collections::{BTreeMap, BTreeSet}, io::{self, IoSlice, Read, Seek, SeekFrom, Write}, mem::replace, ops::{Bound, Range}, sync::Arc};
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{book::{Book, SectionIndex}, hash_table::{HashTable, HashTableEntry, HashTableScanner, SliceHasher, SliceHasherBuilder}};
+use crate::{book::{Book, SectionIndex, write_all_vectored}, crc32::Crc32, hash_table::{EntryMetadata, HashTable, HashTableEntry, HashTableError, HashTableScanner, HashTableSnapshot, ResumableScanner, ScanCursor, SliceHasher, SliceHasherBuilder, metrics::HashTableMetrics}};
 
 use super::HashTableScanFilter;
 
@@ -12,6 +14,15 @@ pub struct SectionHeader {
 pub trait SectionRegistry {
     fn resolve_section(&self, section_index: SectionIndex) -> io::Result<SectionHeader>;
     fn update_section_end_offset(&mut self, section_index: SectionIndex, end_offset: u64) -> io::Result<()>;
+
+    /// Indexes of the sections in `sections` with an end offset past zero, in order. Resolves
+    /// every section unless overridden.
+    fn iter_non_empty(&self, sections: Range<SectionIndex>) -> impl Iterator<Item = io::Result<SectionIndex>> + '_ {
+        sections.filter_map(|section_index| match self.resolve_section(section_index) {
+            Ok(header) => (header.end_offset > 0).then_some(Ok(section_index)),
+            Err(err) => Some(Err(err)),
+        })
+    }
 }
 
 pub type IndexChunk = u32;
@@ -23,16 +34,351 @@ pub struct IndexKey {
     pub index_chunk: IndexChunk,
 }
 
+/// Most 64-bit words a `BloomFilter` can hold.
+pub const MAX_BLOOM_WORDS: usize = 8;
+
+/// The bloom filter of an index chunk or section, or the bits probed for one key. Words past
+/// `BloomConfig::words` stay zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BloomFilter(pub [u64; MAX_BLOOM_WORDS]);
+
+impl BloomFilter {
+    fn set(&mut self, bit: u32) {
+        self.0[bit as usize / 64] |= 1 << (bit % 64);
+    }
+
+    pub fn union(&self, other: &BloomFilter) -> BloomFilter {
+        BloomFilter(std::array::from_fn(|word| self.0[word] | other.0[word]))
+    }
+
+    /// Whether all bits of `query` are set, i.e. the key probed for may have been added.
+    pub fn may_contain(&self, query: &BloomFilter) -> bool {
+        self.0.iter().zip(&query.0).all(|(word, query_word)| word & query_word == *query_word)
+    }
+
+    pub fn count_ones(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+}
+
+/// Size of the bloom filters and how many of their bits each key sets, part of the on-disk format.
+/// More probes cut false positives as long as chunks hold few keys for the width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BloomConfig {
+    /// Width of each filter, a multiple of 64 up to `64 * MAX_BLOOM_WORDS`.
+    pub bits: u16,
+    pub probes: u8,
+}
+
+impl Default for BloomConfig {
+    fn default() -> Self {
+        Self { bits: 64, probes: 1 }
+    }
+}
+
+impl BloomConfig {
+    /// Number of 64-bit words used of each `BloomFilter`.
+    pub fn words(&self) -> usize {
+        self.bits as usize / 64
+    }
+
+    /// Why the config cannot be used, if it cannot.
+    pub fn invalid_reason(&self) -> Option<&'static str> {
+        if self.bits == 0 || !self.bits.is_multiple_of(64) || self.words() > MAX_BLOOM_WORDS {
+            return Some("bits must be a multiple of 64 from 64 to 512");
+        }
+        if self.probes == 0 {
+            return Some("probes must be greater than zero");
+        }
+        None
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct IndexHeader {
-    pub bloom_filter: u64,
+    pub bloom_filter: BloomFilter,
     pub first_entry_offset: u64,
 }
 
 pub trait IndexRegistry {
     fn try_resolve_index(&self, index_key: &IndexKey) -> io::Result<Option<IndexHeader>>;
     fn try_resolve_next_index(&self, index_key: &IndexKey) -> io::Result<Option<IndexHeader>>;
-    fn update_index_bloom_filter(&mut self, index_key: &IndexKey, entry_offset: u64, bloom_bit: u64) -> io::Result<()>;
+    fn update_index_bloom_filter(&mut self, index_key: &IndexKey, entry_offset: u64, bloom_bits: &BloomFilter) -> io::Result<()>;
+
+    /// Union of the bloom filters of the section's chunks, letting a keyed scan skip the section
+    /// altogether. `None` if unknown.
+    fn section_bloom_filter(&self, _section_index: SectionIndex) -> io::Result<Option<BloomFilter>> {
+        Ok(None)
+    }
+}
+
+/// Section headers kept in memory only, indexed by section; create it with `section_count` default headers.
+pub type MemorySectionRegistry = Vec<SectionHeader>;
+
+impl SectionRegistry for MemorySectionRegistry {
+    fn resolve_section(&self, section_index: SectionIndex) -> io::Result<SectionHeader> {
+        self.get(section_index as usize)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Section not found"))
+    }
+
+    fn update_section_end_offset(&mut self, section_index: SectionIndex, end_offset: u64) -> io::Result<()> {
+        let header = self.get_mut(section_index as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Section not found"))?;
+        header.end_offset = header.end_offset.max(end_offset);
+        Ok(())
+    }
+}
+
+/// Index headers kept in memory only.
+pub type MemoryIndexRegistry = BTreeMap<IndexKey, IndexHeader>;
+
+impl IndexRegistry for MemoryIndexRegistry {
+    fn try_resolve_index(&self, index_key: &IndexKey) -> io::Result<Option<IndexHeader>> {
+        Ok(self.get(index_key).copied())
+    }
+
+    fn try_resolve_next_index(&self, index_key: &IndexKey) -> io::Result<Option<IndexHeader>> {
+        let next_section = IndexKey {
+            section_index: index_key.section_index + 1,
+            index_chunk: 0,
+        };
+        Ok(self.range((Bound::Excluded(*index_key), Bound::Excluded(next_section))).next().map(|(_, header)| *header))
+    }
+
+    fn update_index_bloom_filter(&mut self, index_key: &IndexKey, entry_offset: u64, bloom_bits: &BloomFilter) -> io::Result<()> {
+        let header = self.entry(*index_key)
+            .or_insert(IndexHeader {
+                bloom_filter: BloomFilter::default(),
+                first_entry_offset: entry_offset,
+            });
+        header.bloom_filter = header.bloom_filter.union(bloom_bits);
+        Ok(())
+    }
+
+    fn section_bloom_filter(&self, section_index: SectionIndex) -> io::Result<Option<BloomFilter>> {
+        let section = IndexKey { section_index, index_chunk: 0 }..=IndexKey { section_index, index_chunk: IndexChunk::MAX };
+        Ok(Some(self.range(section).fold(BloomFilter::default(), |filter, (_, header)| filter.union(&header.bloom_filter))))
+    }
+}
+
+const ENTRY_HEADER_SIZE: u64 = 8;
+
+/// A byte range of a section that could not be decoded and was skipped by a recovering scan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CorruptRange {
+    pub section_index: SectionIndex,
+    pub start_offset: u64,
+    pub end_offset: u64,
+}
+
+/// The metadata stored after the value of every entry, as part of the on-disk format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum EntryMetadataFormat {
+    #[default]
+    None,
+    /// A u64 LE sequence number.
+    Sequence,
+    /// A u64 LE sequence number followed by a u64 LE timestamp in microseconds since the Unix epoch,
+    /// zero if the target has no clock.
+    SequenceAndTimestamp,
+}
+
+impl EntryMetadataFormat {
+    pub fn trailer_size(self) -> u64 {
+        match self {
+            EntryMetadataFormat::None => 0,
+            EntryMetadataFormat::Sequence => 8,
+            EntryMetadataFormat::SequenceAndTimestamp => 16,
+        }
+    }
+
+    fn write(self, writer: &mut impl Write, metadata: &EntryMetadata) -> io::Result<()> {
+        if self == EntryMetadataFormat::None {
+            return Ok(());
+        }
+        writer.write_all(&metadata.sequence.unwrap_or(0).to_le_bytes())?;
+        if self == EntryMetadataFormat::SequenceAndTimestamp {
+            writer.write_all(&metadata.timestamp_micros.unwrap_or(0).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read(self, reader: &mut impl Read) -> io::Result<EntryMetadata> {
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut buffer = [0u8; 8];
+            reader.read_exact(&mut buffer)?;
+            Ok(u64::from_le_bytes(buffer))
+        };
+        Ok(match self {
+            EntryMetadataFormat::None => EntryMetadata::default(),
+            EntryMetadataFormat::Sequence => EntryMetadata {
+                sequence: Some(read_u64()?),
+                timestamp_micros: None,
+            },
+            EntryMetadataFormat::SequenceAndTimestamp => EntryMetadata {
+                sequence: Some(read_u64()?),
+                timestamp_micros: Some(read_u64()?).filter(|timestamp| *timestamp != 0),
+            },
+        })
+    }
+}
+
+/// The checksum stored between the value and the metadata of every entry, as part of the on-disk
+/// format. It is verified once the value was read to its end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum EntryChecksum {
+    #[default]
+    None,
+    /// A u32 LE CRC-32 of the key followed by the value.
+    Crc32,
+}
+
+impl EntryChecksum {
+    pub fn size(self) -> u64 {
+        match self {
+            EntryChecksum::None => 0,
+            EntryChecksum::Crc32 => 4,
+        }
+    }
+}
+
+fn read_checksum(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+/// Hands out the value of an entry, checking it against the stored checksum once read to its end.
+struct VerifyingReader<R> {
+    value: io::Take<R>,
+    /// CRC-32 of the key and the value read so far, `None` without a checksum or once checked.
+    crc: Option<Crc32>,
+    section_index: SectionIndex,
+    offset: u64,
+}
+
+impl<R: Read> VerifyingReader<R> {
+    /// Reads the rest of the value, checking the entry unless that was done already.
+    fn finish(mut self) -> io::Result<()> {
+        io::copy(&mut self, &mut io::sink())?;
+        self.check()
+    }
+
+    /// Compares the checksum once the value was read to its end.
+    fn check(&mut self) -> io::Result<()> {
+        let Some(crc) = self.crc.take() else {
+            return Ok(());
+        };
+        if read_checksum(self.value.get_mut())? != crc.finalize() {
+            return Err(HashTableError::ChecksumMismatch { section_index: self.section_index, offset: self.offset }.into());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_size = self.value.read(buf)?;
+        let Some(crc) = &mut self.crc else {
+            return Ok(read_size);
+        };
+        crc.update(&buf[..read_size]);
+        if self.value.limit() == 0 {
+            self.check()?;
+        }
+        Ok(read_size)
+    }
+}
+
+/// Totals over the entries of a `BookHashTable`, see `BookHashTable::entry_stats`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntryStats {
+    pub entry_count: u64,
+    pub key_bytes: u64,
+    pub value_bytes: u64,
+    /// Bytes used by each section, including entry headers and metadata.
+    pub section_sizes: Vec<u64>,
+}
+
+/// Which of the entries inserted for the same key scans return.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DuplicateKeys {
+    /// All of them, in the order of inserts.
+    #[default]
+    KeepAll,
+    /// Only the one inserted last; older entries stay stored but are shadowed. Scans read the keys
+    /// of a section before returning its entries, and `HashTableScanFilter::All` keeps the
+    /// distinct keys of one section in memory for that.
+    LatestWins,
+}
+
+/// Microseconds since the Unix epoch, or zero where no clock is available.
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64)
+}
+
+// `SystemTime::now` panics on wasm32-unknown-unknown.
+#[cfg(all(target_family = "wasm", target_os = "unknown"))]
+fn now_micros() -> u64 {
+    0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntrySizeLimits {
+    pub max_key_size: u32,
+    pub max_value_size: u32,
+}
+
+impl Default for EntrySizeLimits {
+    fn default() -> Self {
+        Self {
+            max_key_size: u32::MAX,
+            max_value_size: u32::MAX,
+        }
+    }
+}
+
+impl EntrySizeLimits {
+    pub fn check(&self, key: &[u8], value: &[u8]) -> Result<(u32, u32), HashTableError> {
+        let key_size = u32::try_from(key.len())
+            .ok()
+            .filter(|size| *size <= self.max_key_size)
+            .ok_or(HashTableError::KeyTooLarge { size: key.len(), limit: self.max_key_size })?;
+        let value_size = u32::try_from(value.len())
+            .ok()
+            .filter(|size| *size <= self.max_value_size)
+            .ok_or(HashTableError::ValueTooLarge { size: value.len(), limit: self.max_value_size })?;
+        Ok((key_size, value_size))
+    }
+}
+
+fn entry_end_offset(offset: u64, key_size: u32, value_size: u32, metadata_format: EntryMetadataFormat, checksum: EntryChecksum) -> Option<u64> {
+    offset
+        .checked_add(ENTRY_HEADER_SIZE)?
+        .checked_add(key_size as u64)?
+        .checked_add(value_size as u64)?
+        .checked_add(checksum.size())?
+        .checked_add(metadata_format.trailer_size())
+}
+
+/// The index chunk containing `offset`, failing if the chunk number does not fit an `IndexChunk`.
+fn index_chunk_of(offset: u64, index_chunk_size: IndexChunkSize) -> Result<IndexChunk, HashTableError> {
+    IndexChunk::try_from(offset / index_chunk_size as u64)
+        .map_err(|_| HashTableError::EntryOffsetOverflow { offset })
 }
 
 pub struct BookHashTable<H, B, SR, IR> {
@@ -42,6 +388,13 @@ pub struct BookHashTable<H, B, SR, IR> {
     section_registry: SR,
     index_chunk_size: IndexChunkSize,
     index_registry: IR,
+    limits: EntrySizeLimits,
+    metadata_format: EntryMetadataFormat,
+    checksum: EntryChecksum,
+    duplicate_keys: DuplicateKeys,
+    bloom: BloomConfig,
+    next_sequence: u64,
+    metrics: Option<Arc<dyn HashTableMetrics>>,
 }
 
 impl<H: SliceHasherBuilder, B: Book, SR: SectionRegistry, IR: IndexRegistry> BookHashTable<H, B, SR, IR> {
@@ -60,163 +413,549 @@ impl<H: SliceHasherBuilder, B: Book, SR: SectionRegistry, IR: IndexRegistry> Boo
             section_registry,
             index_chunk_size,
             index_registry,
+            limits: EntrySizeLimits::default(),
+            metadata_format: EntryMetadataFormat::None,
+            checksum: EntryChecksum::None,
+            duplicate_keys: DuplicateKeys::KeepAll,
+            bloom: BloomConfig::default(),
+            next_sequence: 0,
+            metrics: None,
         }
     }
 
-    pub fn book(&mut self) -> &mut B {
-        &mut self.book
+    pub fn with_metrics(mut self, metrics: Arc<dyn HashTableMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
     }
 
-    pub fn section_registry(&mut self) -> &mut SR {
-        &mut self.section_registry
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn HashTableMetrics>>) {
+        self.metrics = metrics;
     }
 
-    pub fn index_registry(&mut self) -> &mut IR {
-        &mut self.index_registry
+    pub fn metrics(&self) -> Option<&Arc<dyn HashTableMetrics>> {
+        self.metrics.as_ref()
     }
-}
 
-impl<H: SliceHasherBuilder, B: Book, SR: SectionRegistry, IR: IndexRegistry> HashTable for BookHashTable<H, B, SR, IR> {
-    fn insert(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+    pub fn with_limits(mut self, limits: EntrySizeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> &EntrySizeLimits {
+        &self.limits
+    }
+
+    /// Sets the metadata stored with every entry. Must match the format the entries
+    /// already in the book were written with; call `recover_sequence` afterwards.
+    pub fn with_metadata_format(mut self, metadata_format: EntryMetadataFormat) -> Self {
+        self.metadata_format = metadata_format;
+        self
+    }
+
+    pub fn metadata_format(&self) -> EntryMetadataFormat {
+        self.metadata_format
+    }
+
+    /// Sets the checksum stored with every entry. Must match the format the entries already in
+    /// the book were written with.
+    pub fn with_checksum(mut self, checksum: EntryChecksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub fn checksum(&self) -> EntryChecksum {
+        self.checksum
+    }
+
+    pub fn with_duplicate_keys(mut self, duplicate_keys: DuplicateKeys) -> Self {
+        self.duplicate_keys = duplicate_keys;
+        self
+    }
+
+    pub fn duplicate_keys(&self) -> DuplicateKeys {
+        self.duplicate_keys
+    }
+
+    /// Sets the shape of the bloom filters. Must match the one the index registry was built with;
+    /// `build_index_registry` regenerates it for another.
+    pub fn with_bloom(mut self, bloom: BloomConfig) -> Self {
+        self.bloom = bloom;
+        self
+    }
+
+    pub fn bloom(&self) -> BloomConfig {
+        self.bloom
+    }
+
+    /// The sequence number the next insert will be stamped with.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Continues the sequence after the highest one stored, which is found in the last entry of each section.
+    pub fn recover_sequence(&mut self) -> io::Result<()> {
+        if self.metadata_format == EntryMetadataFormat::None {
+            return Ok(());
+        }
+        let trailer_size = self.metadata_format.trailer_size();
+        let mut next_sequence = 0;
+        for section_index in 0..self.section_count {
+            let SectionHeader { end_offset } = self.section_registry.resolve_section(section_index)?;
+            if end_offset == 0 {
+                continue;
+            }
+            let trailer_offset = end_offset.checked_sub(trailer_size)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Section is too short for entry metadata"))?;
+            let mut section = self.book.section(section_index);
+            section.seek(SeekFrom::Start(trailer_offset))?;
+            if let Some(sequence) = self.metadata_format.read(&mut section)?.sequence {
+                let after = sequence.checked_add(1)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Stored sequence number is out of range"))?;
+                next_sequence = next_sequence.max(after);
+            }
+        }
+        self.next_sequence = next_sequence;
+        Ok(())
+    }
+
+    /// Offsets in its section between which the entry would be stored if inserted now.
+    pub fn entry_range(&self, key: &[u8], value: &[u8]) -> io::Result<(u64, u64)> {
+        let (key_size, value_size) = self.limits.check(key, value)?;
+
+        let mut hasher = self.hasher_builder.build();
+        hasher.update(key);
+        let section_index = hasher.finalize() % self.section_count;
+
+        let entry_offset = self.section_registry.resolve_section(section_index)?.end_offset;
+        let entry_end = entry_end_offset(entry_offset, key_size, value_size, self.metadata_format, self.checksum)
+            .ok_or(HashTableError::EntryOffsetOverflow { offset: entry_offset })?;
+        Ok((entry_offset, entry_end))
+    }
+
+    /// The section of `key` and its bits in the bloom filters of the section's chunks.
+    fn key_position(&self, key: &[u8]) -> (SectionIndex, BloomFilter) {
         let mut hasher = self.hasher_builder.build();
         hasher.update(key);
         let hash = hasher.finalize();
+        // Double hashing: the probes step through the filter from the bits left by the section
+        // index, by a stride taken from a remix of the hash.
+        let start = hash / self.section_count;
+        let stride = (hash ^ (hash >> 16)).wrapping_mul(0x85ebca6b).rotate_left(13) | 1;
+        let mut bloom_bits = BloomFilter::default();
+        for probe in 0..self.bloom.probes as u32 {
+            bloom_bits.set(start.wrapping_add(probe.wrapping_mul(stride)) % self.bloom.bits as u32);
+        }
+        (hash % self.section_count, bloom_bits)
+    }
 
-        let section_index = hash % self.section_count;
-        let bloom_index = (hash / self.section_count) as u64 % 64;
-        let bloom_bit = 1u64 << bloom_index;
+    /// Calls `visit` with the offset, key size and value size of every entry of the section, in
+    /// order, and the section positioned at the key.
+    fn walk_section(
+        &self,
+        section_index: SectionIndex,
+        mut visit: impl FnMut(u64, u32, u32, &mut B::Section<'_>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let section_end = self.section_registry.resolve_section(section_index)?.end_offset;
+        let mut section = self.book.section(section_index);
+        section.seek(SeekFrom::Start(0))?;
+        let mut entry_offset = 0;
+        while entry_offset < section_end {
+            let mut size_buf = [0u8; 4];
+            section.read_exact(&mut size_buf)?;
+            let key_size = u32::from_le_bytes(size_buf);
+            section.read_exact(&mut size_buf)?;
+            let value_size = u32::from_le_bytes(size_buf);
+            let entry_end = entry_end_offset(entry_offset, key_size, value_size, self.metadata_format, self.checksum)
+                .filter(|entry_end| *entry_end <= section_end)
+                .ok_or(HashTableError::EntryOutOfBounds { offset: entry_offset, key_size, value_size, section_end })?;
+
+            visit(entry_offset, key_size, value_size, &mut section)?;
+
+            section.seek(SeekFrom::Start(entry_end))?;
+            entry_offset = entry_end;
+        }
+        Ok(())
+    }
+
+    /// Regenerates the index chunks and their bloom filters from the entries in the sections,
+    /// as `insert` would have recorded them.
+    pub fn build_index_registry(&self) -> io::Result<MemoryIndexRegistry> {
+        let mut index_registry = MemoryIndexRegistry::new();
+        let mut key = Vec::new();
+        for section_index in 0..self.section_count {
+            self.walk_section(section_index, |entry_offset, key_size, _, section| {
+                key.resize(key_size as usize, 0);
+                section.read_exact(&mut key)?;
+                let (_, bloom_bits) = self.key_position(&key);
+                let index_key = IndexKey {
+                    section_index,
+                    index_chunk: index_chunk_of(entry_offset, self.index_chunk_size)?,
+                };
+                index_registry.update_index_bloom_filter(&index_key, entry_offset, &bloom_bits)
+            })?;
+        }
+        Ok(index_registry)
+    }
+
+    /// Calls `visit` with the key, value and metadata of the entries, section by section in the
+    /// order of inserts. `duplicate_keys` selects them as it does for scans, regardless of the one
+    /// set for the table.
+    pub fn for_each_entry(
+        &self,
+        duplicate_keys: DuplicateKeys,
+        mut visit: impl FnMut(&[u8], &[u8], &EntryMetadata) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut key = Vec::new();
+        let mut value = Vec::new();
+        for section_index in 0..self.section_count {
+            let latest_offsets = match duplicate_keys {
+                DuplicateKeys::KeepAll => None,
+                DuplicateKeys::LatestWins => {
+                    let mut latest_offsets = BTreeMap::new();
+                    self.walk_section(section_index, |entry_offset, key_size, _, section| {
+                        key.resize(key_size as usize, 0);
+                        section.read_exact(&mut key)?;
+                        latest_offsets.insert(key.clone(), entry_offset);
+                        Ok(())
+                    })?;
+                    Some(latest_offsets.into_values().collect::<BTreeSet<_>>())
+                },
+            };
+            self.walk_section(section_index, |entry_offset, key_size, value_size, section| {
+                if latest_offsets.as_ref().is_some_and(|latest_offsets| !latest_offsets.contains(&entry_offset)) {
+                    return Ok(());
+                }
+                key.resize(key_size as usize, 0);
+                section.read_exact(&mut key)?;
+                value.resize(value_size as usize, 0);
+                section.read_exact(&mut value)?;
+                if self.checksum == EntryChecksum::Crc32 {
+                    let mut crc = Crc32::new();
+                    crc.update(&key);
+                    crc.update(&value);
+                    if read_checksum(section)? != crc.finalize() {
+                        return Err(HashTableError::ChecksumMismatch { section_index, offset: entry_offset }.into());
+                    }
+                }
+                let metadata = self.metadata_format.read(section)?;
+                visit(&key, &value, &metadata)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Inserts an entry stamped with `metadata` instead of a new sequence number and timestamp,
+    /// e.g. to copy it from another table. Later inserts continue after its sequence number.
+    pub fn insert_with_metadata(&mut self, key: &[u8], value: &[u8], metadata: &EntryMetadata) -> io::Result<()> {
+        self.insert_entry(key, value, metadata)
+    }
+
+    fn insert_entry(&mut self, key: &[u8], value: &[u8], metadata: &EntryMetadata) -> io::Result<()> {
+        let (key_size, value_size) = self.limits.check(key, value)?;
+        // Checked before anything is written, so the sequence after the entry's always exists.
+        let next_sequence = metadata.sequence.unwrap_or(self.next_sequence).checked_add(1)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Sequence number is out of range"))?;
+
+        let (section_index, bloom_bits) = self.key_position(key);
 
         let mut section = self.book.section(section_index);
         let section_header = self.section_registry.resolve_section(section_index)?;
 
-        let index_chunk = (section_header.end_offset / self.index_chunk_size as u64) as IndexChunk;
+        let entry_offset = section_header.end_offset;
+        let entry_end = entry_end_offset(entry_offset, key_size, value_size, self.metadata_format, self.checksum)
+            .ok_or(HashTableError::EntryOffsetOverflow { offset: entry_offset })?;
+        let index_chunk = index_chunk_of(entry_offset, self.index_chunk_size)?;
         let index_key = IndexKey {
             section_index,
             index_chunk,
         };
 
-        let entry_offset = section_header.end_offset;
         section.seek(SeekFrom::Start(entry_offset))?;
 
-        let key_size = key.len() as u32;
-        let value_size = value.len() as u32;
-        section.write_all(&key_size.to_le_bytes())?;
-        section.write_all(&value_size.to_le_bytes())?;
-        section.write_all(key)?;
-        section.write_all(value)?;
+        let (key_size_bytes, value_size_bytes) = (key_size.to_le_bytes(), value_size.to_le_bytes());
+        let mut trailer = Vec::with_capacity(20);
+        if self.checksum == EntryChecksum::Crc32 {
+            let mut crc = Crc32::new();
+            crc.update(key);
+            crc.update(value);
+            trailer.extend_from_slice(&crc.finalize().to_le_bytes());
+        }
+        self.metadata_format.write(&mut trailer, metadata)?;
+        // Written as one page write per page spanned, rather than one per part.
+        write_all_vectored(&mut section, &mut [
+            IoSlice::new(&key_size_bytes),
+            IoSlice::new(&value_size_bytes),
+            IoSlice::new(key),
+            IoSlice::new(value),
+            IoSlice::new(&trailer),
+        ])?;
+        self.next_sequence = self.next_sequence.max(next_sequence);
     
         let new_end = section.stream_position()?;
+        debug_assert_eq!(new_end, entry_end);
         self.section_registry.update_section_end_offset(section_index, new_end)?;
 
-        self.index_registry.update_index_bloom_filter(&index_key, entry_offset, bloom_bit)?;
+        self.index_registry.update_index_bloom_filter(&index_key, entry_offset, &bloom_bits)?;
 
+        if let Some(metrics) = &self.metrics {
+            metrics.insert(key_size, value_size);
+        }
         Ok(())
     }
 
-    fn scan<'a>(&'a self, filter: HashTableScanFilter<'a>) -> io::Result<impl HashTableScanner + 'a> {
-        let section_index = match filter {
-            HashTableScanFilter::All => None,
-            HashTableScanFilter::Key(key) => {
-                let section_index = {
-                    let mut hasher = self.hasher_builder.build();
-                    hasher.update(key);
-                    let hash = hasher.finalize();
-                    hash % self.section_count
-                };
-                Some(section_index)
-            },
+    /// Counts the entries and their bytes, reading only the header of each entry.
+    pub fn entry_stats(&self) -> io::Result<EntryStats> {
+        let mut stats = EntryStats::default();
+        for section_index in 0..self.section_count {
+            let section_end = self.section_registry.resolve_section(section_index)?.end_offset;
+            stats.section_sizes.push(section_end);
+            self.walk_section(section_index, |_, key_size, value_size, _| {
+                stats.entry_count += 1;
+                stats.key_bytes += key_size as u64;
+                stats.value_bytes += value_size as u64;
+                Ok(())
+            })?;
+        }
+        Ok(stats)
+    }
+
+    /// Bytes taken by the entries shadowed by a later entry of the same key, headers and metadata
+    /// included, which `DuplicateKeys::LatestWins` would drop. Reads the key of every entry.
+    pub fn shadowed_entry_bytes(&self) -> io::Result<u64> {
+        let mut shadowed_bytes = 0;
+        let mut key = Vec::new();
+        for section_index in 0..self.section_count {
+            let mut latest_sizes = BTreeMap::new();
+            self.walk_section(section_index, |_, key_size, value_size, section| {
+                key.resize(key_size as usize, 0);
+                section.read_exact(&mut key)?;
+                let entry_size = ENTRY_HEADER_SIZE + key_size as u64 + value_size as u64
+                    + self.checksum.size() + self.metadata_format.trailer_size();
+                if let Some(shadowed_size) = latest_sizes.insert(key.clone(), entry_size) {
+                    shadowed_bytes += shadowed_size;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(shadowed_bytes)
+    }
+
+    pub fn book(&mut self) -> &mut B {
+        &mut self.book
+    }
+
+    pub fn section_registry(&mut self) -> &mut SR {
+        &mut self.section_registry
+    }
+
+    pub fn index_registry(&mut self) -> &mut IR {
+        &mut self.index_registry
+    }
+}
+
+impl<H: SliceHasherBuilder, B: Book, SR: SectionRegistry, IR: IndexRegistry> HashTable for BookHashTable<H, B, SR, IR> {
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> crate::Result<()> {
+        let metadata = EntryMetadata {
+            sequence: Some(self.next_sequence),
+            timestamp_micros: (self.metadata_format == EntryMetadataFormat::SequenceAndTimestamp).then(now_micros),
         };
-        let bloom_query = match filter {
+        Ok(self.insert_entry(key, value, &metadata)?)
+    }
+
+    fn scan<'a>(&'a self, filter: HashTableScanFilter<'a>) -> crate::Result<impl HashTableScanner + 'a> {
+        Ok(self.scan_sections(filter, None::<fn(CorruptRange)>, ScanCursor::default(), None)?)
+    }
+}
+
+impl<H: SliceHasherBuilder, B: Book, SR: SectionRegistry, IR: IndexRegistry> BookHashTable<H, B, SR, IR> {
+    /// Scans like [`HashTable::scan`], but instead of failing on an entry with an invalid header,
+    /// reports the unreadable range to `on_corruption` and resumes at the next index chunk.
+    pub fn scan_with_recovery<'a>(
+        &'a self,
+        filter: HashTableScanFilter<'a>,
+        on_corruption: impl FnMut(CorruptRange) + 'a,
+    ) -> io::Result<impl HashTableScanner + 'a> {
+        self.scan_sections(filter, Some(on_corruption), ScanCursor::default(), None)
+    }
+
+    /// Scans like [`HashTable::scan`], but returns only the entries accepted by `predicate`, which
+    /// sees their sizes and up to `value_prefix_size` leading bytes of their values.
+    pub fn scan_where<'a>(
+        &'a self,
+        filter: HashTableScanFilter<'a>,
+        value_prefix_size: usize,
+        predicate: impl FnMut(&EntryPreview) -> bool + 'a,
+    ) -> io::Result<impl ResumableScanner + 'a> {
+        Ok(PredicateScanner {
+            scanner: self.scan_sections(filter, None::<fn(CorruptRange)>, ScanCursor::default(), None)?,
+            value_prefix: vec![0u8; value_prefix_size],
+            predicate,
+        })
+    }
+
+    /// Scans like [`HashTable::scan`], starting at `cursor` as returned by [`ResumableScanner::cursor`]
+    /// of an earlier scan with the same filter.
+    pub fn scan_from<'a>(
+        &'a self,
+        filter: HashTableScanFilter<'a>,
+        cursor: ScanCursor,
+    ) -> io::Result<impl ResumableScanner + 'a> {
+        self.scan_sections(filter, None::<fn(CorruptRange)>, cursor, None)
+    }
+
+    /// Pins the current end of every section. The pages up to there are never rewritten, so later
+    /// scans over the snapshot see exactly the entries inserted so far, however many follow.
+    pub fn snapshot(&self) -> io::Result<HashTableSnapshot> {
+        let section_ends = (0..self.section_count)
+            .map(|section_index| Ok(self.section_registry.resolve_section(section_index)?.end_offset))
+            .collect::<io::Result<_>>()?;
+        Ok(HashTableSnapshot { section_ends })
+    }
+
+    /// Scans like [`HashTable::scan`], returning only the entries inserted before `snapshot` was
+    /// taken of this table.
+    pub fn scan_snapshot<'a>(
+        &'a self,
+        snapshot: &'a HashTableSnapshot,
+        filter: HashTableScanFilter<'a>,
+    ) -> io::Result<impl ResumableScanner + 'a> {
+        if snapshot.section_ends.len() != self.section_count as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Snapshot was taken with a different section count"));
+        }
+        self.scan_sections(filter, None::<fn(CorruptRange)>, ScanCursor::default(), Some(&snapshot.section_ends))
+    }
+
+    fn scan_sections<'a, C: FnMut(CorruptRange) + 'a>(
+        &'a self,
+        filter: HashTableScanFilter<'a>,
+        on_corruption: Option<C>,
+        cursor: ScanCursor,
+        section_ends: Option<&'a [u64]>,
+    ) -> io::Result<impl ResumableScanner + 'a> {
+        if let Some(metrics) = &self.metrics {
+            metrics.scan(matches!(filter, HashTableScanFilter::Key(_)));
+        }
+        let (section_index, bloom_query, filter_key) = match filter {
+            HashTableScanFilter::All => (None, None, None),
             HashTableScanFilter::Key(key) => {
-                let mut hasher = self.hasher_builder.build();
-                hasher.update(key);
-                let hash = hasher.finalize();
-                let bloom_index = (hash / self.section_count) as u64 % 64;
-                Some(1u64 << bloom_index)
+                let (section_index, bloom_bits) = self.key_position(key);
+                (Some(section_index), Some(bloom_bits), Some(key))
             },
-            _ => None,
+        };
+        let section_scanner = move |section_index: SectionIndex| -> io::Result<SectionScanner<B::Section<'_>, IR>> {
+            let mut end_offset = self.section_registry.resolve_section(section_index)?.end_offset;
+            if let Some(section_ends) = section_ends {
+                end_offset = end_offset.min(section_ends[section_index as usize]);
+            }
+            let (start_offset, section_end) = if section_index == cursor.section_index {
+                let section_end = cursor.section_end.map_or(end_offset, |end| end.min(end_offset));
+                if cursor.offset > section_end {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Scan cursor is beyond the section end"));
+                }
+                (cursor.offset, section_end)
+            } else {
+                (0, end_offset)
+            };
+            let mut section = self.book.section(section_index);
+            section.seek(SeekFrom::Start(start_offset))?;
+            let mut scanner = SectionScanner {
+                section,
+                section_index,
+                section_end,
+                bloom_query,
+                index_chunk: None,
+                index_chunk_size: self.index_chunk_size,
+                index_registry: &self.index_registry,
+                metadata_format: self.metadata_format,
+                checksum: self.checksum,
+                latest_offsets: None,
+                metrics: self.metrics.as_deref(),
+            };
+            if self.duplicate_keys == DuplicateKeys::LatestWins {
+                scanner.latest_offsets = Some(scanner.latest_entry_offsets(filter_key)?);
+            }
+            Ok(scanner)
         };
         let section_scanners = match section_index {
-            Some(index) => {
-                match self.section_registry.resolve_section(index)? {
-                    SectionHeader {
-                        end_offset
-                    } if end_offset > 0 => SectionScannerIterator::Single(SectionScanner {
-                        section: self.book.section(index),
-                        section_index: index,
-                        section_end: end_offset,
-                        bloom_query,
-                        index_chunk: None,
-                        index_chunk_size: self.index_chunk_size,
-                        index_registry: &self.index_registry,
-                    }),
-                    _ => SectionScannerIterator::None,
+            Some(index) if index >= cursor.section_index => {
+                let section_filter = self.index_registry.section_bloom_filter(index)?;
+                let may_contain = match (section_filter, bloom_query) {
+                    (Some(section_filter), Some(bloom_query)) => {
+                        let may_contain = section_filter.may_contain(&bloom_query);
+                        if let Some(metrics) = &self.metrics {
+                            metrics.section_bloom(may_contain);
+                        }
+                        may_contain
+                    },
+                    _ => true,
+                };
+                let scanner = section_scanner(index)?;
+                if scanner.section_end > 0 && may_contain {
+                    SectionScannerIterator::Single(Box::new(scanner))
+                } else {
+                    SectionScannerIterator::None
                 }
             },
+            Some(_) => SectionScannerIterator::None,
             None => SectionScannerIterator::Many(
-                // TODO: optimize this by supporting iterating non-empty sections only
-                (0..self.section_count)
-                    .map(|section_index| (section_index, self.section_registry.resolve_section(section_index)))
-                    .map(move |(section_index, section_header)| -> io::Result<SectionScanner<B::Section<'_>, IR>> {
-                        let section_header = section_header?;
-                        Ok(SectionScanner {
-                            section: self.book.section(section_index),
-                            section_index,
-                            section_end: section_header.end_offset,
-                            bloom_query,
-                            index_chunk: None,
-                            index_chunk_size: self.index_chunk_size,
-                            index_registry: &self.index_registry,
-                        })
-                    })
+                self.section_registry.iter_non_empty(cursor.section_index..self.section_count)
+                    .map(move |section_index| section_scanner(section_index?))
             ),
         };
         let multi_scanner = MultiSectionScanner {
             scanners: section_scanners,
             current_scanner: None,
+            on_corruption,
+            cursor,
         };
         Ok(FilterScanner {
             filter,
-            key_buffer: [0u8; 256],
+            key_chunk: Vec::new(),
             scanner: multi_scanner,
         })
     }
 }
 
+/// Size of the chunks in which a `FilterScanner` compares keys.
+const KEY_COMPARE_CHUNK_SIZE: usize = 4096;
+
 struct FilterScanner<'key, Scanner> {
     filter: HashTableScanFilter<'key>,
-    key_buffer: [u8; 256],
+    /// Grown on the first key compared, up to `KEY_COMPARE_CHUNK_SIZE`.
+    key_chunk: Vec<u8>,
     scanner: Scanner,
 }
 
+/// Whether `reader` yields exactly the bytes of `expected`, given that it yields as many.
+fn read_matches(reader: &mut impl Read, mut expected: &[u8], chunk: &mut Vec<u8>) -> io::Result<bool> {
+    if chunk.len() < expected.len().min(KEY_COMPARE_CHUNK_SIZE) {
+        chunk.resize(expected.len().min(KEY_COMPARE_CHUNK_SIZE), 0);
+    }
+    while !expected.is_empty() {
+        let size = expected.len().min(chunk.len());
+        reader.read_exact(&mut chunk[..size])?;
+        if chunk[..size] != expected[..size] {
+            return Ok(false);
+        }
+        expected = &expected[size..];
+    }
+    Ok(true)
+}
+
 impl<'key, Scanner: HashTableScanner> HashTableScanner for FilterScanner<'key, Scanner> {
-    fn next(&mut self) -> io::Result<Option<impl HashTableEntry + use<'key, Scanner>>> {
-        'entry_loop: loop {
+    fn next(&mut self) -> crate::Result<Option<impl HashTableEntry + use<'key, Scanner>>> {
+        loop {
             let mut entry = match self.scanner.next()? {
                 Some(e) => e,
                 None => return Ok(None),
             };
             match &self.filter {
                 HashTableScanFilter::Key(expected_key) => {
-                    let mut key_reader = entry.key()?;
-                    let mut expected_key = *expected_key;
-                    loop {
-                        let read_size = key_reader.read(&mut self.key_buffer)?;
-                        if read_size == 0 {
-                            if expected_key.is_empty() {
-                                drop(key_reader);
-                                return Ok(Some(entry));
-                            } else {
-                                continue 'entry_loop;
-                            }
-                        }
-                        if read_size > expected_key.len() {
-                            continue 'entry_loop;
-                        }
-                        if &self.key_buffer[..read_size] != &expected_key[..read_size] {
-                            continue 'entry_loop;
-                        }
-                        expected_key = &expected_key[read_size..];
+                    if entry.key_size() as usize != expected_key.len() {
+                        continue;
+                    }
+                    if read_matches(&mut entry.key()?, expected_key, &mut self.key_chunk)? {
+                        return Ok(Some(entry));
                     }
                 },
                 HashTableScanFilter::All => {
@@ -227,18 +966,78 @@ impl<'key, Scanner: HashTableScanner> HashTableScanner for FilterScanner<'key, S
     }
 }
 
-struct MultiSectionScanner<'a, IR, Section, I: Iterator<Item = io::Result<SectionScanner<'a, Section, IR>>>> {
+impl<Scanner: ResumableScanner> ResumableScanner for FilterScanner<'_, Scanner> {
+    fn cursor(&self) -> ScanCursor {
+        self.scanner.cursor()
+    }
+}
+
+/// What the predicate of [`BookHashTable::scan_where`] sees of an entry.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryPreview<'a> {
+    pub key_size: u32,
+    pub value_size: u32,
+    /// The leading bytes of the value, shorter than requested if the value is.
+    pub value_prefix: &'a [u8],
+}
+
+struct PredicateScanner<Scanner, P> {
+    scanner: Scanner,
+    value_prefix: Vec<u8>,
+    predicate: P,
+}
+
+impl<Scanner: HashTableScanner, P: FnMut(&EntryPreview) -> bool> HashTableScanner for PredicateScanner<Scanner, P> {
+    fn next(&mut self) -> crate::Result<Option<impl HashTableEntry + use<Scanner, P>>> {
+        while let Some(mut entry) = self.scanner.next()? {
+            let prefix_size = self.value_prefix.len().min(entry.value_size() as usize);
+            if prefix_size > 0 {
+                entry.value()?.read_exact(&mut self.value_prefix[..prefix_size])?;
+            }
+            let preview = EntryPreview {
+                key_size: entry.key_size(),
+                value_size: entry.value_size(),
+                value_prefix: &self.value_prefix[..prefix_size],
+            };
+            if (self.predicate)(&preview) {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<Scanner: ResumableScanner, P: FnMut(&EntryPreview) -> bool> ResumableScanner for PredicateScanner<Scanner, P> {
+    fn cursor(&self) -> ScanCursor {
+        self.scanner.cursor()
+    }
+}
+
+struct MultiSectionScanner<'a, IR, Section, I: Iterator<Item = io::Result<SectionScanner<'a, Section, IR>>>, C> {
     scanners: I,
     current_scanner: Option<SectionScanner<'a, Section, IR>>,
+    on_corruption: Option<C>,
+    cursor: ScanCursor,
 }
 
-impl<'a, IR: IndexRegistry, Section: Read + Seek + Clone, I: Iterator<Item = io::Result<SectionScanner<'a, Section, IR>>>> HashTableScanner for MultiSectionScanner<'a, IR, Section, I> {
-    fn next(&mut self) -> io::Result<Option<impl HashTableEntry + use<'a, IR, Section, I>>> {
+impl<'a, IR: IndexRegistry, Section: Read + Seek + Clone, I: Iterator<Item = io::Result<SectionScanner<'a, Section, IR>>>, C: FnMut(CorruptRange)> HashTableScanner for MultiSectionScanner<'a, IR, Section, I, C> {
+    fn next(&mut self) -> crate::Result<Option<impl HashTableEntry + use<'a, IR, Section, I, C>>> {
         loop {
             if let Some(scanner) = &mut self.current_scanner {
-                if let Some(entry) = scanner.next()? {
+                let on_corruption = self.on_corruption.as_mut().map(|f| f as &mut dyn FnMut(CorruptRange));
+                if let Some(entry) = scanner.next(on_corruption)? {
+                    self.cursor = ScanCursor {
+                        section_index: scanner.section_index,
+                        offset: scanner.section.stream_position()?,
+                        section_end: Some(scanner.section_end),
+                    };
                     return Ok(Some(entry));
                 }
+                self.cursor = ScanCursor {
+                    section_index: scanner.section_index.saturating_add(1),
+                    offset: 0,
+                    section_end: None,
+                };
                 self.current_scanner = None;
             }
             if let Some(next_scanner) = self.scanners.next() {
@@ -250,8 +1049,14 @@ impl<'a, IR: IndexRegistry, Section: Read + Seek + Clone, I: Iterator<Item = io:
     }
 }
 
+impl<'a, IR: IndexRegistry, Section: Read + Seek + Clone, I: Iterator<Item = io::Result<SectionScanner<'a, Section, IR>>>, C: FnMut(CorruptRange)> ResumableScanner for MultiSectionScanner<'a, IR, Section, I, C> {
+    fn cursor(&self) -> ScanCursor {
+        self.cursor
+    }
+}
+
 enum SectionScannerIterator<'a, Section, IR, I: Iterator<Item = io::Result<SectionScanner<'a, Section, IR>>>> {
-    Single(SectionScanner<'a, Section, IR>),
+    Single(Box<SectionScanner<'a, Section, IR>>),
     None,
     Many(I),
 }
@@ -264,7 +1069,7 @@ impl<'a, IR, Section, I: Iterator<Item = io::Result<SectionScanner<'a, Section,
             SectionScannerIterator::Single(..) => {
                 let section_scanner = replace(self, SectionScannerIterator::None);
                 if let SectionScannerIterator::Single(section_scanner) = section_scanner {
-                    Some(Ok(section_scanner))
+                    Some(Ok(*section_scanner))
                 } else {
                     None
                 }
@@ -279,77 +1084,170 @@ struct SectionScanner<'a, Section, IR> {
     section: Section,
     section_index: SectionIndex,
     section_end: u64,
-    bloom_query: Option<u64>,
+    bloom_query: Option<BloomFilter>,
     index_chunk: Option<(IndexKey, IndexHeader)>,
     index_chunk_size: IndexChunkSize,
     index_registry: &'a IR,
+    metadata_format: EntryMetadataFormat,
+    checksum: EntryChecksum,
+    /// With `DuplicateKeys::LatestWins`, the offsets of the entries not shadowed by a later one.
+    latest_offsets: Option<BTreeSet<u64>>,
+    metrics: Option<&'a dyn HashTableMetrics>,
 }
 
+/// Reads the parts of the entry through one reader, seeking it to the part asked for, so that
+/// nothing is read or cloned for the parts the caller skips.
 struct ScannerEntry<Reader: Read + Seek + Clone> {
     reader: Reader,
+    section_index: SectionIndex,
+    key_offset: u64,
     key_size: u32,
     value_size: u32,
+    metadata_format: EntryMetadataFormat,
+    checksum: EntryChecksum,
 }
 
 impl<Reader: Read + Seek + Clone, IR: IndexRegistry> SectionScanner<'_, Reader, IR> {
-    fn next(&mut self) -> io::Result<Option<ScannerEntry<Reader>>> {
-        let mut position = self.section.stream_position()?;
+    fn next(&mut self, mut on_corruption: Option<&mut dyn FnMut(CorruptRange)>) -> io::Result<Option<ScannerEntry<Reader>>> {
+        loop {
+            let mut position = self.section.stream_position()?;
 
-        if let Some(bloom_query) = self.bloom_query {
-            let index_chunk = (position / self.index_chunk_size as u64) as IndexChunk;
-            let index_key = IndexKey {
-                section_index: self.section_index,
-                index_chunk,
-            };
-            match &self.index_chunk {
-                Some((current_index_key, _)) if *current_index_key == index_key => {
-                    // TODO: in this case, we may skip next steps
+            if let Some(bloom_query) = &self.bloom_query {
+                let index_chunk = index_chunk_of(position, self.index_chunk_size)?;
+                let index_key = IndexKey {
+                    section_index: self.section_index,
+                    index_chunk,
+                };
+                let mut entered_chunk = false;
+                match &self.index_chunk {
+                    Some((current_index_key, _)) if *current_index_key == index_key => {
+                        // TODO: in this case, we may skip next steps
+                    },
+                    _ => {
+                        self.index_chunk = self.index_registry.try_resolve_index(&index_key)?.map(|ih| (index_key, ih));
+                        entered_chunk = true;
+                    },
+                }
+                let Some((_, index_header)) = &self.index_chunk else {
+                    return Ok(None);
+                };
+                let may_contain = index_header.bloom_filter.may_contain(bloom_query);
+                if let Some(metrics) = self.metrics.filter(|_| entered_chunk) {
+                    metrics.chunk_bloom(may_contain);
+                }
+                if !may_contain {
+                    let next_position = self.next_chunk_offset(position)?;
+                    self.section.seek(SeekFrom::Start(next_position))?;
+                    position = next_position;
+                }
+            }
+
+            match position.cmp(&self.section_end) {
+                Ordering::Greater => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Section stream position exceeded section end"));
                 },
-                _ => {
-                    self.index_chunk = self.index_registry.try_resolve_index(&index_key)?.map(|ih| (index_key, ih));
+                Ordering::Equal => {
+                    return Ok(None);
                 },
-            }
-            let Some((_, index_header)) = &self.index_chunk else {
-                return Ok(None);
+                Ordering::Less => {},
             };
-            if (index_header.bloom_filter & bloom_query) == 0 {
-                let next_index_header = self.index_registry.try_resolve_next_index(&index_key)?;
-                let next_position = match next_index_header {
-                    Some(IndexHeader { first_entry_offset, .. }) => first_entry_offset,
-                    None => self.section_end,
-                };
-                self.section.seek(SeekFrom::Start(next_position))?;
-                position = next_position;
-            }
-        }
 
-        match position.cmp(&self.section_end) {
-            Ordering::Greater => {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "Section stream position exceeded section end"));
-            },
-            Ordering::Equal => {
-                return Ok(None);
-            },
-            Ordering::Less => {},
-        };
+            let mut size_buf = [0u8; 4];
 
-        let mut size_buf = [0u8; 4];
+            self.section.read_exact(&mut size_buf)?;
+            let key_size = u32::from_le_bytes(size_buf);
 
-        self.section.read_exact(&mut size_buf)?;
-        let key_size = u32::from_le_bytes(size_buf);
+            self.section.read_exact(&mut size_buf)?;
+            let value_size = u32::from_le_bytes(size_buf);
 
-        self.section.read_exact(&mut size_buf)?;
-        let value_size = u32::from_le_bytes(size_buf);
+            let entry_end = entry_end_offset(position, key_size, value_size, self.metadata_format, self.checksum)
+                .filter(|entry_end| *entry_end <= self.section_end);
+            let Some(entry_end) = entry_end else {
+                let err = HashTableError::EntryOutOfBounds {
+                    offset: position,
+                    key_size,
+                    value_size,
+                    section_end: self.section_end,
+                };
+                let Some(on_corruption) = on_corruption.as_mut() else {
+                    return Err(err.into());
+                };
+                let resume_position = self.next_chunk_offset(position)?;
+                on_corruption(CorruptRange {
+                    section_index: self.section_index,
+                    start_offset: position,
+                    end_offset: resume_position,
+                });
+                self.section.seek(SeekFrom::Start(resume_position))?;
+                continue;
+            };
 
-        let reader = self.section.clone();
+            if self.latest_offsets.as_ref().is_some_and(|latest_offsets| !latest_offsets.contains(&position)) {
+                self.section.seek(SeekFrom::Start(entry_end))?;
+                continue;
+            }
+
+            let reader = self.section.clone();
 
-        self.section.seek_relative((key_size + value_size) as i64)?;
+            self.section.seek(SeekFrom::Start(entry_end))?;
+            if let Some(metrics) = self.metrics {
+                metrics.entry_scanned();
+            }
 
-        Ok(Some(ScannerEntry {
-            reader,
-            key_size,
-            value_size,
-        }))
+            return Ok(Some(ScannerEntry {
+                reader,
+                section_index: self.section_index,
+                key_offset: position + ENTRY_HEADER_SIZE,
+                key_size,
+                value_size,
+                metadata_format: self.metadata_format,
+                checksum: self.checksum,
+            }));
+        }
+    }
+
+    /// Offsets of the entries of the whole section that are the latest of their key, only of `key`
+    /// if given. Unreadable entries are left out; the scan itself reports them.
+    fn latest_entry_offsets(&self, key: Option<&[u8]>) -> io::Result<BTreeSet<u64>> {
+        let mut section = self.section.clone();
+        section.seek(SeekFrom::Start(0))?;
+        let mut pass = SectionScanner {
+            section,
+            section_index: self.section_index,
+            section_end: self.section_end,
+            bloom_query: self.bloom_query,
+            index_chunk: None,
+            index_chunk_size: self.index_chunk_size,
+            index_registry: self.index_registry,
+            metadata_format: self.metadata_format,
+            checksum: self.checksum,
+            latest_offsets: None,
+            metrics: None,
+        };
+        let mut latest_offsets = BTreeMap::new();
+        let mut entry_key = Vec::new();
+        while let Some(mut entry) = pass.next(Some(&mut |_| {}))? {
+            entry.read_key_into(&mut entry_key)?;
+            if key.is_some_and(|key| key != entry_key) {
+                continue;
+            }
+            latest_offsets.insert(entry_key.clone(), entry.key_offset - ENTRY_HEADER_SIZE);
+        }
+        Ok(latest_offsets.into_values().collect())
+    }
+
+    /// Returns the offset of the first entry in the index chunk following the one containing `position`,
+    /// or the section end if there is none.
+    fn next_chunk_offset(&self, position: u64) -> io::Result<u64> {
+        let index_key = IndexKey {
+            section_index: self.section_index,
+            index_chunk: index_chunk_of(position, self.index_chunk_size)?,
+        };
+        let next_index_header = self.index_registry.try_resolve_next_index(&index_key)?;
+        Ok(match next_index_header {
+            Some(IndexHeader { first_entry_offset, .. }) if first_entry_offset > position => first_entry_offset.min(self.section_end),
+            _ => self.section_end,
+        })
     }
 }
 
@@ -363,13 +1261,56 @@ impl<Reader: Read + Seek + Clone> HashTableEntry for ScannerEntry<Reader> {
     }
 
     fn key(&mut self) -> io::Result<impl Read + '_> {
-        let reader = self.reader.clone();
-        Ok(reader.take(self.key_size as u64))
+        self.reader.seek(SeekFrom::Start(self.key_offset))?;
+        Ok((&mut self.reader).take(self.key_size as u64))
     }
 
     fn value(&mut self) -> io::Result<impl Read + '_> {
-        let mut reader = self.reader.clone();
-        reader.seek(SeekFrom::Current(self.key_size as i64))?;
-        Ok(reader.take(self.value_size as u64))
+        self.verifying_value()
+    }
+
+    fn verify(&mut self) -> io::Result<()> {
+        self.verifying_value()?.finish()
+    }
+
+    fn metadata(&mut self) -> io::Result<EntryMetadata> {
+        let metadata_offset = self.key_offset + self.key_size as u64 + self.value_size as u64 + self.checksum.size();
+        self.reader.seek(SeekFrom::Start(metadata_offset))?;
+        self.metadata_format.read(&mut self.reader)
+    }
+}
+
+impl<Reader: Read + Seek + Clone> ScannerEntry<Reader> {
+    fn verifying_value(&mut self) -> io::Result<VerifyingReader<&mut Reader>> {
+        let crc = match self.checksum {
+            EntryChecksum::None => {
+                self.reader.seek(SeekFrom::Start(self.key_offset + self.key_size as u64))?;
+                None
+            },
+            EntryChecksum::Crc32 => {
+                // The key is read here, so that the checksum can be completed with the value.
+                self.reader.seek(SeekFrom::Start(self.key_offset))?;
+                let mut crc = Crc32::new();
+                let mut key = (&mut self.reader).take(self.key_size as u64);
+                let mut buffer = [0u8; 256];
+                loop {
+                    let read_size = key.read(&mut buffer)?;
+                    if read_size == 0 {
+                        break;
+                    }
+                    crc.update(&buffer[..read_size]);
+                }
+                if key.limit() > 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Some(crc)
+            },
+        };
+        Ok(VerifyingReader {
+            value: (&mut self.reader).take(self.value_size as u64),
+            crc,
+            section_index: self.section_index,
+            offset: self.key_offset - ENTRY_HEADER_SIZE,
+        })
     }
 }
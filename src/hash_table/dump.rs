@@ -0,0 +1,139 @@
+use core::slice;
+use std::io::{self, Read, Write};
+
+use super::{HashTable, HashTableEntry, HashTableScanFilter, HashTableScanner};
+
+/// Starts every dump; the last byte is the format version.
+const DUMP_MAGIC: [u8; 8] = *b"DSDUMP\0\x01";
+
+const ENTRY_TAG: u8 = 1;
+
+/// Followed by the number of entries, so a truncated dump is never mistaken for a complete one.
+const END_TAG: u8 = 0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DumpError {
+    #[error("Not a dump, or a dump of an unsupported version")]
+    InvalidMagic,
+    #[error("Unknown record tag {tag}")]
+    UnknownTag { tag: u8 },
+    #[error("Dump ends after {entry_count} entries, but {expected} were written")]
+    EntryCountMismatch { entry_count: u64, expected: u64 },
+    #[error("Value of an entry is shorter than its size of {value_size} bytes")]
+    ShortValue { value_size: u32 },
+}
+
+impl From<DumpError> for io::Error {
+    fn from(err: DumpError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Writes every entry returned by a scan of `hash_table` to `writer`, in the order of the scan,
+/// as length-prefixed keys and values. The dump does not depend on the page size, section count
+/// or hasher, so it can be imported into any table. Returns the number of entries written.
+pub fn export(hash_table: &impl HashTable, writer: &mut impl Write) -> io::Result<u64> {
+    writer.write_all(&DUMP_MAGIC)?;
+    let mut entry_count = 0u64;
+    let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+    while let Some(mut entry) = scanner.next()? {
+        let (key_size, value_size) = (entry.key_size(), entry.value_size());
+        entry.verify()?;
+        writer.write_all(&[ENTRY_TAG])?;
+        writer.write_all(&key_size.to_le_bytes())?;
+        writer.write_all(&value_size.to_le_bytes())?;
+        writer.write_all(&entry.read_key_to_vec()?)?;
+        // Streamed, as values may be large.
+        let copied = io::copy(&mut entry.value()?.take(value_size as u64), writer)?;
+        if copied != value_size as u64 {
+            return Err(DumpError::ShortValue { value_size }.into());
+        }
+        entry_count += 1;
+    }
+    writer.write_all(&[END_TAG])?;
+    writer.write_all(&entry_count.to_le_bytes())?;
+    Ok(entry_count)
+}
+
+/// Inserts the entries of a dump written by `export` into `hash_table`, in the order they were
+/// exported. Entries inserted before an error are kept. Returns the number of entries inserted.
+pub fn import(hash_table: &mut impl HashTable, reader: &mut impl Read) -> io::Result<u64> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != DUMP_MAGIC {
+        return Err(DumpError::InvalidMagic.into());
+    }
+    let (mut key, mut value) = (Vec::new(), Vec::new());
+    let mut entry_count = 0u64;
+    loop {
+        let mut tag = 0u8;
+        reader.read_exact(slice::from_mut(&mut tag))?;
+        match tag {
+            ENTRY_TAG => {
+                let mut sizes = [0u8; 8];
+                reader.read_exact(&mut sizes)?;
+                let key_size = u32::from_le_bytes(sizes[0..4].try_into().unwrap());
+                let value_size = u32::from_le_bytes(sizes[4..8].try_into().unwrap());
+                read_exact_into(reader, &mut key, key_size)?;
+                read_exact_into(reader, &mut value, value_size)?;
+                hash_table.insert(&key, &value)?;
+                entry_count += 1;
+            },
+            END_TAG => {
+                let mut buffer = [0u8; 8];
+                reader.read_exact(&mut buffer)?;
+                let expected = u64::from_le_bytes(buffer);
+                if expected != entry_count {
+                    return Err(DumpError::EntryCountMismatch { entry_count, expected }.into());
+                }
+                return Ok(entry_count);
+            },
+            tag => return Err(DumpError::UnknownTag { tag }.into()),
+        }
+    }
+}
+
+/// Reads `size` bytes into `buffer` without trusting `size` for the allocation up front.
+fn read_exact_into(reader: &mut impl Read, buffer: &mut Vec<u8>, size: u32) -> io::Result<()> {
+    buffer.clear();
+    let read = reader.take(size as u64).read_to_end(buffer)?;
+    if read != size as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Dump ends within an entry"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_table::MemoryHashTable;
+
+    #[test]
+    fn test_export_import() -> io::Result<()> {
+        let mut source = MemoryHashTable::in_memory(64, 4, 64);
+        source.insert(b"foo", b"bar")?;
+        source.insert(b"test-key", &[7u8; 300])?;
+        source.insert(b"foo", b"baz")?;
+        source.insert(b"", b"")?;
+
+        let mut dump = Vec::new();
+        assert_eq!(export(&source, &mut dump)?, 4);
+
+        let mut target = MemoryHashTable::in_memory(128, 16, 256);
+        assert_eq!(import(&mut target, &mut dump.as_slice())?, 4);
+        let mut values = Vec::new();
+        let mut scanner = target.scan(HashTableScanFilter::Key(b"foo"))?;
+        while let Some(mut entry) = scanner.next()? {
+            values.push(entry.read_value_to_vec()?);
+        }
+        assert_eq!(values, [b"bar".to_vec(), b"baz".to_vec()]);
+        assert_eq!(target.get(b"test-key")?, Some(vec![7u8; 300]));
+        assert_eq!(target.get(b"")?, Some(Vec::new()));
+
+        let err = import(&mut MemoryHashTable::in_memory(64, 4, 64), &mut &dump[..dump.len() - 20]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = import(&mut MemoryHashTable::in_memory(64, 4, 64), &mut &b"not a dump"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+}
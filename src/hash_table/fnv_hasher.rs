@@ -0,0 +1,53 @@
+use crate::hash_table::{Hash, SliceHasher};
+
+use super::SliceHasherBuilder;
+
+/// Hash of the empty input, from which FNV-1a starts.
+pub const FNV_OFFSET_BASIS: Hash = 0x811c9dc5;
+
+/// Continues the 32-bit FNV-1a `hash` over `data`.
+pub fn fnv1a(hash: Hash, data: &[u8]) -> Hash {
+    data.iter().fold(hash, |hash, &byte| (hash ^ byte as Hash).wrapping_mul(0x01000193))
+}
+
+/// The finalizer of MurmurHash3, spreading the bits of a FNV-1a hash. FNV-1a clusters for inputs
+/// differing only in their last bytes, and its low bits are the ones picking sections with
+/// `HasherKind::Fnv`, so hashes used for anything else are mixed first.
+pub fn fmix32(mut hash: Hash) -> Hash {
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^ (hash >> 16)
+}
+
+/// 32-bit FNV-1a over the whole key.
+pub struct FnvHasher {
+    hash: Hash,
+}
+
+impl FnvHasher {
+    pub fn new() -> Self {
+        Self { hash: FNV_OFFSET_BASIS }
+    }
+}
+
+impl SliceHasher for FnvHasher {
+    fn update(&mut self, data: &[u8]) {
+        self.hash = fnv1a(self.hash, data);
+    }
+
+    fn finalize(self) -> Hash {
+        self.hash
+    }
+}
+
+pub struct FnvHasherBuilder;
+
+impl SliceHasherBuilder for FnvHasherBuilder {
+    type Hasher = FnvHasher;
+
+    fn build(&self) -> Self::Hasher {
+        FnvHasher::new()
+    }
+}
@@ -0,0 +1,52 @@
+use crate::hash_table::{Hash, SliceHasher, SliceHasherBuilder, fnv_hasher::FnvHasher, prefix_hasher::PrefixHasher, xxhash::XxHasher32};
+
+/// A hasher chosen at runtime, e.g. from a config. It decides which section every key goes to,
+/// so it is part of the on-disk format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HasherKind {
+    /// `PrefixHasher`; keys sharing their first four bytes all go to the same section.
+    #[default]
+    Prefix,
+    /// `FnvHasher`.
+    Fnv,
+    /// `XxHasher32`.
+    Xxhash32,
+}
+
+pub enum AnyHasher {
+    Prefix(PrefixHasher),
+    Fnv(FnvHasher),
+    Xxhash32(XxHasher32),
+}
+
+impl SliceHasher for AnyHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            AnyHasher::Prefix(hasher) => hasher.update(data),
+            AnyHasher::Fnv(hasher) => hasher.update(data),
+            AnyHasher::Xxhash32(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Hash {
+        match self {
+            AnyHasher::Prefix(hasher) => hasher.finalize(),
+            AnyHasher::Fnv(hasher) => hasher.finalize(),
+            AnyHasher::Xxhash32(hasher) => hasher.finalize(),
+        }
+    }
+}
+
+impl SliceHasherBuilder for HasherKind {
+    type Hasher = AnyHasher;
+
+    fn build(&self) -> Self::Hasher {
+        match self {
+            HasherKind::Prefix => AnyHasher::Prefix(PrefixHasher::new()),
+            HasherKind::Fnv => AnyHasher::Fnv(FnvHasher::new()),
+            HasherKind::Xxhash32 => AnyHasher::Xxhash32(XxHasher32::new()),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::RwLock;
+
+use crate::{book::{SectionIndex, pager::{PagerBook, PagerBookMemoryHeader}}, pager::{PageSize, memory::MemoryPager}};
+
+use super::{book::{BookHashTable, IndexChunkSize, MemoryIndexRegistry, MemorySectionRegistry, SectionHeader}, prefix_hasher::PrefixHasherBuilder};
+
+/// A `BookHashTable` kept entirely in memory.
+pub type MemoryHashTable = BookHashTable<
+    PrefixHasherBuilder,
+    PagerBook<MemoryPager, PagerBookMemoryHeader>,
+    MemorySectionRegistry,
+    MemoryIndexRegistry,
+>;
+
+impl MemoryHashTable {
+    pub fn in_memory(page_size: PageSize, section_count: SectionIndex, index_chunk_size: IndexChunkSize) -> Self {
+        BookHashTable::new(
+            PrefixHasherBuilder,
+            PagerBook::new(MemoryPager::new(page_size), RwLock::default()),
+            section_count,
+            vec![SectionHeader { end_offset: 0 }; section_count as usize],
+            index_chunk_size,
+            MemoryIndexRegistry::new(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, io::{self, Seek, SeekFrom, Write}};
+
+    use super::*;
+    use crate::{book::Book, hash_table::{EntryMetadata, dump, HashTable, HashTableEntry, HashTableError, HashTableScanFilter, HashTableScanner, book::{EntryChecksum, EntryMetadataFormat}}};
+
+    #[test]
+    fn test_insert_and_scan() -> io::Result<()> {
+        let mut hash_table = MemoryHashTable::in_memory(64, 4, 64);
+        for i in 0..8 {
+            hash_table.insert(format!("key-{}", i % 3).as_bytes(), format!("value-{}", i).as_bytes())?;
+        }
+
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(b"key-1"))?;
+        let mut values = Vec::new();
+        while let Some(mut entry) = scanner.next()? {
+            values.push(String::from_utf8(entry.read_value_to_vec()?).unwrap());
+        }
+        assert_eq!(values, ["value-1", "value-4", "value-7"]);
+
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        let mut count = 0;
+        while scanner.next()?.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 8);
+        drop(scanner);
+
+        assert_eq!(hash_table.get(b"key-1")?, Some(b"value-7".to_vec()));
+        assert_eq!(hash_table.get(b"key-3")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_keys() -> io::Result<()> {
+        let mut hash_table = MemoryHashTable::in_memory(512, 4, 1024);
+        let long_key = |last: u8, len: usize| {
+            let mut key = vec![b'k'; len];
+            key[len - 1] = last;
+            key
+        };
+        hash_table.insert(&long_key(b'a', 10_000), b"a")?;
+        hash_table.insert(&long_key(b'b', 10_000), b"b")?;
+        hash_table.insert(&long_key(b'a', 9_999), b"shorter")?;
+        hash_table.insert(&long_key(b'a', 10_000), b"a-again")?;
+
+        let key = long_key(b'a', 10_000);
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(&key))?;
+        let mut values = Vec::new();
+        while let Some(mut entry) = scanner.next()? {
+            values.push(String::from_utf8(entry.read_value_to_vec()?).unwrap());
+        }
+        assert_eq!(values, ["a", "a-again"]);
+        drop(scanner);
+
+        assert_eq!(hash_table.get(&long_key(b'b', 10_000))?, Some(b"b".to_vec()));
+        assert_eq!(hash_table.get(&long_key(b'c', 10_000))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_mismatch() -> io::Result<()> {
+        let mut hash_table = MemoryHashTable::in_memory(64, 1, 64).with_checksum(EntryChecksum::Crc32);
+        hash_table.insert(b"key", b"value")?;
+        hash_table.insert(b"other", b"data")?;
+        assert_eq!(hash_table.get(b"key")?, Some(b"value".to_vec()));
+
+        // Flips the first byte of the first value, after its 8 byte header and 3 byte key.
+        let mut section = hash_table.book().section(0);
+        section.seek(SeekFrom::Start(11))?;
+        section.write_all(b"V")?;
+
+        let err = hash_table.get(b"key").unwrap_err();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<HashTableError>()),
+            Some(HashTableError::ChecksumMismatch { section_index: 0, offset: 0 }),
+        ));
+        assert_eq!(hash_table.get(b"other")?, Some(b"data".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify() -> io::Result<()> {
+        let mut hash_table = MemoryHashTable::in_memory(64, 1, 64).with_checksum(EntryChecksum::Crc32);
+        hash_table.insert(b"key", b"")?;
+        hash_table.insert(b"other", b"data")?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        while let Some(mut entry) = scanner.next()? {
+            entry.verify()?;
+        }
+        drop(scanner);
+
+        // Flips the first byte of the key of the first entry, whose value is empty.
+        let mut section = hash_table.book().section(0);
+        section.seek(SeekFrom::Start(8))?;
+        section.write_all(b"K")?;
+
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        let mut entry = scanner.next()?.unwrap();
+        assert_eq!(entry.read_key_to_vec()?, b"Key");
+        let err = entry.verify().unwrap_err();
+        assert!(matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<HashTableError>()),
+            Some(HashTableError::ChecksumMismatch { section_index: 0, offset: 0 }),
+        ));
+        drop(scanner);
+        assert_eq!(dump::export(&hash_table, &mut Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_where() -> io::Result<()> {
+        let mut hash_table = MemoryHashTable::in_memory(64, 4, 64);
+        for i in 0..20 {
+            let value = if i % 4 == 0 { format!("keep-{i}") } else { format!("drop-{i}") };
+            hash_table.insert(format!("key-{i}").as_bytes(), value.as_bytes())?;
+        }
+        hash_table.insert(b"key-short", b"k")?;
+
+        let mut scanner = hash_table.scan_where(HashTableScanFilter::All, 4, |entry| entry.value_prefix == b"keep")?;
+        let mut values = BTreeSet::new();
+        while let Some(mut entry) = scanner.next()? {
+            values.insert(String::from_utf8(entry.read_value_to_vec()?).unwrap());
+        }
+        assert_eq!(values, (0..20).step_by(4).map(|i| format!("keep-{i}")).collect());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_overflow() -> io::Result<()> {
+        let mut hash_table = MemoryHashTable::in_memory(64, 1, 64).with_metadata_format(EntryMetadataFormat::Sequence);
+        let metadata = |sequence| EntryMetadata { sequence: Some(sequence), ..Default::default() };
+        let err = hash_table.insert_with_metadata(b"key", b"value", &metadata(u64::MAX)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(hash_table.get(b"key")?, None);
+
+        hash_table.insert_with_metadata(b"key", b"value", &metadata(u64::MAX - 1))?;
+        assert_eq!(hash_table.next_sequence(), u64::MAX);
+        assert_eq!(hash_table.insert(b"key", b"other").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        hash_table.recover_sequence()?;
+        assert_eq!(hash_table.next_sequence(), u64::MAX);
+        assert_eq!(hash_table.get(b"key")?, Some(b"value".to_vec()));
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+/// Receives what a `BookHashTable` and the `ManagedHashTable` around it do, e.g. to export it as
+/// counters to a monitoring system. Called inline on the hot paths, so implementations should be
+/// cheap, e.g. atomic increments. Every method does nothing by default.
+pub trait HashTableMetrics: Send + Sync {
+    fn insert(&self, _key_size: u32, _value_size: u32) {}
+
+    /// A scan was started, `by_key` unless it is over all entries.
+    fn scan(&self, _by_key: bool) {}
+
+    /// A scan read an entry, whether or not its key matched.
+    fn entry_scanned(&self) {}
+
+    /// The bloom filter of a whole section was checked for a key scan. The section is skipped
+    /// unless it `may_contain` the key.
+    fn section_bloom(&self, _may_contain: bool) {}
+
+    /// The bloom filter of an index chunk was checked for a key scan. The chunk is skipped unless
+    /// it `may_contain` the key.
+    fn chunk_bloom(&self, _may_contain: bool) {}
+
+    /// A `sync`, or a `full_sync` if `full`, completed within `duration`.
+    fn sync(&self, _full: bool, _duration: Duration) {}
+}
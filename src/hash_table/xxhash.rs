@@ -0,0 +1,147 @@
+use crate::hash_table::{Hash, SliceHasher};
+
+use super::SliceHasherBuilder;
+
+const PRIME_1: u32 = 2654435761;
+const PRIME_2: u32 = 2246822519;
+const PRIME_3: u32 = 3266489917;
+const PRIME_4: u32 = 668265263;
+const PRIME_5: u32 = 374761393;
+
+const STRIPE_SIZE: usize = 16;
+
+fn round(accumulator: u32, lane: u32) -> u32 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME_2))
+        .rotate_left(13)
+        .wrapping_mul(PRIME_1)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// Streaming xxHash32 with a seed of zero.
+pub struct XxHasher32 {
+    accumulators: [u32; 4],
+    buffer: [u8; STRIPE_SIZE],
+    buffered: usize,
+    total_len: u64,
+}
+
+impl XxHasher32 {
+    pub fn new() -> Self {
+        Self {
+            accumulators: [
+                PRIME_1.wrapping_add(PRIME_2),
+                PRIME_2,
+                0,
+                0u32.wrapping_sub(PRIME_1),
+            ],
+            buffer: [0u8; STRIPE_SIZE],
+            buffered: 0,
+            total_len: 0,
+        }
+    }
+
+    fn consume_stripe(&mut self, stripe: &[u8]) {
+        for (lane, accumulator) in self.accumulators.iter_mut().enumerate() {
+            *accumulator = round(*accumulator, read_u32(&stripe[lane * 4..]));
+        }
+    }
+}
+
+impl SliceHasher for XxHasher32 {
+    fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+
+        if self.buffered > 0 {
+            let len = data.len().min(STRIPE_SIZE - self.buffered);
+            self.buffer[self.buffered..self.buffered + len].copy_from_slice(&data[..len]);
+            self.buffered += len;
+            data = &data[len..];
+            if self.buffered < STRIPE_SIZE {
+                return;
+            }
+            let stripe = self.buffer;
+            self.consume_stripe(&stripe);
+            self.buffered = 0;
+        }
+
+        let mut stripes = data.chunks_exact(STRIPE_SIZE);
+        for stripe in &mut stripes {
+            self.consume_stripe(stripe);
+        }
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn finalize(self) -> Hash {
+        let [v1, v2, v3, v4] = self.accumulators;
+        let mut hash = if self.total_len >= STRIPE_SIZE as u64 {
+            v1.rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18))
+        } else {
+            PRIME_5
+        };
+        hash = hash.wrapping_add(self.total_len as u32);
+
+        let mut rest = &self.buffer[..self.buffered];
+        while rest.len() >= 4 {
+            hash = hash.wrapping_add(read_u32(rest).wrapping_mul(PRIME_3)).rotate_left(17).wrapping_mul(PRIME_4);
+            rest = &rest[4..];
+        }
+        for &byte in rest {
+            hash = hash.wrapping_add((byte as u32).wrapping_mul(PRIME_5)).rotate_left(11).wrapping_mul(PRIME_1);
+        }
+
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(PRIME_2);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(PRIME_3);
+        hash ^ (hash >> 16)
+    }
+}
+
+pub struct XxHasher32Builder;
+
+impl SliceHasherBuilder for XxHasher32Builder {
+    type Hasher = XxHasher32;
+
+    fn build(&self) -> Self::Hasher {
+        XxHasher32::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xxhash32(chunks: &[&[u8]]) -> Hash {
+        let mut hasher = XxHasher32::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finalize()
+    }
+
+    #[test]
+    fn test_known_hashes() {
+        assert_eq!(xxhash32(&[b""]), 0x02cc5d05);
+        assert_eq!(xxhash32(&[b"a"]), 0x550d7456);
+        assert_eq!(xxhash32(&[b"abc"]), 0x32d153ff);
+        assert_eq!(xxhash32(&[b"Nobody inspects the spammish repetition"]), 0xe2293b2f);
+    }
+
+    #[test]
+    fn test_split_updates_match() {
+        let data = (0..100u8).collect::<Vec<_>>();
+        let whole = xxhash32(&[&data]);
+        for split in [1, 3, 15, 16, 17, 40] {
+            assert_eq!(xxhash32(&[&data[..split], &data[split..]]), whole, "split at {split}");
+        }
+    }
+}
@@ -1,6 +1,21 @@
 pub mod book;
 pub mod pager;
 pub mod hash_table;
+pub mod vfs;
+pub mod cluster;
+pub mod prelude;
+
+mod crc32;
+mod lru;
+mod error;
+
+pub use error::{Error, PoisonedLockError, Result};
 
 #[cfg(feature = "dbms")]
 pub mod dbms;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
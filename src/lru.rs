@@ -0,0 +1,108 @@
+//! The recency bookkeeping shared by the page caches of the pagers and `PagerBook`.
+
+use std::{collections::BTreeMap, ops::RangeBounds};
+
+/// Entries by key, remembering the order in which they were used. Inserting an entry or getting it
+/// through `get_mut` makes it the most recently used one.
+///
+/// Evicting is left to the caller, who may have to write an entry back before dropping it, or skip
+/// entries still in use: `iter_by_use` starts with the least recently used entry.
+pub(crate) struct Lru<K, V> {
+    entries: BTreeMap<K, (V, u64)>,
+    /// Keys of the entries by their last use.
+    recency: BTreeMap<u64, K>,
+    next_use: u64,
+}
+
+impl<K: Ord + Copy, V> Lru<K, V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            recency: BTreeMap::new(),
+            next_use: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// The entry of `key`, made the most recently used one.
+    pub(crate) fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let (value, last_use) = self.entries.get_mut(key)?;
+        self.recency.remove(last_use);
+        *last_use = self.next_use;
+        self.recency.insert(self.next_use, *key);
+        self.next_use += 1;
+        Some(value)
+    }
+
+    /// Inserts the entry as the most recently used one, returning the one it replaced.
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let replaced = self.remove(&key);
+        self.entries.insert(key, (value, self.next_use));
+        self.recency.insert(self.next_use, key);
+        self.next_use += 1;
+        replaced
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, last_use) = self.entries.remove(key)?;
+        self.recency.remove(&last_use);
+        Some(value)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    /// The entries in the order of their keys.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, (value, _))| (key, value))
+    }
+
+    /// The entries in the order of their keys, without changing their recency.
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.entries.iter_mut().map(|(key, (value, _))| (key, value))
+    }
+
+    /// The keys within `range`, in order.
+    pub(crate) fn keys_in(&self, range: impl RangeBounds<K>) -> impl Iterator<Item = &K> {
+        self.entries.range(range).map(|(key, _)| key)
+    }
+
+    /// The entries from the least to the most recently used one.
+    pub(crate) fn iter_by_use(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.recency.values().map(|key| (key, &self.entries[key].0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recency() {
+        let mut lru = Lru::new();
+        lru.insert(1, "one");
+        lru.insert(2, "two");
+        lru.insert(3, "three");
+        assert!(lru.get_mut(&1).is_some());
+        assert_eq!(lru.insert(2, "deux"), Some("two"));
+        assert_eq!(lru.iter_by_use().map(|(key, _)| *key).collect::<Vec<_>>(), [3, 1, 2]);
+
+        // Iterating does not count as a use.
+        assert_eq!(lru.iter().map(|(_, value)| *value).collect::<Vec<_>>(), ["one", "deux", "three"]);
+        assert_eq!(lru.remove(&3), Some("three"));
+        assert_eq!(lru.iter_by_use().next(), Some((&1, &"one")));
+        assert_eq!(lru.keys_in(2..).copied().collect::<Vec<_>>(), [2]);
+        assert_eq!(lru.len(), 2);
+        lru.clear();
+        assert!(!lru.contains(&1) && lru.iter_by_use().next().is_none());
+    }
+}
@@ -1,7 +1,15 @@
-use std::io::{self, Read, Seek, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 
 pub mod memory;
 pub mod fs;
+pub mod caching;
+pub mod tiered;
+pub mod encrypted;
+pub mod object;
+#[cfg(feature = "async")]
+pub mod async_pager;
+#[cfg(all(unix, feature = "mmap"))]
+pub mod mmap;
 
 pub type PageIndex = u32;
 
@@ -19,3 +27,24 @@ pub trait Pager {
 pub trait Page: Read + Write + Seek + Clone {
     fn index(&self) -> PageIndex;
 }
+
+/// The offset within a page of `page_size` bytes that `pos` seeks to from `current`, for the
+/// `Seek` implementations of the pages. Fails with `InvalidInput` outside of the page.
+pub(crate) fn seek_in_page(current: u64, page_size: u64, pos: SeekFrom) -> io::Result<u64> {
+    let (anchor, offset, is_forward) = match pos {
+        SeekFrom::Start(offset) => (0u64, offset, true),
+        SeekFrom::End(offset @ 0..) => (page_size, offset as u64, true),
+        SeekFrom::End(offset @ ..0) => (page_size, -offset as u64, false),
+        SeekFrom::Current(offset @ 0..) => (current, offset as u64, true),
+        SeekFrom::Current(offset @ ..0) => (current, -offset as u64, false),
+    };
+    let new_offset = if is_forward {
+        anchor.checked_add(offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek overflow"))?
+    } else {
+        anchor.checked_sub(offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek underflow"))?
+    };
+    if new_offset > page_size {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek out of bounds"));
+    }
+    Ok(new_offset)
+}
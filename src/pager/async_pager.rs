@@ -0,0 +1,391 @@
+use std::{future::{Future, ready}, io::{self, Read, Seek, SeekFrom, Write}, pin::{Pin, pin}, sync::{Arc, Mutex, mpsc}, task::{Context, Poll, Wake, Waker}, thread::{self, Thread}};
+
+use crate::pager::{Page, PageIndex, PageSize, Pager, memory::MemoryPager, seek_in_page};
+use crate::PoisonedLockError;
+
+/// `Pager` with futures in place of blocking page accesses.
+pub trait AsyncPager {
+    type Page: AsyncPage;
+
+    /// Returns the size of each page in bytes.
+    fn page_size(&self) -> PageSize;
+
+    fn page(&self, page_index: PageIndex) -> io::Result<Self::Page>;
+}
+
+/// A page read and written at offsets rather than through a cursor. The futures own their
+/// buffers, so they can be sent to other tasks.
+pub trait AsyncPage: Clone {
+    fn index(&self) -> PageIndex;
+
+    /// Reads `len` bytes from `offset`, fewer when the page ends before.
+    fn read_at(&self, offset: PageSize, len: PageSize) -> impl Future<Output = io::Result<Vec<u8>>> + Send + 'static;
+
+    /// Writes `data` from `offset`, returning the number of bytes written, fewer when the page
+    /// ends before.
+    fn write_at(&self, offset: PageSize, data: Vec<u8>) -> impl Future<Output = io::Result<usize>> + Send + 'static;
+}
+
+fn read_at<P: Pager>(pager: &P, page_index: PageIndex, offset: PageSize, len: PageSize) -> io::Result<Vec<u8>> {
+    let mut page = pager.page(page_index)?;
+    page.seek(SeekFrom::Start(offset as u64))?;
+    let mut data = vec![0u8; len.min(pager.page_size().saturating_sub(offset)) as usize];
+    page.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn write_at<P: Pager>(pager: &P, page_index: PageIndex, offset: PageSize, data: &[u8]) -> io::Result<usize> {
+    let mut page = pager.page(page_index)?;
+    page.seek(SeekFrom::Start(offset as u64))?;
+    let written = data.len().min(pager.page_size().saturating_sub(offset) as usize);
+    page.write_all(&data[..written])?;
+    page.flush()?;
+    Ok(written)
+}
+
+/// Memory pages are accessed without blocking, so the futures are ready once created.
+impl AsyncPager for Arc<MemoryPager> {
+    type Page = AsyncMemoryPage;
+
+    fn page_size(&self) -> PageSize {
+        MemoryPager::page_size(self)
+    }
+
+    fn page(&self, page_index: PageIndex) -> io::Result<Self::Page> {
+        Ok(AsyncMemoryPage {
+            index: page_index,
+            pager: self.clone(),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct AsyncMemoryPage {
+    index: PageIndex,
+    pager: Arc<MemoryPager>,
+}
+
+impl AsyncPage for AsyncMemoryPage {
+    fn index(&self) -> PageIndex {
+        self.index
+    }
+
+    fn read_at(&self, offset: PageSize, len: PageSize) -> impl Future<Output = io::Result<Vec<u8>>> + Send + 'static {
+        ready(read_at(&*self.pager, self.index, offset, len))
+    }
+
+    fn write_at(&self, offset: PageSize, data: Vec<u8>) -> impl Future<Output = io::Result<usize>> + Send + 'static {
+        ready(write_at(&*self.pager, self.index, offset, &data))
+    }
+}
+
+type Job<P> = Box<dyn FnOnce(&P) + Send>;
+
+/// A pager moved to a worker thread of its own, which runs the page accesses in the order they
+/// were started, e.g. to use a `FilePager` from async code. Works with any executor, as no
+/// runtime is needed to drive the I/O.
+///
+/// The worker stops once the pager and all its pages were dropped.
+pub struct ThreadPager<P> {
+    page_size: PageSize,
+    jobs: mpsc::Sender<Job<P>>,
+}
+
+impl<P> Clone for ThreadPager<P> {
+    fn clone(&self) -> Self {
+        Self {
+            page_size: self.page_size,
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+
+impl<P: Pager + Send + 'static> ThreadPager<P> {
+    pub fn new(pager: P) -> io::Result<Self> {
+        let page_size = pager.page_size();
+        let (jobs, receiver) = mpsc::channel::<Job<P>>();
+        thread::Builder::new()
+            .name("datastore-pager".into())
+            .spawn(move || {
+                for job in receiver {
+                    job(&pager);
+                }
+            })?;
+        Ok(Self { page_size, jobs })
+    }
+}
+
+impl<P> ThreadPager<P> {
+    /// Runs `operation` on the worker thread, e.g. to sync the pager.
+    pub fn run<T: Send + 'static>(&self, operation: impl FnOnce(&P) -> io::Result<T> + Send + 'static) -> Reply<T> {
+        run(&self.jobs, operation)
+    }
+}
+
+fn run<P, T: Send + 'static>(jobs: &mpsc::Sender<Job<P>>, operation: impl FnOnce(&P) -> io::Result<T> + Send + 'static) -> Reply<T> {
+    let (sender, reply) = reply_channel();
+    // A failed send drops the sender, which resolves the reply with an error.
+    let _ = jobs.send(Box::new(move |pager| sender.send(operation(pager))));
+    reply
+}
+
+impl<P: Pager + 'static> AsyncPager for ThreadPager<P> {
+    type Page = ThreadPage<P>;
+
+    fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    fn page(&self, page_index: PageIndex) -> io::Result<Self::Page> {
+        Ok(ThreadPage {
+            index: page_index,
+            jobs: self.jobs.clone(),
+        })
+    }
+}
+
+pub struct ThreadPage<P> {
+    index: PageIndex,
+    jobs: mpsc::Sender<Job<P>>,
+}
+
+impl<P> Clone for ThreadPage<P> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+
+impl<P: Pager + 'static> AsyncPage for ThreadPage<P> {
+    fn index(&self) -> PageIndex {
+        self.index
+    }
+
+    fn read_at(&self, offset: PageSize, len: PageSize) -> impl Future<Output = io::Result<Vec<u8>>> + Send + 'static {
+        let index = self.index;
+        run(&self.jobs, move |pager| read_at(pager, index, offset, len))
+    }
+
+    fn write_at(&self, offset: PageSize, data: Vec<u8>) -> impl Future<Output = io::Result<usize>> + Send + 'static {
+        let index = self.index;
+        run(&self.jobs, move |pager| write_at(pager, index, offset, &data))
+    }
+}
+
+/// A `Pager` over an `AsyncPager`, blocking the calling thread on every page access, so the
+/// blocking `Book` implementations can run on async pagers until async counterparts exist.
+pub struct BlockingPager<A> {
+    pager: A,
+}
+
+impl<A: AsyncPager> BlockingPager<A> {
+    pub fn new(pager: A) -> Self {
+        Self { pager }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.pager
+    }
+}
+
+impl<A: AsyncPager> Pager for BlockingPager<A> {
+    type Page<'a> = BlockingPage<A::Page> where Self: 'a;
+
+    fn page_size(&self) -> PageSize {
+        self.pager.page_size()
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        Ok(BlockingPage {
+            page: self.pager.page(page_index)?,
+            page_size: self.pager.page_size(),
+            offset: 0,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct BlockingPage<T> {
+    page: T,
+    page_size: PageSize,
+    offset: u64,
+}
+
+impl<T: AsyncPage> Page for BlockingPage<T> {
+    fn index(&self) -> PageIndex {
+        self.page.index()
+    }
+}
+
+impl<T: AsyncPage> Read for BlockingPage<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_size = (self.page_size as u64 - self.offset).min(buf.len() as u64) as PageSize;
+        if read_size == 0 {
+            return Ok(0);
+        }
+        let data = block_on(self.page.read_at(self.offset as PageSize, read_size))?;
+        buf[..data.len()].copy_from_slice(&data);
+        self.offset += data.len() as u64;
+        Ok(data.len())
+    }
+}
+
+impl<T: AsyncPage> Write for BlockingPage<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write_size = (self.page_size as u64 - self.offset).min(buf.len() as u64) as usize;
+        if write_size == 0 {
+            return Ok(0);
+        }
+        let written = block_on(self.page.write_at(self.offset as PageSize, buf[..write_size].to_vec()))?;
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: AsyncPage> Seek for BlockingPage<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let page_size = self.page_size as u64;
+        self.offset = seek_in_page(self.offset, page_size, pos)?;
+        Ok(self.offset)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        Ok(())
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls `future` to completion on the calling thread, parking it while pending.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+struct ReplyState<T, E> {
+    result: Option<Result<T, E>>,
+    waker: Option<Waker>,
+}
+
+/// The result of an operation running on a worker thread. The pagers reply with `io::Error`s, the
+/// tables with `crate::Error`s.
+pub struct Reply<T, E = io::Error> {
+    shared: Arc<Mutex<ReplyState<T, E>>>,
+}
+
+pub(crate) fn reply_channel<T, E: From<io::Error>>() -> (ReplySender<T, E>, Reply<T, E>) {
+    let shared = Arc::new(Mutex::new(ReplyState { result: None, waker: None }));
+    (ReplySender(Some(shared.clone())), Reply { shared })
+}
+
+impl<T, E: From<io::Error>> Future for Reply<T, E> {
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let Ok(mut state) = self.shared.lock() else {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError).into()));
+        };
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+/// Resolves its `Reply`, with an error if dropped unsent, e.g. because the worker panicked.
+pub(crate) struct ReplySender<T, E: From<io::Error>>(Option<Arc<Mutex<ReplyState<T, E>>>>);
+
+impl<T, E: From<io::Error>> ReplySender<T, E> {
+    pub(crate) fn send(mut self, result: Result<T, E>) {
+        if let Some(shared) = self.0.take() {
+            resolve(&shared, result);
+        }
+    }
+}
+
+impl<T, E: From<io::Error>> Drop for ReplySender<T, E> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.0.take() {
+            resolve(&shared, Err(io::Error::new(io::ErrorKind::BrokenPipe, "Worker thread stopped before replying").into()));
+        }
+    }
+}
+
+fn resolve<T, E>(shared: &Mutex<ReplyState<T, E>>, result: Result<T, E>) {
+    let Ok(mut state) = shared.lock() else {
+        return;
+    };
+    state.result = Some(result);
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{book::{Book, pager::{PagerBook, PagerBookMemoryHeader}}, pager::fs::FilePager};
+
+    #[test]
+    fn test_async_pagers() -> io::Result<()> {
+        let memory = Arc::new(MemoryPager::new(64));
+        let file = ThreadPager::new(FilePager::new(tempfile::tempfile()?, 64)?)?;
+        block_on(async {
+            let page = memory.page(1)?;
+            assert_eq!(page.write_at(60, b"memory".to_vec()).await?, 4);
+            assert_eq!(page.read_at(58, 16).await?, b"\0\0memo");
+
+            let page = file.page(2)?;
+            assert_eq!(page.read_at(0, 8).await?, [0u8; 8]);
+            assert_eq!(page.write_at(8, b"file".to_vec()).await?, 4);
+            assert_eq!(page.read_at(8, 4).await?, b"file");
+            file.run(|pager| pager.sync()).await
+        })?;
+        let mut buffer = [0u8; 4];
+        let mut page = Pager::page(&*memory, 1)?;
+        page.seek(SeekFrom::Start(60))?;
+        page.read_exact(&mut buffer)?;
+        assert_eq!(&buffer, b"memo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_pager_book() -> io::Result<()> {
+        let pager = ThreadPager::new(MemoryPager::new(16))?;
+        let book = PagerBook::new(BlockingPager::new(pager), PagerBookMemoryHeader::default());
+        let mut section = book.section(3);
+        section.write_all(&[5u8; 40])?;
+        let mut buffer = [0u8; 40];
+        section.rewind()?;
+        section.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [5u8; 40]);
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use std::{io::{self, Read, Seek, SeekFrom, Write}, sync::Mutex};
+
+use crate::{lru::Lru, pager::{Page, PageIndex, PageSize, Pager, seek_in_page}};
+use crate::PoisonedLockError;
+
+struct CachedPage {
+    data: Box<[u8]>,
+    /// Written since it was last written back.
+    dirty: bool,
+}
+
+/// Wraps a pager, keeping the contents of up to `capacity` of the most recently used pages.
+/// Reads and writes are copies from and to the cached page, so the many small accesses of a
+/// `PagerBook` section reach the inner pager once per page. Written pages are written back when
+/// evicted, on `sync` and when the pager is dropped.
+pub struct CachingPager<P: Pager> {
+    pager: P,
+    capacity: usize,
+    cache: Mutex<Lru<PageIndex, CachedPage>>,
+}
+
+impl<P: Pager> CachingPager<P> {
+    /// Caches at least one page, even with a `capacity` of zero.
+    pub fn new(pager: P, capacity: usize) -> Self {
+        Self {
+            pager,
+            capacity: capacity.max(1),
+            cache: Mutex::new(Lru::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.pager
+    }
+
+    /// Writes back the pages written since they were cached or last written back. The inner
+    /// pager is not synced, e.g. call `FilePager::sync` afterwards for durability.
+    pub fn sync(&self) -> io::Result<()> {
+        let mut cache = self.cache.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        for (index, page) in cache.iter_mut() {
+            if page.dirty {
+                self.write_back(*index, &page.data)?;
+                page.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of cached pages written since they were last written back.
+    pub fn dirty_pages(&self) -> io::Result<usize> {
+        let cache = self.cache.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        Ok(cache.iter().filter(|(_, page)| page.dirty).count())
+    }
+
+    fn write_back(&self, page_index: PageIndex, data: &[u8]) -> io::Result<()> {
+        let mut page = self.pager.page(page_index)?;
+        page.write_all(data)?;
+        page.flush()
+    }
+
+    /// Runs `access` on the cached page, reading it from the inner pager and evicting the least
+    /// recently used page if it is not cached.
+    fn with_page<T>(&self, page_index: PageIndex, access: impl FnOnce(&mut CachedPage) -> T) -> io::Result<T> {
+        let mut pages = self.cache.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        if !pages.contains(&page_index) {
+            while pages.len() >= self.capacity {
+                let Some((&evicted, page)) = pages.iter_by_use().next() else {
+                    break;
+                };
+                // Kept cached if writing it back fails, so nothing written is lost.
+                if page.dirty {
+                    self.write_back(evicted, &page.data)?;
+                }
+                pages.remove(&evicted);
+            }
+            let mut data = vec![0u8; self.pager.page_size() as usize].into_boxed_slice();
+            self.pager.page(page_index)?.read_exact(&mut data)?;
+            pages.insert(page_index, CachedPage { data, dirty: false });
+        }
+        Ok(access(pages.get_mut(&page_index).unwrap()))
+    }
+}
+
+impl<P: Pager> Drop for CachingPager<P> {
+    fn drop(&mut self) {
+        let _ = self.sync();
+    }
+}
+
+impl<P: Pager> Pager for CachingPager<P> {
+    type Page<'a> = CachingPage<'a, P> where Self: 'a;
+
+    fn page_size(&self) -> PageSize {
+        self.pager.page_size()
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        Ok(CachingPage {
+            index: page_index,
+            pager: self,
+            offset: 0,
+        })
+    }
+}
+
+pub struct CachingPage<'a, P: Pager> {
+    index: PageIndex,
+    pager: &'a CachingPager<P>,
+    offset: u64,
+}
+
+impl<P: Pager> Clone for CachingPage<'_, P> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            pager: self.pager,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<P: Pager> Page for CachingPage<'_, P> {
+    fn index(&self) -> PageIndex {
+        self.index
+    }
+}
+
+impl<P: Pager> Read for CachingPage<'_, P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let read_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if read_size == 0 {
+            return Ok(0);
+        }
+        let start = self.offset as usize;
+        self.pager.with_page(self.index, |page| {
+            buf[..read_size].copy_from_slice(&page.data[start..start + read_size]);
+        })?;
+        self.offset += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl<P: Pager> Write for CachingPage<'_, P> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let write_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if write_size == 0 {
+            return Ok(0);
+        }
+        let start = self.offset as usize;
+        self.pager.with_page(self.index, |page| {
+            page.data[start..start + write_size].copy_from_slice(&buf[..write_size]);
+            page.dirty = true;
+        })?;
+        self.offset += write_size as u64;
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<P: Pager> Seek for CachingPage<'_, P> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let page_size = self.pager.page_size() as u64;
+        self.offset = seek_in_page(self.offset, page_size, pos)?;
+        Ok(self.offset)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        Ok(())
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pager::memory::MemoryPager, testing::counting::CountingPager};
+
+    #[test]
+    fn test_caching_pager() -> io::Result<()> {
+        let pager = CachingPager::new(CountingPager::new(MemoryPager::new(64)), 2);
+        for _ in 0..3 {
+            let mut page = pager.page(0)?;
+            page.write_all(b"cached")?;
+            let mut buffer = [0u8; 6];
+            page.rewind()?;
+            page.read_exact(&mut buffer)?;
+            assert_eq!(&buffer, b"cached");
+        }
+        // Read once, and not written back yet.
+        let counts = pager.inner().counts()?;
+        assert_eq!((counts.page_requests, counts.bytes_written), (1, 0));
+        assert_eq!(pager.dirty_pages()?, 1);
+
+        // Evicting the least recently used page writes it back.
+        pager.page(1)?.write_all(&[1])?;
+        pager.page(0)?.read_exact(&mut [0u8; 1])?;
+        pager.page(2)?.write_all(&[2])?;
+        let mut buffer = [0u8; 1];
+        pager.inner().inner().page(1)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [1]);
+        assert_eq!(pager.dirty_pages()?, 2);
+
+        pager.sync()?;
+        assert_eq!(pager.dirty_pages()?, 0);
+        let mut buffer = [0u8; 6];
+        pager.inner().inner().page(0)?.read_exact(&mut buffer)?;
+        assert_eq!(&buffer, b"cached");
+        pager.inner().inner().page(2)?.read_exact(&mut buffer[..1])?;
+        assert_eq!(buffer[0], 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,439 @@
+use std::{io::{self, Read, Seek, SeekFrom, Write}, sync::{Mutex, MutexGuard}};
+
+use crate::pager::{Page, PageIndex, PageSize, Pager, seek_in_page};
+use crate::PoisonedLockError;
+
+/// Authenticated encryption of whole pages for an `EncryptedPager`, e.g. AES-GCM or
+/// XChaCha20-Poly1305 keyed with the key material of the store.
+pub trait PageCipher {
+    /// Number of bytes a sealed page is longer than its plaintext, e.g. for a nonce and a tag.
+    fn overhead(&self) -> PageSize;
+
+    /// Encrypts `page` into `sealed`, which is `overhead` bytes longer. `page_index` should be
+    /// authenticated along with it, so sealed pages cannot be swapped unnoticed.
+    fn seal(&self, page_index: PageIndex, page: &[u8], sealed: &mut [u8]) -> io::Result<()>;
+
+    /// Decrypts `sealed` into `page`, failing with `InvalidData` if it was not sealed for
+    /// `page_index` with the same key.
+    fn open(&self, page_index: PageIndex, sealed: &[u8], page: &mut [u8]) -> io::Result<()>;
+}
+
+/// Index under which the header is sealed, which no page of an `EncryptedPager` can have.
+const HEADER_PAGE_INDEX: PageIndex = PageIndex::MAX;
+
+/// Wraps a pager, sealing every page with a `PageCipher` before it is written, so nothing but
+/// ciphertext reaches the inner pager. Pages are `overhead` bytes smaller than the inner ones.
+///
+/// Inner page 0 holds a sealed header with the number of pages sealed so far, and page `n` is
+/// stored in inner page `n + 1`. The pages below that number must authenticate, so one that was
+/// zeroed fails with `InvalidData` like any other tampering. Pages past it read as zeros if
+/// they are all zeros on the inner pager, as they were never written. Like replaying an older
+/// sealed page, rolling the header back to an older count is not detected.
+///
+/// Every read and write decrypts the whole page, and every write seals and rewrites it, so it
+/// is best placed beneath a `PagerBook` with a page cache.
+pub struct EncryptedPager<P, C> {
+    pager: P,
+    cipher: C,
+    sealed_pages: Mutex<PageIndex>,
+}
+
+impl<P: Pager, C: PageCipher> EncryptedPager<P, C> {
+    pub fn new(pager: P, cipher: C) -> io::Result<Self> {
+        if cipher.overhead() >= pager.page_size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Page size must exceed the overhead of the cipher"));
+        }
+        let mut encrypted = Self { pager, cipher, sealed_pages: Mutex::new(0) };
+        let sealed = encrypted.read_sealed(0)?;
+        if sealed.iter().any(|&byte| byte != 0) {
+            let mut header = vec![0u8; encrypted.page_size() as usize];
+            encrypted.cipher.open(HEADER_PAGE_INDEX, &sealed, &mut header)?;
+            let sealed_pages = header.get(..4)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Encrypted pager header is too short"))?;
+            *encrypted.sealed_pages.get_mut().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))? =
+                PageIndex::from_le_bytes(sealed_pages.try_into().unwrap());
+        }
+        Ok(encrypted)
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.pager
+    }
+
+    pub fn into_inner(self) -> P {
+        self.pager
+    }
+
+    fn sealed_pages(&self) -> io::Result<MutexGuard<'_, PageIndex>> {
+        self.sealed_pages.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))
+    }
+
+    fn read_sealed(&self, inner_index: PageIndex) -> io::Result<Vec<u8>> {
+        let mut sealed = vec![0u8; self.pager.page_size() as usize];
+        let mut page = self.pager.page(inner_index)?;
+        page.read_exact(&mut sealed)?;
+        Ok(sealed)
+    }
+
+    fn write_sealed(&self, inner_index: PageIndex, sealed: &[u8]) -> io::Result<()> {
+        let mut page = self.pager.page(inner_index)?;
+        page.write_all(sealed)?;
+        page.flush()
+    }
+
+    fn read_page(&self, page_index: PageIndex, page: &mut P::Page<'_>) -> io::Result<Vec<u8>> {
+        let mut sealed = vec![0u8; self.pager.page_size() as usize];
+        page.rewind()?;
+        page.read_exact(&mut sealed)?;
+        let mut plaintext = vec![0u8; self.page_size() as usize];
+        // Pages past the header's count that are still zeros were never written. Ones that are
+        // not were sealed before a crash kept the header from being updated, so they must open.
+        if page_index >= *self.sealed_pages()? && sealed.iter().all(|&byte| byte == 0) {
+            return Ok(plaintext);
+        }
+        self.cipher.open(page_index, &sealed, &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    fn write_page(&self, page_index: PageIndex, page: &mut P::Page<'_>, plaintext: &[u8]) -> io::Result<()> {
+        let mut sealed = vec![0u8; self.pager.page_size() as usize];
+        self.cipher.seal(page_index, plaintext, &mut sealed)?;
+        // Held until the header is written, so pages are sealed and counted one writer at a time.
+        let mut sealed_pages = self.sealed_pages()?;
+        if page_index < *sealed_pages {
+            drop(sealed_pages);
+            page.rewind()?;
+            return page.write_all(&sealed);
+        }
+
+        // Seal the skipped pages too, so that every page below the count has been sealed.
+        let empty = vec![0u8; self.page_size() as usize];
+        let mut sealed_empty = vec![0u8; self.pager.page_size() as usize];
+        for skipped_index in *sealed_pages..page_index {
+            if self.read_sealed(skipped_index + 1)?.iter().any(|&byte| byte != 0) {
+                continue;
+            }
+            self.cipher.seal(skipped_index, &empty, &mut sealed_empty)?;
+            self.write_sealed(skipped_index + 1, &sealed_empty)?;
+        }
+        page.rewind()?;
+        page.write_all(&sealed)?;
+        page.flush()?;
+
+        let mut header = vec![0u8; self.page_size() as usize];
+        header[..4].copy_from_slice(&(page_index + 1).to_le_bytes());
+        self.cipher.seal(HEADER_PAGE_INDEX, &header, &mut sealed)?;
+        self.write_sealed(0, &sealed)?;
+        *sealed_pages = page_index + 1;
+        Ok(())
+    }
+}
+
+impl<P: Pager, C: PageCipher> Pager for EncryptedPager<P, C> {
+    type Page<'a> = EncryptedPage<'a, P, C> where Self: 'a;
+
+    fn page_size(&self) -> PageSize {
+        self.pager.page_size() - self.cipher.overhead()
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        let inner_index = page_index.checked_add(1)
+            .filter(|_| page_index != HEADER_PAGE_INDEX)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Page index is out of range"))?;
+        Ok(EncryptedPage {
+            index: page_index,
+            page: self.pager.page(inner_index)?,
+            pager: self,
+            offset: 0,
+        })
+    }
+}
+
+pub struct EncryptedPage<'a, P: Pager + 'a, C> {
+    index: PageIndex,
+    page: P::Page<'a>,
+    pager: &'a EncryptedPager<P, C>,
+    offset: u64,
+}
+
+impl<'a, P: Pager + 'a, C> Clone for EncryptedPage<'a, P, C> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            page: self.page.clone(),
+            pager: self.pager,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<P: Pager, C: PageCipher> Page for EncryptedPage<'_, P, C> {
+    fn index(&self) -> PageIndex {
+        self.index
+    }
+}
+
+impl<P: Pager, C: PageCipher> Read for EncryptedPage<'_, P, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let read_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if read_size == 0 {
+            return Ok(0);
+        }
+        let plaintext = self.pager.read_page(self.index, &mut self.page)?;
+        let start = self.offset as usize;
+        buf[..read_size].copy_from_slice(&plaintext[start..start + read_size]);
+        self.offset += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl<P: Pager, C: PageCipher> Write for EncryptedPage<'_, P, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let write_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if write_size == 0 {
+            return Ok(0);
+        }
+        let mut plaintext = self.pager.read_page(self.index, &mut self.page)?;
+        let start = self.offset as usize;
+        plaintext[start..start + write_size].copy_from_slice(&buf[..write_size]);
+        self.pager.write_page(self.index, &mut self.page, &plaintext)?;
+        self.offset += write_size as u64;
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.page.flush()
+    }
+}
+
+impl<P: Pager, C: PageCipher> Seek for EncryptedPage<'_, P, C> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let page_size = self.pager.page_size() as u64;
+        self.offset = seek_in_page(self.offset, page_size, pos)?;
+        Ok(self.offset)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        Ok(())
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+/// `PageCipher` sealing with XChaCha20-Poly1305 under a random nonce, which is stored in front
+/// of the ciphertext and followed by the tag.
+#[cfg(feature = "encryption")]
+pub struct XChaCha20Poly1305Cipher {
+    cipher: chacha20poly1305::XChaCha20Poly1305,
+}
+
+#[cfg(feature = "encryption")]
+impl XChaCha20Poly1305Cipher {
+    const NONCE_SIZE: usize = 24;
+    const TAG_SIZE: usize = 16;
+
+    pub fn new(key: &[u8; 32]) -> Self {
+        use chacha20poly1305::KeyInit;
+        Self { cipher: chacha20poly1305::XChaCha20Poly1305::new(key.into()) }
+    }
+
+    fn check_sizes(page: usize, sealed: usize) -> io::Result<()> {
+        if sealed != page + Self::NONCE_SIZE + Self::TAG_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Sealed page size does not match the page size"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl PageCipher for XChaCha20Poly1305Cipher {
+    fn overhead(&self) -> PageSize {
+        (Self::NONCE_SIZE + Self::TAG_SIZE) as PageSize
+    }
+
+    fn seal(&self, page_index: PageIndex, page: &[u8], sealed: &mut [u8]) -> io::Result<()> {
+        use chacha20poly1305::{AeadCore, AeadInPlace, XChaCha20Poly1305, aead::OsRng};
+        Self::check_sizes(page.len(), sealed.len())?;
+        let (nonce, rest) = sealed.split_at_mut(Self::NONCE_SIZE);
+        let (body, tag) = rest.split_at_mut(page.len());
+        let generated = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        nonce.copy_from_slice(&generated);
+        body.copy_from_slice(page);
+        let computed = self.cipher.encrypt_in_place_detached(&generated, &page_index.to_le_bytes(), body)
+            .map_err(|_| io::Error::other("Failed to seal page"))?;
+        tag.copy_from_slice(&computed);
+        Ok(())
+    }
+
+    fn open(&self, page_index: PageIndex, sealed: &[u8], page: &mut [u8]) -> io::Result<()> {
+        use chacha20poly1305::{AeadInPlace, Tag, XNonce};
+        Self::check_sizes(page.len(), sealed.len())?;
+        let (nonce, rest) = sealed.split_at(Self::NONCE_SIZE);
+        let (body, tag) = rest.split_at(page.len());
+        page.copy_from_slice(body);
+        self.cipher.decrypt_in_place_detached(XNonce::from_slice(nonce), &page_index.to_le_bytes(), page, Tag::from_slice(tag))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Page failed authentication"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::RwLock};
+
+    use super::*;
+    use crate::{book::{Book, pager::PagerBook}, crc32::Crc32, pager::memory::MemoryPager};
+
+    /// Not a cipher at all: XORs with the key and the page index, and appends a CRC as the tag.
+    struct XorCipher(u8);
+
+    impl PageCipher for XorCipher {
+        fn overhead(&self) -> PageSize {
+            4
+        }
+
+        fn seal(&self, page_index: PageIndex, page: &[u8], sealed: &mut [u8]) -> io::Result<()> {
+            let (body, tag) = sealed.split_at_mut(page.len());
+            for (sealed, byte) in body.iter_mut().zip(page) {
+                *sealed = byte ^ self.0 ^ page_index as u8;
+            }
+            let mut crc = Crc32::new();
+            crc.update(&page_index.to_le_bytes());
+            crc.update(page);
+            tag.copy_from_slice(&crc.finalize().to_le_bytes());
+            Ok(())
+        }
+
+        fn open(&self, page_index: PageIndex, sealed: &[u8], page: &mut [u8]) -> io::Result<()> {
+            let (body, tag) = sealed.split_at(page.len());
+            for (byte, sealed) in page.iter_mut().zip(body) {
+                *byte = sealed ^ self.0 ^ page_index as u8;
+            }
+            let mut crc = Crc32::new();
+            crc.update(&page_index.to_le_bytes());
+            crc.update(page);
+            if tag != crc.finalize().to_le_bytes() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Page failed authentication"));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_encrypted_pager() -> io::Result<()> {
+        let pager = EncryptedPager::new(MemoryPager::new(68), XorCipher(0x5a))?;
+        assert_eq!(pager.page_size(), 64);
+
+        let mut page = pager.page(3)?;
+        let mut buffer = [0u8; 64];
+        page.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [0u8; 64]);
+        page.seek(SeekFrom::Start(10))?;
+        page.write_all(b"secret")?;
+        page.seek(SeekFrom::Start(8))?;
+        let mut read = [0u8; 8];
+        page.read_exact(&mut read)?;
+        assert_eq!(&read, b"\0\0secret");
+
+        let sealed = pager.inner().export(|pages| {
+            pages.map(|(index, page)| (index, page.to_vec())).collect::<Vec<_>>()
+        })?;
+        // The header and pages 0 to 3, the skipped ones sealed empty.
+        assert_eq!(sealed.len(), 5);
+        assert!(!sealed.iter().any(|(_, page)| page.windows(6).any(|window| window == b"secret")));
+
+        // Tampering with the ciphertext, or reading it with another key, fails.
+        let mut inner = pager.inner().page(4)?;
+        inner.write_all(&[1])?;
+        assert_eq!(pager.page(3)?.read(&mut read).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(EncryptedPager::new(pager.into_inner(), XorCipher(0)).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+
+        assert_eq!(EncryptedPager::new(MemoryPager::new(4), XorCipher(0)).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidInput));
+        Ok(())
+    }
+
+    #[test]
+    fn test_zeroed_page_is_rejected() -> io::Result<()> {
+        let pager = EncryptedPager::new(MemoryPager::new(68), XorCipher(0x5a))?;
+        pager.page(0)?.write_all(b"first")?;
+        pager.page(2)?.write_all(b"third")?;
+
+        // Page 1 was skipped but sealed empty, so it reads as zeros until it is tampered with.
+        let mut read = [0u8; 5];
+        pager.page(1)?.read_exact(&mut read)?;
+        assert_eq!(read, [0u8; 5]);
+        pager.page(4)?.read_exact(&mut read)?;
+        assert_eq!(read, [0u8; 5]);
+
+        for inner_index in [1, 2, 3] {
+            pager.inner().page(inner_index)?.write_all(&[0u8; 68])?;
+            assert_eq!(pager.page(inner_index - 1)?.read(&mut read).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_pager_reopen() -> io::Result<()> {
+        let pager = EncryptedPager::new(MemoryPager::new(68), XorCipher(0x5a))?;
+        pager.page(1)?.write_all(b"secret")?;
+
+        let pager = EncryptedPager::new(pager.into_inner(), XorCipher(0x5a))?;
+        let mut read = [0u8; 6];
+        pager.page(1)?.read_exact(&mut read)?;
+        assert_eq!(&read, b"secret");
+        pager.inner().page(1)?.write_all(&[0u8; 68])?;
+        assert_eq!(pager.page(0)?.read(&mut read).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A tampered header fails the open.
+        let inner = pager.into_inner();
+        inner.page(0)?.write_all(&[1])?;
+        assert_eq!(EncryptedPager::new(inner, XorCipher(0x5a)).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_pager_book() -> io::Result<()> {
+        let pager = EncryptedPager::new(MemoryPager::new(36), XorCipher(0x17))?;
+        let book = PagerBook::new(pager, RwLock::new(BTreeMap::new()));
+        let data: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        book.section(1).write_all(&data)?;
+        let mut read = vec![0u8; data.len()];
+        book.section(1).read_exact(&mut read)?;
+        assert_eq!(read, data);
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_xchacha20poly1305_cipher() -> io::Result<()> {
+        let pager = EncryptedPager::new(MemoryPager::new(104), XChaCha20Poly1305Cipher::new(&[7; 32]))?;
+        assert_eq!(pager.page_size(), 64);
+        pager.page(0)?.write_all(b"secret")?;
+        pager.page(1)?.write_all(b"secret")?;
+        let mut read = [0u8; 6];
+        pager.page(0)?.read_exact(&mut read)?;
+        assert_eq!(&read, b"secret");
+
+        let sealed = pager.inner().export(|pages| {
+            pages.map(|(_, page)| page.to_vec()).collect::<Vec<_>>()
+        })?;
+        assert!(!sealed.iter().any(|page| page.windows(6).any(|window| window == b"secret")));
+        // Random nonces, so equal pages do not seal alike.
+        assert_ne!(sealed[1], sealed[2]);
+
+        // Tampering with the ciphertext, swapping pages or using another key fails.
+        let mut inner = pager.inner().page(1)?;
+        inner.seek(SeekFrom::Start(30))?;
+        inner.write_all(&[sealed[1][30] ^ 1])?;
+        assert_eq!(pager.page(0)?.read(&mut read).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        pager.inner().page(1)?.write_all(&sealed[2])?;
+        assert_eq!(pager.page(0)?.read(&mut read).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let inner = pager.into_inner();
+        assert_eq!(EncryptedPager::new(inner, XChaCha20Poly1305Cipher::new(&[8; 32])).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        Ok(())
+    }
+}
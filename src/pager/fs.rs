@@ -1,44 +1,105 @@
 use std::{fs::File, io::{self, Read, Seek, SeekFrom, Write}, sync::Mutex};
 
-use crate::pager::{Page, PageSize, Pager};
+use crate::{pager::{Page, PageSize, Pager, seek_in_page}, vfs::VfsFile};
+use crate::PoisonedLockError;
 
 use super::PageIndex;
 
-struct FilePagerResource {
-    file: File,
+/// Alignment of the buffers, offsets and sizes of direct I/O, see `FilePager::new_direct`.
+pub const DIRECT_IO_ALIGNMENT: PageSize = 4096;
+
+struct FilePagerResource<F> {
+    file: F,
     size: u64,
+    /// Holds a whole page for direct I/O, `None` otherwise.
+    direct_buffer: Option<AlignedBuffer>,
 }
 
-pub struct FilePager {
+pub struct FilePager<F = File> {
     page_size: PageSize,
-    resource: Mutex<FilePagerResource>,
+    resource: Mutex<FilePagerResource<F>>,
+}
+
+/// A zeroed buffer of `len` bytes starting at a multiple of `DIRECT_IO_ALIGNMENT`.
+struct AlignedBuffer {
+    storage: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    fn new(len: usize) -> Self {
+        let alignment = DIRECT_IO_ALIGNMENT as usize;
+        let storage = vec![0u8; len + alignment];
+        let start = storage.as_ptr().align_offset(alignment);
+        Self { storage, start, len }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.storage[self.start..self.start + self.len]
+    }
 }
 
-#[derive(Clone)]
-pub struct FilePage<'a> {
+pub struct FilePage<'a, F = File> {
     index: PageIndex,
-    pager: &'a FilePager,
+    pager: &'a FilePager<F>,
     page_offset: u64,
     file_offset: u64,
 }
 
-impl FilePager {
-    pub fn new(file: File, page_size: PageSize) -> io::Result<Self> {
-        let size = file.metadata()?.len();
+impl<F> Clone for FilePage<'_, F> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            pager: self.pager,
+            page_offset: self.page_offset,
+            file_offset: self.file_offset,
+        }
+    }
+}
+
+impl<F: VfsFile> FilePager<F> {
+    pub fn new(file: F, page_size: PageSize) -> io::Result<Self> {
+        let size = file.len()?;
         Ok(Self {
             page_size,
-            resource: Mutex::new(FilePagerResource { file, size }),
+            resource: Mutex::new(FilePagerResource { file, size, direct_buffer: None }),
         })
     }
 
+    /// Like `new`, for a file opened for direct I/O, e.g. with `StdFs::open_direct`. Each access
+    /// reads, and each write rewrites, its whole page through an aligned buffer, so `page_size`
+    /// has to be a multiple of `DIRECT_IO_ALIGNMENT`.
+    pub fn new_direct(file: F, page_size: PageSize) -> io::Result<Self> {
+        if page_size == 0 || !page_size.is_multiple_of(DIRECT_IO_ALIGNMENT) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Page size must be a multiple of the direct I/O alignment"));
+        }
+        let size = file.len()?;
+        Ok(Self {
+            page_size,
+            resource: Mutex::new(FilePagerResource {
+                file,
+                size,
+                direct_buffer: Some(AlignedBuffer::new(page_size as usize)),
+            }),
+        })
+    }
+
+    /// Picks up pages another handle appended to the file since it was opened.
+    pub fn refresh(&self) -> io::Result<()> {
+        let mut resource = self.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        resource.size = resource.size.max(resource.file.len()?);
+        Ok(())
+    }
+
     pub fn sync(&self) -> io::Result<()> {
-        let resource = self.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
+        let resource = self.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
         resource.file.sync_all()
     }
 }
 
-impl Pager for FilePager {
-    type Page<'a> = FilePage<'a> where Self: 'a;
+impl<F: VfsFile> Pager for FilePager<F> {
+    type Page<'a> = FilePage<'a, F> where Self: 'a;
 
     fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
         Ok(FilePage {
@@ -54,13 +115,50 @@ impl Pager for FilePager {
     }
 }
 
-impl Page for FilePage<'_> {
+impl<F: VfsFile> FilePagerResource<F> {
+    /// Reads the page starting at `page_start` into `direct_buffer`, zeros past the end of the file.
+    fn read_direct(&mut self, page_start: u64) -> io::Result<&mut [u8]> {
+        let Self { file, size, direct_buffer } = self;
+        let buffer = direct_buffer.as_mut().expect("Direct I/O buffer").as_mut_slice();
+        let mut read_size = 0;
+        if page_start < *size {
+            file.seek(SeekFrom::Start(page_start))?;
+            while read_size < buffer.len() {
+                match file.read(&mut buffer[read_size..]) {
+                    Ok(0) => break,
+                    Ok(size) => read_size += size,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        buffer[read_size..].fill(0);
+        Ok(buffer)
+    }
+
+    fn write_direct(&mut self, page_start: u64) -> io::Result<()> {
+        let Self { file, size, direct_buffer } = self;
+        let buffer = direct_buffer.as_mut().expect("Direct I/O buffer").as_mut_slice();
+        file.seek(SeekFrom::Start(page_start))?;
+        file.write_all(buffer)?;
+        *size = (*size).max(page_start + buffer.len() as u64);
+        Ok(())
+    }
+}
+
+impl<F: VfsFile> FilePage<'_, F> {
+    fn page_start(&self) -> u64 {
+        self.file_offset - self.page_offset
+    }
+}
+
+impl<F: VfsFile> Page for FilePage<'_, F> {
     fn index(&self) -> PageIndex {
         self.index
     }
 }
 
-impl Read for FilePage<'_> {
+impl<F: VfsFile> Read for FilePage<'_, F> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let page_size = self.pager.page_size() as u64;
         if self.page_offset == page_size {
@@ -70,7 +168,15 @@ impl Read for FilePage<'_> {
         if max_read_size == 0 {
             return Ok(0);
         }
-        let mut resource = self.pager.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
+        let mut resource = self.pager.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        if resource.direct_buffer.is_some() {
+            let page_offset = self.page_offset as usize;
+            let page = resource.read_direct(self.page_start())?;
+            buf[..max_read_size].copy_from_slice(&page[page_offset..page_offset + max_read_size]);
+            self.page_offset += max_read_size as u64;
+            self.file_offset += max_read_size as u64;
+            return Ok(max_read_size);
+        }
         let read_size = if self.file_offset >= resource.size {
             buf[..max_read_size].fill(0);
             self.page_offset += max_read_size as u64;
@@ -86,14 +192,23 @@ impl Read for FilePage<'_> {
     }
 }
 
-impl Write for FilePage<'_> {
+impl<F: VfsFile> Write for FilePage<'_, F> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let page_size = self.pager.page_size() as u64;
         let max_write_size = (page_size - self.page_offset).min(buf.len() as u64) as usize;
         if max_write_size == 0 {
             return Ok(0);
         }
-        let mut resource = self.pager.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
+        let mut resource = self.pager.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        if resource.direct_buffer.is_some() {
+            let page_offset = self.page_offset as usize;
+            let page = resource.read_direct(self.page_start())?;
+            page[page_offset..page_offset + max_write_size].copy_from_slice(&buf[..max_write_size]);
+            resource.write_direct(self.page_start())?;
+            self.page_offset += max_write_size as u64;
+            self.file_offset += max_write_size as u64;
+            return Ok(max_write_size);
+        }
         resource.file.seek(SeekFrom::Start(self.file_offset))?;
         let write_size = resource.file.write(&buf[..max_write_size])?;
         self.page_offset += write_size as u64;
@@ -105,29 +220,15 @@ impl Write for FilePage<'_> {
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let mut resource = self.pager.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
+        let mut resource = self.pager.resource.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
         resource.file.flush()
     }
 }
 
-impl Seek for FilePage<'_> {
+impl<F: VfsFile> Seek for FilePage<'_, F> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         let page_size = self.pager.page_size() as u64;
-        let (anchor, offset, is_forward) = match pos {
-            SeekFrom::Start(offset) => (0u64, offset, true),
-            SeekFrom::End(offset @ 0..) => (page_size, offset as u64, true),
-            SeekFrom::End(offset @ ..0) => (page_size, -offset as u64, false),
-            SeekFrom::Current(offset @ 0..) => (self.page_offset, offset as u64, true),
-            SeekFrom::Current(offset @ ..0) => (self.page_offset, -offset as u64, false),
-        };
-        let new_page_offset = if is_forward {
-            anchor.checked_add(offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek overflow"))?
-        } else {
-            anchor.checked_sub(offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek underflow"))?
-        };
-        if new_page_offset > page_size {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek out of bounds"));
-        }
+        let new_page_offset = seek_in_page(self.page_offset, page_size, pos)?;
         let new_file_offset = (self.index as u64).checked_mul(page_size).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "File offset overflow"))?
             .checked_add(new_page_offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "File offset overflow"))?;
         self.page_offset = new_page_offset;
@@ -147,15 +248,7 @@ impl Seek for FilePage<'_> {
 
     fn seek_relative(&mut self, offset: i64) -> io::Result<()> {
         let page_size = self.pager.page_size() as u64;
-        let new_page_offset = if offset >= 0 {
-            let new_page_offset = self.page_offset.checked_add(offset as u64).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek overflow"))?;
-            if new_page_offset > page_size {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek out of bounds"));
-            }
-            new_page_offset
-        } else {
-            self.page_offset.checked_sub(-offset as u64).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek underflow"))?
-        };
+        let new_page_offset = seek_in_page(self.page_offset, page_size, SeekFrom::Current(offset))?;
         self.page_offset = new_page_offset;
         self.file_offset = (self.index as u64).checked_mul(page_size).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "File offset overflow"))?
             .checked_add(new_page_offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "File offset overflow"))?;
@@ -247,6 +340,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_direct_file_pager() -> io::Result<()> {
+        assert_eq!(FilePager::new_direct(tempfile()?, 1000).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidInput));
+
+        let dir = tempfile::tempdir()?;
+        #[cfg(feature = "direct-io")]
+        let file = crate::vfs::StdFs.open_direct(&dir.path().join("pages.dat"))?;
+        #[cfg(not(feature = "direct-io"))]
+        let file = File::options().read(true).write(true).create_new(true).open(dir.path().join("pages.dat"))?;
+        let pager = FilePager::new_direct(file, DIRECT_IO_ALIGNMENT)?;
+
+        let mut page = pager.page(1)?;
+        page.seek(SeekFrom::Start(100))?;
+        page.write_all(b"direct")?;
+        page.seek(SeekFrom::Start(98))?;
+        let mut buffer = [1u8; 8];
+        page.read_exact(&mut buffer)?;
+        assert_eq!(&buffer, b"\0\0direct");
+        let mut buffer = vec![1u8; DIRECT_IO_ALIGNMENT as usize];
+        pager.page(2)?.read_exact(&mut buffer)?;
+        assert!(buffer.iter().all(|&byte| byte == 0));
+        pager.sync()?;
+        assert_eq!(std::fs::metadata(dir.path().join("pages.dat"))?.len(), 2 * DIRECT_IO_ALIGNMENT as u64);
+        Ok(())
+    }
+
     #[test]
     fn test_file_page_seeking() -> io::Result<()> {
         let file = tempfile()?;
@@ -1,26 +1,125 @@
-use std::{collections::BTreeMap, io::{self, Read, Seek, Write}, sync::{Arc, RwLock, RwLockReadGuard}};
+use std::{collections::{BTreeMap, BTreeSet}, fs::File, io::{self, Read, Seek, Write}, sync::{Arc, RwLock}};
 
-use crate::pager::{Page, PageIndex, PageSize, Pager};
+use crate::{lru::Lru, pager::{Page, PageIndex, PageSize, Pager, fs::FilePager, seek_in_page}};
+use crate::PoisonedLockError;
+
+type PageData = Arc<RwLock<Box<[u8]>>>;
+
+/// Pages evicted from a bounded `MemoryPager`, see `MemoryPager::with_spill`.
+struct Spill {
+    pager: FilePager<File>,
+    /// Pages written to the spill file, read back from it when accessed again.
+    pages: RwLock<BTreeSet<PageIndex>>,
+}
 
 pub struct MemoryPager {
     page_size: PageSize,
-    pages: RwLock<BTreeMap<PageIndex, Arc<RwLock<Box<[u8]>>>>>,
+    pages: RwLock<Lru<PageIndex, PageData>>,
+    /// Most pages held in memory, unbounded if `None`.
+    max_pages: Option<usize>,
+    spill: Option<Spill>,
 }
 
 impl MemoryPager {
     pub fn new(page_size: PageSize) -> Self {
         Self {
             page_size,
-            pages: RwLock::new(BTreeMap::new()),
+            pages: RwLock::new(Lru::new()),
+            max_pages: None,
+            spill: None,
+        }
+    }
+
+    /// Holds at most `max_pages` pages (at least one) in memory. Once as many are held, the least
+    /// recently accessed page not referenced by a live `MemoryPage` is evicted to the spill pager
+    /// for a new one; without a spill pager, adding one fails with `OutOfMemory`.
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages.max(1));
+        self
+    }
+
+    /// Evicts pages to `pager`, reading them back when accessed again. Pages of `pager` are
+    /// overwritten as pages are evicted, so it should be dedicated to this pager.
+    pub fn with_spill(mut self, pager: FilePager<File>) -> io::Result<Self> {
+        if pager.page_size() != self.page_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Spill pager has a different page size"));
+        }
+        self.spill = Some(Spill { pager, pages: RwLock::new(BTreeSet::new()) });
+        Ok(self)
+    }
+
+    /// Number of pages held in memory.
+    pub fn resident_pages(&self) -> io::Result<usize> {
+        Ok(self.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?.len())
+    }
+
+    /// Iterates the pages in order, reading the ones evicted to the spill pager back into buffers
+    /// without making them resident again.
+    pub fn export<T>(&self, callback: impl FnOnce(&mut dyn Iterator<Item = (PageIndex, &[u8])>) -> T) -> io::Result<T> {
+        let pages = self.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let resident = pages.iter()
+            .map(|(index, page)| page.read().map(|page| (*index, page)).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut spilled = Vec::new();
+        if let Some(spill) = &self.spill {
+            let spilled_pages = spill.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+            // A page read back stays in the spill file, but the resident copy is the current one.
+            for &index in spilled_pages.iter().filter(|index| !pages.contains(index)) {
+                let mut data = vec![0u8; self.page_size as usize].into_boxed_slice();
+                spill.pager.page(index)?.read_exact(&mut data)?;
+                spilled.push((index, data));
+            }
+        }
+        let mut all: BTreeMap<PageIndex, &[u8]> = resident.iter().map(|(index, page)| (*index, &page[..])).collect();
+        all.extend(spilled.iter().map(|(index, data)| (*index, &data[..])));
+        Ok(callback(&mut all.into_iter()))
+    }
+
+    /// The page held in memory, read back from the spill pager, or created if `create` is set.
+    fn load(&self, page_index: PageIndex, create: bool) -> io::Result<Option<PageData>> {
+        let mut pages = self.pages.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        if let Some(page) = pages.get_mut(&page_index) {
+            return Ok(Some(page.clone()));
+        }
+        let spilled = match &self.spill {
+            Some(spill) => spill.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?.contains(&page_index),
+            None => false,
+        };
+        if !spilled && !create {
+            return Ok(None);
         }
+        self.make_room(&mut pages)?;
+        let mut data = vec![0u8; self.page_size as usize].into_boxed_slice();
+        if spilled && let Some(spill) = &self.spill {
+            spill.pager.page(page_index)?.read_exact(&mut data)?;
+        }
+        let data = Arc::new(RwLock::new(data));
+        pages.insert(page_index, data.clone());
+        Ok(Some(data))
     }
 
-    pub fn export<T>(&self, callback: impl FnOnce(&mut dyn Iterator<Item = (PageIndex, RwLockReadGuard<Box<[u8]>>)>) -> T) -> io::Result<T> {
-        let pages = self.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
-        let pages = pages.iter()
-            .map(|(index, page)| page.read().map(|page|(*index, page)).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock")));
-        let pages: Vec<_> = pages.collect::<io::Result<_>>()?;
-        Ok(callback(&mut pages.into_iter()))
+    fn make_room(&self, pages: &mut Lru<PageIndex, PageData>) -> io::Result<()> {
+        let Some(max_pages) = self.max_pages else {
+            return Ok(());
+        };
+        while pages.len() >= max_pages {
+            let Some(spill) = &self.spill else {
+                return Err(io::Error::new(io::ErrorKind::OutOfMemory, "Memory pager is full"));
+            };
+            // Pages referenced by a `MemoryPage` would keep being written after the eviction.
+            let (&evicted, page) = pages.iter_by_use()
+                .find(|(_, page)| Arc::strong_count(page) == 1)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "Every page of the memory pager is in use"))?;
+            {
+                let data = page.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+                let mut page = spill.pager.page(evicted)?;
+                page.write_all(&data)?;
+                page.flush()?;
+            }
+            spill.pages.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?.insert(evicted);
+            pages.remove(&evicted);
+        }
+        Ok(())
     }
 }
 
@@ -32,12 +131,12 @@ impl Pager for MemoryPager {
     }
     
     fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
-        return Ok(MemoryPage {
+        Ok(MemoryPage {
             index: page_index,
             pager: self,
             page: None,
             offset: 0,
-        });
+        })
     }
 }
 
@@ -45,34 +144,37 @@ impl Pager for MemoryPager {
 pub struct MemoryPage<'a> {
     index: PageIndex,
     pager: &'a MemoryPager,
-    page: Option<Arc<RwLock<Box<[u8]>>>>,
+    page: Option<PageData>,
     offset: u64,
 }
 
 impl<'a> MemoryPage<'a> {
-    fn try_get(&mut self) -> io::Result<Option<Arc<RwLock<Box<[u8]>>>>> {
+    fn try_get(&mut self) -> io::Result<Option<PageData>> {
         if let Some(page) = &self.page {
             return Ok(Some(page.clone()));
         }
-        let pages = self.pager.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
-        if let Some(page) = pages.get(&self.index) {
+        let mut pages = self.pager.pages.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        if let Some(page) = pages.get_mut(&self.index) {
+            let page = page.clone();
             self.page = Some(page.clone());
-            return Ok(Some(page.clone()));
+            return Ok(Some(page));
+        }
+        drop(pages);
+        if self.pager.spill.is_none() {
+            return Ok(None);
         }
-        Ok(None)
+        let page = self.pager.load(self.index, false)?;
+        self.page = page.clone();
+        Ok(page)
     }
 
-    fn get_or_create(&mut self) -> io::Result<Arc<RwLock<Box<[u8]>>>> {
+    fn get_or_create(&mut self) -> io::Result<PageData> {
         if let Some(page) = self.try_get()? {
             return Ok(page);
         }
-        drop(self.page.take());
-        let mut pages = self.pager.pages.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
-        let page = pages.entry(self.index).or_insert_with(|| {
-            Arc::new(RwLock::new(vec![0u8; self.pager.page_size as usize].into_boxed_slice()))
-        });
+        let page = self.pager.load(self.index, true)?.unwrap();
         self.page = Some(page.clone());
-        Ok(page.clone())
+        Ok(page)
     }   
 }
 
@@ -92,7 +194,7 @@ impl Read for MemoryPage<'_> {
         let end = self.offset as usize + read_size;
         match self.try_get()? {
             Some(page) => {
-                let page = page.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
+                let page = page.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
                 buf[..read_size].copy_from_slice(&page[self.offset as usize..end]);
             },
             None => {
@@ -109,7 +211,7 @@ impl Write for MemoryPage<'_> {
         let page_size = self.pager.page_size() as u64;
         let write_size = (page_size - self.offset).min(buf.len() as u64) as usize;
         let page = self.get_or_create()?;
-        let mut page = page.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Poisoned lock"))?;
+        let mut page = page.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
         let end = self.offset as usize + write_size;
         page[self.offset as usize..end]
             .copy_from_slice(&buf[..write_size]);
@@ -125,22 +227,7 @@ impl Write for MemoryPage<'_> {
 impl Seek for MemoryPage<'_> {
     fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
         let page_size = self.pager.page_size() as u64;
-        let (anchor, offset, is_forward) = match pos {
-            io::SeekFrom::Start(offset) => (0u64, offset, true),
-            io::SeekFrom::End(offset @ 0..) => (page_size, offset as u64, true),
-            io::SeekFrom::End(offset @ ..0) => (page_size, -offset as u64, false),
-            io::SeekFrom::Current(offset @ 0..) => (self.offset, offset as u64, true),
-            io::SeekFrom::Current(offset @ ..0) => (self.offset, -offset as u64, false),
-        };
-        let new_offset = if is_forward {
-            anchor.checked_add(offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek overflow"))?
-        } else {
-            anchor.checked_sub(offset).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek underflow"))?
-        };
-        if new_offset > page_size {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek out of bounds"));
-        }
-        self.offset = new_offset;
+        self.offset = seek_in_page(self.offset, page_size, pos)?;
         Ok(self.offset)
     }
 
@@ -155,16 +242,54 @@ impl Seek for MemoryPage<'_> {
 
     fn seek_relative(&mut self, offset: i64) -> io::Result<()> {
         let page_size = self.pager.page_size() as u64;
-        if offset >= 0 {
-            let new_offset = self.offset.checked_add(offset as u64).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek overflow"))?;
-            if new_offset > page_size {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek out of bounds"));
-            }
-            self.offset = new_offset;
-        } else {
-            let new_offset = self.offset.checked_sub(-offset as u64).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek underflow"))?;
-            self.offset = new_offset;
+        self.offset = seek_in_page(self.offset, page_size, io::SeekFrom::Current(offset))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_memory_pager() -> io::Result<()> {
+        let pager = MemoryPager::new(16).with_max_pages(2);
+        pager.page(0)?.write_all(&[1u8; 16])?;
+        pager.page(1)?.write_all(&[2u8; 16])?;
+        assert_eq!(pager.page(2)?.write_all(&[3u8; 16]).unwrap_err().kind(), io::ErrorKind::OutOfMemory);
+
+        let pager = MemoryPager::new(16).with_max_pages(2).with_spill(FilePager::new(tempfile::tempfile()?, 16)?)?;
+        for index in 0..4 {
+            pager.page(index)?.write_all(&[index as u8 + 1; 16])?;
+        }
+        assert_eq!(pager.resident_pages()?, 2);
+        // A page in use is not evicted.
+        let mut held = pager.page(3)?;
+        held.write_all(&[9u8; 4])?;
+        let mut buffer = [0u8; 16];
+        for index in 0..3 {
+            pager.page(index)?.read_exact(&mut buffer)?;
+            assert_eq!(buffer, [index as u8 + 1; 16]);
+        }
+        held.read_exact(&mut buffer[..12])?;
+        assert_eq!(buffer[..12], [4u8; 12]);
+        drop(held);
+        pager.page(3)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer[..4], [9u8; 4]);
+        assert_eq!(pager.resident_pages()?, 2);
+        // Never written, so read as zeros without being loaded.
+        pager.page(7)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [0u8; 16]);
+        assert_eq!(pager.resident_pages()?, 2);
+
+        // Spilled pages are exported too, without being loaded.
+        let exported = pager.export(|pages| pages.map(|(index, page)| (index, page.to_vec())).collect::<Vec<_>>())?;
+        assert_eq!(exported.iter().map(|(index, _)| *index).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(exported[3].1[..4], [9u8; 4]);
+        for (index, page) in &exported[..3] {
+            assert_eq!(page[..], [*index as u8 + 1; 16]);
         }
+        assert_eq!(pager.resident_pages()?, 2);
         Ok(())
     }
 }
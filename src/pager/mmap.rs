@@ -0,0 +1,262 @@
+use std::{fs::File, io::{self, Read, Seek, SeekFrom, Write}, os::fd::AsRawFd, ptr, sync::RwLock};
+
+use crate::pager::{Page, PageIndex, PageSize, Pager, seek_in_page};
+use crate::PoisonedLockError;
+
+/// The file mapped in whole, `len` bytes from `ptr`.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// The mapping is only accessed through the `RwLock` of its pager.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    const EMPTY: Self = Self { ptr: ptr::null_mut(), len: 0 };
+
+    fn map(file: &File, len: usize) -> io::Result<Self> {
+        if len == 0 {
+            return Ok(Self::EMPTY);
+        }
+        // SAFETY: a fresh shared mapping of an open file, released by `Drop`.
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr: ptr.cast(), len })
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        // SAFETY: the range is mapped.
+        if unsafe { libc::msync(self.ptr.cast(), self.len, libc::MS_SYNC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // SAFETY: the range was mapped by `map` and is not referenced any more.
+            unsafe { libc::munmap(self.ptr.cast(), self.len) };
+        }
+    }
+}
+
+/// A pager over a memory mapping of the pages file, serving reads and writes as copies from and
+/// to the mapping, without seeking or a system call per access. Reads share the mapping, so any
+/// number of them run at once; writes take it exclusively.
+///
+/// Writes past the end of the mapping remap the file, growing it to the end of the page written
+/// unless it is longer already.
+pub struct MmapPager {
+    page_size: PageSize,
+    file: File,
+    mapping: RwLock<Mapping>,
+}
+
+impl MmapPager {
+    pub fn new(file: File, page_size: PageSize) -> io::Result<Self> {
+        let mapping = Mapping::map(&file, file_len(&file)?)?;
+        Ok(Self {
+            page_size,
+            file,
+            mapping: RwLock::new(mapping),
+        })
+    }
+
+    /// Picks up pages another handle appended to the file since it was mapped.
+    pub fn refresh(&self) -> io::Result<()> {
+        let mut mapping = self.mapping.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let len = file_len(&self.file)?;
+        if len > mapping.len {
+            *mapping = Mapping::EMPTY;
+            *mapping = Mapping::map(&self.file, len)?;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&self) -> io::Result<()> {
+        let mapping = self.mapping.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        mapping.sync()?;
+        // For the length of the file.
+        self.file.sync_all()
+    }
+
+    fn page_start(&self, page_index: PageIndex) -> io::Result<usize> {
+        (page_index as usize).checked_mul(self.page_size as usize).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "File offset overflow"))
+    }
+}
+
+fn file_len(file: &File) -> io::Result<usize> {
+    usize::try_from(file.metadata()?.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "File too large to map"))
+}
+
+impl Pager for MmapPager {
+    type Page<'a> = MmapPage<'a>;
+
+    fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        Ok(MmapPage {
+            index: page_index,
+            pager: self,
+            start: self.page_start(page_index)?,
+            offset: 0,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct MmapPage<'a> {
+    index: PageIndex,
+    pager: &'a MmapPager,
+    /// Offset of the page in the file.
+    start: usize,
+    offset: u64,
+}
+
+impl Page for MmapPage<'_> {
+    fn index(&self) -> PageIndex {
+        self.index
+    }
+}
+
+impl Read for MmapPage<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let read_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if read_size == 0 {
+            return Ok(0);
+        }
+        let mapping = self.pager.mapping.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let position = self.start + self.offset as usize;
+        // Past the end of the file, the page reads as zeros.
+        let mapped_size = mapping.len.saturating_sub(position).min(read_size);
+        // SAFETY: `mapped_size` bytes from `position` are mapped, and writers are locked out.
+        unsafe { ptr::copy_nonoverlapping(mapping.ptr.add(position), buf.as_mut_ptr(), mapped_size) };
+        buf[mapped_size..read_size].fill(0);
+        self.offset += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl Write for MmapPage<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let write_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if write_size == 0 {
+            return Ok(0);
+        }
+        let mut mapping = self.pager.mapping.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let position = self.start + self.offset as usize;
+        if position + write_size > mapping.len {
+            // Another handle may have appended pages the mapping does not cover yet.
+            let file_len = file_len(&self.pager.file)?;
+            let len = file_len.max(self.start + page_size as usize);
+            *mapping = Mapping::EMPTY;
+            if len > file_len {
+                self.pager.file.set_len(len as u64)?;
+            }
+            *mapping = Mapping::map(&self.pager.file, len)?;
+        }
+        // SAFETY: the range is mapped, as the file was grown to cover it, and the lock is exclusive.
+        unsafe { ptr::copy_nonoverlapping(buf.as_ptr(), mapping.ptr.add(position), write_size) };
+        self.offset += write_size as u64;
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MmapPage<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let page_size = self.pager.page_size() as u64;
+        self.offset = seek_in_page(self.offset, page_size, pos)?;
+        Ok(self.offset)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        Ok(())
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pager::fs::FilePager;
+
+    #[test]
+    fn test_mmap_pager() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pages.dat");
+        let open = || File::options().read(true).write(true).create(true).truncate(false).open(&path);
+        let pager = MmapPager::new(open()?, 256)?;
+
+        let mut buffer = [1u8; 256];
+        pager.page(2)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [0u8; 256]);
+
+        pager.page(1)?.write_all(&[7u8; 256])?;
+        let mut page = pager.page(0)?;
+        page.seek(SeekFrom::Start(100))?;
+        page.write_all(b"mapped")?;
+        page.seek(SeekFrom::Start(98))?;
+        let mut read = [0u8; 8];
+        page.read_exact(&mut read)?;
+        assert_eq!(&read, b"\0\0mapped");
+        pager.sync()?;
+        assert_eq!(std::fs::metadata(&path)?.len(), 512);
+
+        // Shares the file with other handles.
+        let file_pager = FilePager::new(open()?, 256)?;
+        file_pager.page(1)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [7u8; 256]);
+        file_pager.page(3)?.write_all(&[9u8; 256])?;
+        pager.refresh()?;
+        pager.page(3)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [9u8; 256]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_keeps_pages_appended_elsewhere() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pages.dat");
+        let open = || File::options().read(true).write(true).create(true).truncate(false).open(&path);
+        let pager = MmapPager::new(open()?, 256)?;
+        pager.page(0)?.write_all(&[1u8; 256])?;
+
+        // Appended past the mapping, which is not refreshed before the next write.
+        let file_pager = FilePager::new(open()?, 256)?;
+        file_pager.page(3)?.write_all(&[9u8; 256])?;
+        pager.page(1)?.write_all(&[7u8; 256])?;
+        assert_eq!(std::fs::metadata(&path)?.len(), 1024);
+
+        let mut buffer = [0u8; 256];
+        file_pager.page(3)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [9u8; 256]);
+        pager.page(3)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [9u8; 256]);
+        file_pager.page(1)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [7u8; 256]);
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, io::{self, Read, Seek, SeekFrom, Write}, sync::{Arc, RwLock}};
+
+use crate::pager::{Page, PageIndex, PageSize, Pager, seek_in_page};
+use crate::PoisonedLockError;
+
+/// A flat key-value store of whole objects, such as an S3-compatible bucket.
+pub trait ObjectStore {
+    /// The object stored under `key`, `None` if there is none.
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Stores `data` under `key`, replacing the object stored there.
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+}
+
+impl ObjectStore for RwLock<BTreeMap<String, Vec<u8>>> {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let objects = self.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        Ok(objects.get(key).cloned())
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let mut objects = self.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        objects.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+struct CachedPage {
+    data: Box<[u8]>,
+    /// Written since it was last stored.
+    dirty: bool,
+}
+
+/// A pager storing every page as an object of its own, under `prefix` followed by the page
+/// index in hex. Pages are fetched on first access and kept in a write-back cache; written
+/// pages only reach the store with `sync`, so nothing written since is durable.
+///
+/// The cache is not bounded, so it suits cold archives read in parts rather than large working sets.
+pub struct ObjectStorePager<S> {
+    store: S,
+    prefix: String,
+    page_size: PageSize,
+    pages: RwLock<BTreeMap<PageIndex, Arc<RwLock<CachedPage>>>>,
+}
+
+impl<S: ObjectStore> ObjectStorePager<S> {
+    pub fn new(store: S, prefix: impl Into<String>, page_size: PageSize) -> Self {
+        Self {
+            store,
+            prefix: prefix.into(),
+            page_size,
+            pages: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Stores the pages written since the last sync, in the order of their indexes.
+    pub fn sync(&self) -> io::Result<()> {
+        let pages: Vec<_> = {
+            let pages = self.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+            pages.iter().map(|(index, page)| (*index, page.clone())).collect()
+        };
+        for (index, page) in pages {
+            let mut page = page.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+            if page.dirty {
+                self.store.put(&self.object_key(index), &page.data)?;
+                page.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the cached pages not written since the last sync, so they are fetched again.
+    pub fn evict_clean(&self) -> io::Result<()> {
+        let mut pages = self.pages.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        pages.retain(|_, page| page.read().map_or(true, |page| page.dirty));
+        Ok(())
+    }
+
+    fn object_key(&self, page_index: PageIndex) -> String {
+        format!("{}{:08x}", self.prefix, page_index)
+    }
+
+    fn cached_page(&self, page_index: PageIndex) -> io::Result<Arc<RwLock<CachedPage>>> {
+        if let Some(page) = self.pages.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?.get(&page_index) {
+            return Ok(page.clone());
+        }
+        let data = match self.store.get(&self.object_key(page_index))? {
+            Some(data) if data.len() == self.page_size as usize => data.into_boxed_slice(),
+            Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Object of a page has the wrong size")),
+            None => vec![0u8; self.page_size as usize].into_boxed_slice(),
+        };
+        let mut pages = self.pages.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        // Another handle may have fetched, and written, the page meanwhile.
+        let page = pages.entry(page_index).or_insert_with(|| Arc::new(RwLock::new(CachedPage { data, dirty: false })));
+        Ok(page.clone())
+    }
+}
+
+impl<S: ObjectStore> Pager for ObjectStorePager<S> {
+    type Page<'a> = ObjectStorePage<'a, S> where Self: 'a;
+
+    fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        Ok(ObjectStorePage {
+            index: page_index,
+            pager: self,
+            page: None,
+            offset: 0,
+        })
+    }
+}
+
+pub struct ObjectStorePage<'a, S> {
+    index: PageIndex,
+    pager: &'a ObjectStorePager<S>,
+    /// Fetched on first access.
+    page: Option<Arc<RwLock<CachedPage>>>,
+    offset: u64,
+}
+
+impl<S> Clone for ObjectStorePage<'_, S> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            pager: self.pager,
+            page: self.page.clone(),
+            offset: self.offset,
+        }
+    }
+}
+
+impl<S: ObjectStore> ObjectStorePage<'_, S> {
+    fn get(&mut self) -> io::Result<Arc<RwLock<CachedPage>>> {
+        if let Some(page) = &self.page {
+            return Ok(page.clone());
+        }
+        let page = self.pager.cached_page(self.index)?;
+        self.page = Some(page.clone());
+        Ok(page)
+    }
+}
+
+impl<S: ObjectStore> Page for ObjectStorePage<'_, S> {
+    fn index(&self) -> PageIndex {
+        self.index
+    }
+}
+
+impl<S: ObjectStore> Read for ObjectStorePage<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let read_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if read_size == 0 {
+            return Ok(0);
+        }
+        let page = self.get()?;
+        let page = page.read().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let start = self.offset as usize;
+        buf[..read_size].copy_from_slice(&page.data[start..start + read_size]);
+        self.offset += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl<S: ObjectStore> Write for ObjectStorePage<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        let write_size = (page_size - self.offset).min(buf.len() as u64) as usize;
+        if write_size == 0 {
+            return Ok(0);
+        }
+        let page = self.get()?;
+        let mut page = page.write().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let start = self.offset as usize;
+        page.data[start..start + write_size].copy_from_slice(&buf[..write_size]);
+        page.dirty = true;
+        self.offset += write_size as u64;
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: ObjectStore> Seek for ObjectStorePage<'_, S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let page_size = self.pager.page_size() as u64;
+        self.offset = seek_in_page(self.offset, page_size, pos)?;
+        Ok(self.offset)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        Ok(())
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_store_pager() -> io::Result<()> {
+        let pager = ObjectStorePager::new(RwLock::new(BTreeMap::new()), "archive/pages/", 64);
+        let mut page = pager.page(2)?;
+        let mut buffer = [1u8; 64];
+        page.read_exact(&mut buffer)?;
+        assert_eq!(buffer, [0u8; 64]);
+        page.seek(SeekFrom::Start(8))?;
+        page.write_all(b"object")?;
+
+        // Written back only by `sync`.
+        assert!(pager.store().get("archive/pages/00000002")?.is_none());
+        pager.sync()?;
+        let object = pager.store().get("archive/pages/00000002")?.unwrap();
+        assert_eq!(&object[8..14], b"object");
+        assert_eq!(pager.store().read().unwrap().len(), 1);
+
+        // Fetched again after eviction, including changes made to the store meanwhile.
+        let mut changed = object.clone();
+        changed[0] = 9;
+        pager.store().put("archive/pages/00000002", &changed)?;
+        pager.evict_clean()?;
+        pager.page(2)?.read_exact(&mut buffer)?;
+        assert_eq!(buffer[..], changed[..]);
+
+        pager.store().put("archive/pages/00000003", b"short")?;
+        assert_eq!(pager.page(3)?.read(&mut buffer).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::{io::{self, Read, Seek, SeekFrom, Write}, sync::Mutex};
+
+use crate::{lru::Lru, pager::{Page, PageIndex, PageSize, Pager, seek_in_page}};
+use crate::PoisonedLockError;
+
+struct Frame {
+    /// Page of the hot pager holding the page.
+    slot: PageIndex,
+    /// Written since it was last flushed to the cold pager.
+    dirty: bool,
+}
+
+struct TierState {
+    /// Frames by the index of the cold page they hold.
+    frames: Lru<PageIndex, Frame>,
+    /// Slots of the hot pager not holding a page.
+    free_slots: Vec<PageIndex>,
+}
+
+/// A buffer pool of `capacity` pages of the hot pager, e.g. a `MemoryPager`, in front of the cold
+/// one, e.g. a `FilePager`. Pages are copied to a slot of the hot pager when first accessed, then
+/// read and written there; written pages reach the cold pager when their slot is reused for
+/// another page, on `flush` and when the pager is dropped.
+///
+/// The hot pager only ever holds slots `0..capacity`.
+pub struct TieredPager<Hot: Pager, Cold: Pager> {
+    hot: Hot,
+    cold: Cold,
+    state: Mutex<TierState>,
+}
+
+impl<Hot: Pager, Cold: Pager> TieredPager<Hot, Cold> {
+    /// Holds at least one page, even with a `capacity` of zero.
+    pub fn new(hot: Hot, cold: Cold, capacity: PageIndex) -> io::Result<Self> {
+        if hot.page_size() != cold.page_size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Hot and cold pagers have different page sizes"));
+        }
+        Ok(Self {
+            hot,
+            cold,
+            state: Mutex::new(TierState {
+                frames: Lru::new(),
+                free_slots: (0..capacity.max(1)).rev().collect(),
+            }),
+        })
+    }
+
+    pub fn hot(&self) -> &Hot {
+        &self.hot
+    }
+
+    pub fn cold(&self) -> &Cold {
+        &self.cold
+    }
+
+    /// Writes the pages written since they were last flushed to the cold pager, without syncing it.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        for (index, frame) in state.frames.iter_mut() {
+            if frame.dirty {
+                self.write_back(*index, frame.slot)?;
+                frame.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn copy(&self, from: &mut impl Read, to: &mut impl Write) -> io::Result<()> {
+        let mut data = vec![0u8; self.hot.page_size() as usize];
+        from.read_exact(&mut data)?;
+        to.write_all(&data)?;
+        to.flush()
+    }
+
+    fn write_back(&self, page_index: PageIndex, slot: PageIndex) -> io::Result<()> {
+        self.copy(&mut self.hot.page(slot)?, &mut self.cold.page(page_index)?)
+    }
+
+    /// Runs `access` on the hot page holding `page_index`, copying it from the cold pager and
+    /// reusing the slot of the least recently used page if it is not held.
+    fn with_page<T>(&self, page_index: PageIndex, write: bool, access: impl FnOnce(&mut Hot::Page<'_>) -> io::Result<T>) -> io::Result<T> {
+        let mut state = self.state.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        let TierState { frames, free_slots } = &mut *state;
+        if !frames.contains(&page_index) {
+            let slot = match free_slots.pop() {
+                Some(slot) => slot,
+                None => {
+                    let (&evicted, frame) = frames.iter_by_use().next().unwrap();
+                    // Kept held if writing it back fails, so nothing written is lost.
+                    if frame.dirty {
+                        self.write_back(evicted, frame.slot)?;
+                    }
+                    frames.remove(&evicted).unwrap().slot
+                },
+            };
+            if let Err(err) = self.copy(&mut self.cold.page(page_index)?, &mut self.hot.page(slot)?) {
+                free_slots.push(slot);
+                return Err(err);
+            }
+            frames.insert(page_index, Frame { slot, dirty: false });
+        }
+        let frame = frames.get_mut(&page_index).unwrap();
+        frame.dirty |= write;
+        access(&mut self.hot.page(frame.slot)?)
+    }
+}
+
+impl<Hot: Pager, Cold: Pager> Drop for TieredPager<Hot, Cold> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl<Hot: Pager, Cold: Pager> Pager for TieredPager<Hot, Cold> {
+    type Page<'a> = TieredPage<'a, Hot, Cold> where Self: 'a;
+
+    fn page_size(&self) -> PageSize {
+        self.cold.page_size()
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        Ok(TieredPage {
+            index: page_index,
+            pager: self,
+            offset: 0,
+        })
+    }
+}
+
+pub struct TieredPage<'a, Hot: Pager, Cold: Pager> {
+    index: PageIndex,
+    pager: &'a TieredPager<Hot, Cold>,
+    offset: u64,
+}
+
+impl<Hot: Pager, Cold: Pager> Clone for TieredPage<'_, Hot, Cold> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            pager: self.pager,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<Hot: Pager, Cold: Pager> Page for TieredPage<'_, Hot, Cold> {
+    fn index(&self) -> PageIndex {
+        self.index
+    }
+}
+
+impl<Hot: Pager, Cold: Pager> Read for TieredPage<'_, Hot, Cold> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        if self.offset == page_size || buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = self.offset;
+        let read = self.pager.with_page(self.index, false, |page| {
+            page.seek(SeekFrom::Start(offset))?;
+            page.read(buf)
+        })?;
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl<Hot: Pager, Cold: Pager> Write for TieredPage<'_, Hot, Cold> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let page_size = self.pager.page_size() as u64;
+        if self.offset == page_size || buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = self.offset;
+        let written = self.pager.with_page(self.index, true, |page| {
+            page.seek(SeekFrom::Start(offset))?;
+            page.write(buf)
+        })?;
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<Hot: Pager, Cold: Pager> Seek for TieredPage<'_, Hot, Cold> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let page_size = self.pager.page_size() as u64;
+        self.offset = seek_in_page(self.offset, page_size, pos)?;
+        Ok(self.offset)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.offset = 0;
+        Ok(())
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{book::{Book, pager::{PagerBook, PagerBookMemoryHeader}}, pager::{fs::FilePager, memory::MemoryPager}, testing::counting::CountingPager};
+
+    #[test]
+    fn test_tiered_pager() -> io::Result<()> {
+        let cold = CountingPager::new(FilePager::new(tempfile::tempfile()?, 32)?);
+        let pager = TieredPager::new(MemoryPager::new(32), cold, 2)?;
+        let mut book = PagerBook::new(pager, PagerBookMemoryHeader::default());
+        let mut section = book.section(0);
+        section.write_all(&[1u8; 48])?;
+        let mut buffer = [0u8; 48];
+        for _ in 0..3 {
+            section.rewind()?;
+            section.read_exact(&mut buffer)?;
+            assert_eq!(buffer, [1u8; 48]);
+        }
+        // Each page is read from the cold pager once, and written to it only when flushed.
+        let counts = book.pager().cold().counts()?;
+        assert_eq!((counts.reads > 0, counts.bytes_read, counts.bytes_written), (true, 64, 0));
+        book.pager().flush()?;
+        assert_eq!(book.pager().cold().counts()?.bytes_written, 64);
+
+        // A third page reuses the slot of the least recently used one, writing it back.
+        let mut section = book.section(1);
+        section.write_all(&[2u8; 8])?;
+        section.rewind()?;
+        section.read_exact(&mut buffer[..8])?;
+        assert_eq!(buffer[..8], [2u8; 8]);
+        assert_eq!(book.pager().hot().resident_pages()?, 2);
+        book.pager().flush()?;
+        let mut data = [0u8; 32];
+        book.pager().cold().inner().page(2)?.read_exact(&mut data)?;
+        assert_eq!(data[..8], [2u8; 8]);
+        Ok(())
+    }
+}
@@ -0,0 +1,15 @@
+//! The traits needed to work with the crate's types, and the types most programs start from.
+//!
+//! ```
+//! use datastore::prelude::*;
+//! ```
+
+pub use crate::book::{Book, Section};
+pub use crate::book::pager::PageRegistry;
+pub use crate::hash_table::{HashTable, HashTableEntry, MemoryHashTable, HashTableScanFilter, HashTableScanner, ResumableScanner, ScanCursor, SliceHasher, SliceHasherBuilder};
+pub use crate::hash_table::book::{IndexRegistry, SectionRegistry};
+pub use crate::pager::{Page, Pager};
+pub use crate::vfs::{Vfs, VfsFile};
+
+#[cfg(feature = "dbms")]
+pub use crate::dbms::{FileHashTable, HashTableConfig, ManagedHashTable};
@@ -0,0 +1,10 @@
+//! Test utilities for code built on this crate: a file system that simulates crashes,
+//! a harness that checks a store's durability guarantees against it, self-cleaning stores,
+//! and wrappers that count the I/O performed through them.
+
+pub mod counting;
+pub mod fs;
+#[cfg(feature = "dbms")]
+pub mod crash;
+#[cfg(feature = "dbms")]
+pub mod temp;
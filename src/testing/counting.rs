@@ -0,0 +1,297 @@
+use std::{collections::BTreeSet, io::{self, Read, Seek, SeekFrom, Write}, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}};
+
+#[cfg(feature = "dbms")]
+use crate::dbms::wal::{SerializableEvent, WriteAheadLog};
+use crate::PoisonedLockError;
+use crate::pager::{Page, PageIndex, PageSize, Pager};
+
+/// Operations performed through a `CountingPager` since it was created or last reset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PagerCounts {
+    /// Calls to `Pager::page`.
+    pub page_requests: u64,
+    /// Number of different pages at least one byte was read from.
+    pub distinct_pages_read: u64,
+    pub reads: u64,
+    pub bytes_read: u64,
+    pub writes: u64,
+    pub bytes_written: u64,
+}
+
+#[derive(Default)]
+struct PagerCounters {
+    page_requests: AtomicU64,
+    reads: AtomicU64,
+    bytes_read: AtomicU64,
+    writes: AtomicU64,
+    bytes_written: AtomicU64,
+    pages_read: Mutex<BTreeSet<PageIndex>>,
+}
+
+/// Wraps a pager, counting the page requests and the bytes read and written through it.
+pub struct CountingPager<P> {
+    pager: P,
+    counters: Arc<PagerCounters>,
+}
+
+impl<P: Pager> CountingPager<P> {
+    pub fn new(pager: P) -> Self {
+        Self {
+            pager,
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn counts(&self) -> io::Result<PagerCounts> {
+        let counters = &self.counters;
+        let pages_read = counters.pages_read.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        Ok(PagerCounts {
+            page_requests: counters.page_requests.load(Ordering::Relaxed),
+            distinct_pages_read: pages_read.len() as u64,
+            reads: counters.reads.load(Ordering::Relaxed),
+            bytes_read: counters.bytes_read.load(Ordering::Relaxed),
+            writes: counters.writes.load(Ordering::Relaxed),
+            bytes_written: counters.bytes_written.load(Ordering::Relaxed),
+        })
+    }
+
+    pub fn reset_counts(&self) -> io::Result<()> {
+        let counters = &self.counters;
+        let mut pages_read = counters.pages_read.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+        pages_read.clear();
+        for counter in [&counters.page_requests, &counters.reads, &counters.bytes_read, &counters.writes, &counters.bytes_written] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.pager
+    }
+
+    pub fn into_inner(self) -> P {
+        self.pager
+    }
+}
+
+impl<P: Pager> Pager for CountingPager<P> {
+    type Page<'a> = CountingPage<'a, P::Page<'a>> where Self: 'a;
+
+    fn page_size(&self) -> PageSize {
+        self.pager.page_size()
+    }
+
+    fn page<'a>(&'a self, page_index: PageIndex) -> io::Result<Self::Page<'a>> {
+        self.counters.page_requests.fetch_add(1, Ordering::Relaxed);
+        Ok(CountingPage {
+            page: self.pager.page(page_index)?,
+            counters: &self.counters,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct CountingPage<'a, Inner> {
+    page: Inner,
+    counters: &'a PagerCounters,
+}
+
+impl<Inner: Page> Page for CountingPage<'_, Inner> {
+    fn index(&self) -> PageIndex {
+        self.page.index()
+    }
+}
+
+impl<Inner: Page> Read for CountingPage<'_, Inner> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_size = self.page.read(buf)?;
+        self.counters.reads.fetch_add(1, Ordering::Relaxed);
+        self.counters.bytes_read.fetch_add(read_size as u64, Ordering::Relaxed);
+        if read_size > 0 {
+            let mut pages_read = self.counters.pages_read.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))?;
+            pages_read.insert(self.page.index());
+        }
+        Ok(read_size)
+    }
+}
+
+impl<Inner: Page> Write for CountingPage<'_, Inner> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write_size = self.page.write(buf)?;
+        self.counters.writes.fetch_add(1, Ordering::Relaxed);
+        self.counters.bytes_written.fetch_add(write_size as u64, Ordering::Relaxed);
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.page.flush()
+    }
+}
+
+impl<Inner: Page> Seek for CountingPage<'_, Inner> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.page.seek(pos)
+    }
+}
+
+/// Events recorded through a `CountingWAL` since it was created or last reset.
+#[cfg(feature = "dbms")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WALCounts {
+    pub records: u64,
+    /// Serialized size of the recorded events.
+    pub bytes: u64,
+}
+
+/// Wraps a write-ahead log, counting the events recorded and their serialized size.
+#[cfg(feature = "dbms")]
+pub struct CountingWAL<WAL> {
+    wal: WAL,
+    records: AtomicU64,
+    bytes: AtomicU64,
+}
+
+#[cfg(feature = "dbms")]
+impl<WAL> CountingWAL<WAL> {
+    pub fn new(wal: WAL) -> Self {
+        Self {
+            wal,
+            records: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn counts(&self) -> WALCounts {
+        WALCounts {
+            records: self.records.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_counts(&self) {
+        self.records.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+    }
+
+    pub fn inner(&self) -> &WAL {
+        &self.wal
+    }
+
+    pub fn into_inner(self) -> WAL {
+        self.wal
+    }
+}
+
+#[cfg(feature = "dbms")]
+impl<WAL: WriteAheadLog> WriteAheadLog for CountingWAL<WAL>
+where
+    WAL::Event: SerializableEvent,
+{
+    type Event = WAL::Event;
+
+    fn record(&self, event: Self::Event) -> io::Result<()> {
+        let mut size = ByteCounter(0);
+        event.write(&mut size)?;
+        self.wal.record(event)?;
+        self.records.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(size.0, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "dbms")]
+struct ByteCounter(u64);
+
+#[cfg(feature = "dbms")]
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+
+    use super::*;
+    use crate::{book::pager::PagerBook, hash_table::{HashTable, HashTableEntry, HashTableScanFilter, HashTableScanner, book::{BookHashTable, MemoryIndexRegistry, SectionHeader}, prefix_hasher::PrefixHasherBuilder}, pager::memory::MemoryPager};
+
+    #[test]
+    fn test_keyed_scan_reads_only_its_section() -> io::Result<()> {
+        let section_count = 8;
+        let mut hash_table = BookHashTable::new(
+            PrefixHasherBuilder,
+            PagerBook::new(CountingPager::new(MemoryPager::new(64)), RwLock::default()),
+            section_count,
+            vec![SectionHeader { end_offset: 0 }; section_count as usize],
+            64,
+            MemoryIndexRegistry::new(),
+        );
+        for i in 0..64u32 {
+            hash_table.insert(&i.to_le_bytes(), b"value")?;
+        }
+        let counts = hash_table.book().pager().counts()?;
+        assert_eq!(counts.bytes_written, 64 * (8 + 4 + 5));
+        assert_eq!(counts.bytes_read, 0);
+
+        hash_table.book().pager().reset_counts()?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        while scanner.next()?.is_some() {}
+        drop(scanner);
+        let full_scan = hash_table.book().pager().counts()?;
+
+        hash_table.book().pager().reset_counts()?;
+        let key = 7u32.to_le_bytes();
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(&key))?;
+        assert!(scanner.next()?.is_some());
+        while scanner.next()?.is_some() {}
+        drop(scanner);
+        let keyed_scan = hash_table.book().pager().counts()?;
+
+        assert!(keyed_scan.distinct_pages_read > 0);
+        assert!(keyed_scan.distinct_pages_read < full_scan.distinct_pages_read);
+        assert!(keyed_scan.bytes_read < full_scan.bytes_read);
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_only_scan_skips_values() -> io::Result<()> {
+        let section_count = 8;
+        let mut hash_table = BookHashTable::new(
+            PrefixHasherBuilder,
+            PagerBook::new(CountingPager::new(MemoryPager::new(64)), RwLock::default()),
+            section_count,
+            vec![SectionHeader { end_offset: 0 }; section_count as usize],
+            64,
+            MemoryIndexRegistry::new(),
+        );
+        for i in 0..64u32 {
+            hash_table.insert(&i.to_le_bytes(), b"value")?;
+        }
+
+        hash_table.book().pager().reset_counts()?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        while let Some(mut entry) = scanner.next()? {
+            entry.read_key_to_vec()?;
+        }
+        drop(scanner);
+        assert_eq!(hash_table.book().pager().counts()?.bytes_read, 64 * (8 + 4));
+
+        hash_table.book().pager().reset_counts()?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+        while let Some(mut entry) = scanner.next()? {
+            entry.read_key_to_vec()?;
+            entry.read_value_to_vec()?;
+        }
+        drop(scanner);
+        assert_eq!(hash_table.book().pager().counts()?.bytes_read, 64 * (8 + 4 + 5));
+        Ok(())
+    }
+}
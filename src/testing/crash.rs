@@ -0,0 +1,313 @@
+use std::{collections::BTreeMap, io};
+
+use crate::{book::SectionIndex, dbms::{HashTableConfig, ManagedHashTable, ManagedHashTableError}, hash_table::{HashTable, HashTableEntry, HashTableScanFilter, HashTableScanner}};
+
+use super::fs::{CrashFs, CrashMode};
+
+const STORE_DIR: &str = "/store";
+
+#[derive(Clone, Debug)]
+pub enum CrashOp {
+    Insert(Vec<u8>, Vec<u8>),
+    Sync,
+    FullSync,
+    ResizeSections(SectionIndex),
+    /// Inserts the entries in a transaction, which must survive either whole or not at all.
+    Commit(Vec<(Vec<u8>, Vec<u8>)>),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[error("Crash at operation {crash_point} ({mode:?}): {reason}")]
+pub struct CrashViolation {
+    pub crash_point: u64,
+    pub mode: CrashMode,
+    pub reason: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrashReport {
+    /// Number of mutating file system operations performed by the uninterrupted workload.
+    pub crash_points: u64,
+    /// Number of crash/recover/verify cycles that were run.
+    pub runs: u64,
+}
+
+/// What the workload acknowledged before the injected crash.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrashProgress {
+    /// Inserts covered by a completed `sync` or `full_sync`; these must survive.
+    pub synced_inserts: usize,
+    /// Inserts that were started; nothing beyond these may appear.
+    pub attempted_inserts: usize,
+}
+
+/// Runs a `ManagedHashTable` workload on a `CrashFs`, crashing it at every mutating operation in
+/// turn, and checks that the recovered store opens, scans cleanly and holds a prefix of the
+/// inserts that contains at least everything that was synced.
+pub struct CrashHarness {
+    config: HashTableConfig,
+    ops: Vec<CrashOp>,
+    modes: Vec<CrashMode>,
+}
+
+impl CrashHarness {
+    pub fn new(config: HashTableConfig) -> Self {
+        Self {
+            config,
+            ops: Vec::new(),
+            modes: vec![CrashMode::LoseUnsynced, CrashMode::KeepUnsynced],
+        }
+    }
+
+    pub fn modes(mut self, modes: &[CrashMode]) -> Self {
+        self.modes = modes.to_vec();
+        self
+    }
+
+    pub fn insert(mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
+        self.ops.push(CrashOp::Insert(key.into(), value.into()));
+        self
+    }
+
+    pub fn sync(mut self) -> Self {
+        self.ops.push(CrashOp::Sync);
+        self
+    }
+
+    pub fn full_sync(mut self) -> Self {
+        self.ops.push(CrashOp::FullSync);
+        self
+    }
+
+    pub fn resize_sections(mut self, section_count: SectionIndex) -> Self {
+        self.ops.push(CrashOp::ResizeSections(section_count));
+        self
+    }
+
+    pub fn commit<K: Into<Vec<u8>>, V: Into<Vec<u8>>>(mut self, entries: impl IntoIterator<Item = (K, V)>) -> Self {
+        self.ops.push(CrashOp::Commit(entries.into_iter().map(|(key, value)| (key.into(), value.into())).collect()));
+        self
+    }
+
+    pub fn run(&self) -> Result<CrashReport, CrashViolation> {
+        self.run_with(|_, _| Ok(()))
+    }
+
+    /// Like `run`, additionally calling `verify` on every recovered store.
+    pub fn run_with(
+        &self,
+        mut verify: impl FnMut(&ManagedHashTable<&CrashFs>, CrashProgress) -> io::Result<()>,
+    ) -> Result<CrashReport, CrashViolation> {
+        let dry_run_fs = CrashFs::new();
+        self.run_workload(&dry_run_fs).1.map_err(|err| CrashViolation {
+            crash_point: u64::MAX,
+            mode: CrashMode::KeepUnsynced,
+            reason: format!("Workload failed without a crash: {}", err),
+        })?;
+        let crash_points = dry_run_fs.operation_count().map_err(|err| CrashViolation {
+            crash_point: u64::MAX,
+            mode: CrashMode::KeepUnsynced,
+            reason: err.to_string(),
+        })?;
+
+        let mut runs = 0;
+        for crash_point in 0..crash_points {
+            for &mode in &self.modes {
+                let violation = |reason: String| CrashViolation { crash_point, mode, reason };
+                let fs = CrashFs::new();
+                fs.crash_at(crash_point).map_err(|err| violation(err.to_string()))?;
+                let (progress, result) = self.run_workload(&fs);
+                match (result, fs.has_crashed()) {
+                    (Err(_), Ok(true)) => {},
+                    (Err(err), _) => return Err(violation(format!("Workload failed before the crash: {}", err))),
+                    (Ok(()), _) => return Err(violation("Workload completed without reaching the crash".into())),
+                }
+
+                let recovered_fs = fs.recover(mode).map_err(|err| violation(err.to_string()))?;
+                let hash_table = self.reopen(&recovered_fs)
+                    .map_err(|err| violation(format!("Failed to reopen: {}", err)))?;
+                self.check_entries(&hash_table, progress).map_err(violation)?;
+                verify(&hash_table, progress).map_err(|err| violation(err.to_string()))?;
+                runs += 1;
+            }
+        }
+
+        Ok(CrashReport { crash_points, runs })
+    }
+
+    /// Opens the recovered store with the section count it has, as a resize may or may not have
+    /// survived the crash.
+    fn reopen<'a>(&self, fs: &'a CrashFs) -> crate::Result<ManagedHashTable<&'a CrashFs>> {
+        match ManagedHashTable::open_with_vfs(fs, STORE_DIR, self.config.clone()) {
+            Err(err) if matches!(
+                err.get_ref().and_then(|err| err.downcast_ref::<ManagedHashTableError>()),
+                Some(ManagedHashTableError::ConfigMismatch { field: "section_count", .. }),
+            ) => ManagedHashTable::open_existing_with_vfs(fs, STORE_DIR),
+            result => result,
+        }
+    }
+
+    fn run_workload(&self, fs: &CrashFs) -> (CrashProgress, io::Result<()>) {
+        let mut progress = CrashProgress {
+            synced_inserts: 0,
+            attempted_inserts: 0,
+        };
+        let result = (|| {
+            let mut hash_table = ManagedHashTable::open_with_vfs(fs, STORE_DIR, self.config.clone())?;
+            for op in &self.ops {
+                match op {
+                    CrashOp::Insert(key, value) => {
+                        progress.attempted_inserts += 1;
+                        hash_table.insert(key, value)?;
+                    },
+                    CrashOp::Sync => {
+                        hash_table.sync()?;
+                        progress.synced_inserts = progress.attempted_inserts;
+                    },
+                    CrashOp::FullSync => {
+                        hash_table.full_sync()?;
+                        progress.synced_inserts = progress.attempted_inserts;
+                    },
+                    CrashOp::ResizeSections(section_count) => {
+                        hash_table = hash_table.resize_sections(*section_count)?;
+                        progress.synced_inserts = progress.attempted_inserts;
+                    },
+                    CrashOp::Commit(entries) => {
+                        progress.attempted_inserts += entries.len();
+                        let mut transaction = hash_table.begin_transaction()?;
+                        for (key, value) in entries {
+                            transaction.insert(key, value)?;
+                        }
+                        transaction.commit()?;
+                        progress.synced_inserts = progress.attempted_inserts;
+                    },
+                }
+            }
+            Ok(())
+        })();
+        (progress, result)
+    }
+
+    fn check_entries(&self, hash_table: &ManagedHashTable<&CrashFs>, progress: CrashProgress) -> Result<(), String> {
+        let found = read_entries(hash_table).map_err(|err| format!("Failed to scan: {}", err))?;
+        let found_count = found.values().map(Vec::len).sum::<usize>();
+        if found_count < progress.synced_inserts || found_count > progress.attempted_inserts {
+            return Err(format!(
+                "Found {} entries, expected between {} synced and {} attempted inserts",
+                found_count, progress.synced_inserts, progress.attempted_inserts,
+            ));
+        }
+
+        let mut inserted = 0;
+        for op in &self.ops {
+            if let CrashOp::Commit(entries) = op {
+                if found_count > inserted && found_count < inserted + entries.len() {
+                    return Err(format!("Found {} of the {} entries of a transaction", found_count - inserted, entries.len()));
+                }
+                inserted += entries.len();
+            } else if let CrashOp::Insert(..) = op {
+                inserted += 1;
+            }
+        }
+
+        let mut expected = BTreeMap::<Vec<u8>, Vec<Vec<u8>>>::new();
+        let inserts = self.ops.iter().flat_map(|op| match op {
+            CrashOp::Insert(key, value) => vec![(key, value)],
+            CrashOp::Commit(entries) => entries.iter().map(|(key, value)| (key, value)).collect(),
+            _ => Vec::new(),
+        });
+        for (key, value) in inserts.take(found_count) {
+            expected.entry(key.clone()).or_default().push(value.clone());
+        }
+        if found != expected {
+            return Err(format!("Recovered entries are not the first {} inserts", found_count));
+        }
+        Ok(())
+    }
+}
+
+fn read_entries(hash_table: &impl HashTable) -> io::Result<BTreeMap<Vec<u8>, Vec<Vec<u8>>>> {
+    let mut entries = BTreeMap::<Vec<u8>, Vec<Vec<u8>>>::new();
+    let mut scanner = hash_table.scan(HashTableScanFilter::All)?;
+    while let Some(mut entry) = scanner.next()? {
+        let key = entry.read_key_to_vec()?;
+        let value = entry.read_value_to_vec()?;
+        entries.entry(key).or_default().push(value);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_table_survives_every_crash_point() {
+        let config = HashTableConfig {
+            page_size: 64,
+            section_count: 4,
+            index_chunk_size: 64,
+            ..Default::default()
+        };
+        let report = CrashHarness::new(config)
+            .insert("foo", "bar")
+            .insert("test-key", "test-value")
+            .sync()
+            .insert("foo", "baz")
+            .insert("sample-key", vec![1u8; 100])
+            .full_sync()
+            .insert("foo", "qux")
+            .sync()
+            .insert("unsynced", "value")
+            .run()
+            .unwrap();
+        assert!(report.crash_points > 0);
+        assert_eq!(report.runs, report.crash_points * 2);
+    }
+
+    #[test]
+    fn test_transaction_survives_every_crash_point() {
+        let config = HashTableConfig {
+            page_size: 64,
+            section_count: 4,
+            index_chunk_size: 64,
+            ..Default::default()
+        };
+        let report = CrashHarness::new(config)
+            .insert("foo", "bar")
+            .sync()
+            .commit([("foo", "baz"), ("test-key", "test-value"), ("sample-key", "sample-value")])
+            .insert("foo", "qux")
+            .commit([("foo", "quux"), ("other-key", "other-value")])
+            .run()
+            .unwrap();
+        assert!(report.crash_points > 0);
+    }
+
+    #[test]
+    fn test_resize_survives_every_crash_point() {
+        let config = HashTableConfig {
+            page_size: 64,
+            section_count: 2,
+            index_chunk_size: 64,
+            ..Default::default()
+        };
+        // `CrashFs` loses both names of a file renamed before its directory was synced, so power
+        // loss in the middle of moving the resized files into place is not modelled.
+        let report = CrashHarness::new(config)
+            .modes(&[CrashMode::KeepUnsynced])
+            .insert("foo", "bar")
+            .insert("test-key", "test-value")
+            .insert("foo", "baz")
+            .sync()
+            .resize_sections(8)
+            .insert("foo", "qux")
+            .sync()
+            .run()
+            .unwrap();
+        assert!(report.crash_points > 0);
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::{BTreeMap, BTreeSet}, io::{self, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}, sync::{Arc, Mutex, MutexGuard}};
+
+use crate::vfs::{Vfs, VfsFile};
+use crate::PoisonedLockError;
+
+/// What survives of the unsynced state when `CrashFs` is recovered after a crash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CrashMode {
+    /// Power loss: only data flushed by `sync_all`/`sync_data` survives, and only files whose
+    /// directory was synced after they were created.
+    LoseUnsynced,
+    /// Process crash: everything written so far survives, including the first half of the write
+    /// that was interrupted.
+    KeepUnsynced,
+}
+
+#[derive(Clone, Default)]
+struct CrashFsFile {
+    volatile: Vec<u8>,
+    durable: Vec<u8>,
+    linked: bool,
+}
+
+#[derive(Default)]
+struct CrashFsState {
+    files: BTreeMap<PathBuf, CrashFsFile>,
+    dirs: BTreeSet<PathBuf>,
+    operation_count: u64,
+    crash_at: Option<u64>,
+    crashed: bool,
+}
+
+fn crashed_error() -> io::Error {
+    io::Error::other("Injected crash")
+}
+
+impl CrashFsState {
+    /// Counts a mutating operation, failing it and every later call once the crash point is reached.
+    fn begin_operation(&mut self) -> io::Result<bool> {
+        if self.crashed {
+            return Err(crashed_error());
+        }
+        let crash_now = self.crash_at == Some(self.operation_count);
+        self.operation_count += 1;
+        if crash_now {
+            self.crashed = true;
+        }
+        Ok(crash_now)
+    }
+
+    fn check_alive(&self) -> io::Result<()> {
+        if self.crashed {
+            return Err(crashed_error());
+        }
+        Ok(())
+    }
+
+    fn file_mut(&mut self, path: &Path) -> io::Result<&mut CrashFsFile> {
+        self.files.get_mut(path).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "File was removed"))
+    }
+}
+
+/// An in-memory file system that tracks which data is durable and can cut power at a chosen
+/// mutating operation (`write`, `set_len`, `sync_all`, `sync_data`, `rename`, `remove_file` or
+/// `sync_dir`). Directories, and the removal of files, are treated as always durable.
+#[derive(Clone, Default)]
+pub struct CrashFs {
+    state: Arc<Mutex<CrashFsState>>,
+}
+
+impl CrashFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the mutating operation with the zero-based number `operation` and everything after it fail.
+    pub fn crash_at(&self, operation: u64) -> io::Result<()> {
+        let mut state = self.lock()?;
+        state.crash_at = Some(operation);
+        Ok(())
+    }
+
+    pub fn has_crashed(&self) -> io::Result<bool> {
+        Ok(self.lock()?.crashed)
+    }
+
+    /// Number of mutating operations performed so far.
+    pub fn operation_count(&self) -> io::Result<u64> {
+        Ok(self.lock()?.operation_count)
+    }
+
+    /// Returns a new file system holding what would be found on disk after restarting.
+    pub fn recover(&self, mode: CrashMode) -> io::Result<Self> {
+        let state = self.lock()?;
+        let files = state.files
+            .iter()
+            .filter_map(|(path, file)| {
+                let contents = match mode {
+                    CrashMode::LoseUnsynced if !file.linked => return None,
+                    CrashMode::LoseUnsynced => file.durable.clone(),
+                    CrashMode::KeepUnsynced => file.volatile.clone(),
+                };
+                Some((path.clone(), CrashFsFile {
+                    volatile: contents.clone(),
+                    durable: contents,
+                    linked: true,
+                }))
+            })
+            .collect();
+        Ok(Self {
+            state: Arc::new(Mutex::new(CrashFsState {
+                files,
+                dirs: state.dirs.clone(),
+                ..Default::default()
+            })),
+        })
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, CrashFsState>> {
+        self.state.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))
+    }
+}
+
+impl Vfs for CrashFs {
+    type File = CrashFile;
+
+    fn open(&self, path: &Path) -> io::Result<CrashFile> {
+        let mut state = self.lock()?;
+        state.check_alive()?;
+        if !state.files.contains_key(path) {
+            let parent = path.parent().unwrap_or(Path::new(""));
+            if !parent.as_os_str().is_empty() && !state.dirs.contains(parent) {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "Parent directory does not exist"));
+            }
+            state.files.insert(path.to_path_buf(), CrashFsFile::default());
+        }
+        Ok(CrashFile {
+            state: self.state.clone(),
+            path: path.to_path_buf(),
+            position: 0,
+        })
+    }
+
+    fn open_read_only(&self, path: &Path) -> io::Result<CrashFile> {
+        let state = self.lock()?;
+        state.check_alive()?;
+        if !state.files.contains_key(path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "File does not exist"));
+        }
+        Ok(CrashFile {
+            state: self.state.clone(),
+            path: path.to_path_buf(),
+            position: 0,
+        })
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        let state = self.lock()?;
+        state.check_alive()?;
+        Ok(state.files.contains_key(path) || state.dirs.contains(path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.lock()?;
+        if state.begin_operation()? {
+            return Err(crashed_error());
+        }
+        let mut file = state.files.remove(from)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "File does not exist"))?;
+        // Until the directory is synced, neither name is guaranteed to survive a power loss.
+        file.linked = false;
+        state.files.insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.lock()?;
+        if state.begin_operation()? {
+            return Err(crashed_error());
+        }
+        state.files.remove(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "File does not exist"))?;
+        Ok(())
+    }
+
+    fn create_dir_all(&self, dir_path: &Path) -> io::Result<()> {
+        let mut state = self.lock()?;
+        state.check_alive()?;
+        for ancestor in dir_path.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            state.dirs.insert(ancestor.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn sync_dir(&self, dir_path: &Path) -> io::Result<()> {
+        let mut state = self.lock()?;
+        if state.begin_operation()? {
+            return Err(crashed_error());
+        }
+        for (path, file) in state.files.iter_mut() {
+            if path.parent() == Some(dir_path) {
+                file.linked = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct CrashFile {
+    state: Arc<Mutex<CrashFsState>>,
+    path: PathBuf,
+    position: u64,
+}
+
+impl CrashFile {
+    fn lock(&self) -> io::Result<MutexGuard<'_, CrashFsState>> {
+        self.state.lock().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, PoisonedLockError))
+    }
+}
+
+impl Read for CrashFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock()?;
+        state.check_alive()?;
+        let file = state.file_mut(&self.path)?;
+        let start = (self.position as usize).min(file.volatile.len());
+        let read_size = (file.volatile.len() - start).min(buf.len());
+        buf[..read_size].copy_from_slice(&file.volatile[start..start + read_size]);
+        drop(state);
+        self.position += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+impl Write for CrashFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock()?;
+        let crash_now = state.begin_operation()?;
+        // An interrupted write is torn: only its first half reaches the file.
+        let write_size = if crash_now { buf.len() / 2 } else { buf.len() };
+        let file = state.file_mut(&self.path)?;
+        let start = self.position as usize;
+        let end = start + write_size;
+        if file.volatile.len() < end {
+            file.volatile.resize(end, 0);
+        }
+        file.volatile[start..end].copy_from_slice(&buf[..write_size]);
+        if crash_now {
+            return Err(crashed_error());
+        }
+        drop(state);
+        self.position = end as u64;
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock()?.check_alive()
+    }
+}
+
+impl Seek for CrashFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.len()?;
+        let (anchor, offset) = match pos {
+            SeekFrom::Start(offset) => (0, offset as i64),
+            SeekFrom::End(offset) => (len, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        self.position = anchor.checked_add_signed(offset)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek out of bounds"))?;
+        Ok(self.position)
+    }
+}
+
+impl VfsFile for CrashFile {
+    fn len(&self) -> io::Result<u64> {
+        let mut state = self.lock()?;
+        state.check_alive()?;
+        Ok(state.file_mut(&self.path)?.volatile.len() as u64)
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        let mut state = self.lock()?;
+        if state.begin_operation()? {
+            return Err(crashed_error());
+        }
+        state.file_mut(&self.path)?.volatile.resize(size as usize, 0);
+        Ok(())
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        let mut state = self.lock()?;
+        if state.begin_operation()? {
+            return Err(crashed_error());
+        }
+        let file = state.file_mut(&self.path)?;
+        file.durable = file.volatile.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recover_keeps_only_synced_data() -> io::Result<()> {
+        let fs = CrashFs::new();
+        fs.create_dir_all(Path::new("/store"))?;
+
+        let mut synced = fs.open(Path::new("/store/synced"))?;
+        synced.write_all(b"durable")?;
+        synced.sync_all()?;
+        synced.write_all(b"-volatile")?;
+
+        let mut early = fs.open(Path::new("/store/early"))?;
+        fs.sync_dir(Path::new("/store"))?;
+        let mut late = fs.open(Path::new("/store/late"))?;
+        late.write_all(b"late")?;
+        late.sync_all()?;
+        early.write_all(b"x")?;
+
+        let recovered = fs.recover(CrashMode::LoseUnsynced)?;
+        let mut contents = Vec::new();
+        recovered.open(Path::new("/store/synced"))?.read_to_end(&mut contents)?;
+        assert_eq!(contents, b"durable");
+        assert!(recovered.exists(Path::new("/store/early"))?);
+        assert!(!recovered.exists(Path::new("/store/late"))?);
+
+        let recovered = fs.recover(CrashMode::KeepUnsynced)?;
+        contents.clear();
+        recovered.open(Path::new("/store/synced"))?.read_to_end(&mut contents)?;
+        assert_eq!(contents, b"durable-volatile");
+        Ok(())
+    }
+
+    #[test]
+    fn test_crash_tears_write_and_fails_later_operations() -> io::Result<()> {
+        let fs = CrashFs::new();
+        let mut file = fs.open(Path::new("file"))?;
+        file.write_all(b"abcd")?;
+        fs.crash_at(fs.operation_count()?)?;
+
+        assert!(file.write_all(b"efgh").is_err());
+        assert!(fs.has_crashed()?);
+        assert!(file.sync_all().is_err());
+        assert!(fs.open(Path::new("other")).is_err());
+
+        let mut contents = Vec::new();
+        fs.recover(CrashMode::KeepUnsynced)?.open(Path::new("file"))?.read_to_end(&mut contents)?;
+        assert_eq!(contents, b"abcdef");
+        Ok(())
+    }
+}
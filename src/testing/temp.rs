@@ -0,0 +1,88 @@
+use std::{ops::{Deref, DerefMut}, path::Path};
+
+use tempfile::TempDir;
+
+use crate::dbms::{HashTableConfig, ManagedHashTable};
+
+/// A `ManagedHashTable` in a temporary directory that is removed when it is dropped.
+pub struct TempHashTable {
+    // Declared first so the store is closed before its directory is removed.
+    hash_table: ManagedHashTable,
+    dir: TempDir,
+}
+
+impl TempHashTable {
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Closes the store and opens it again in the same directory, e.g. to check what was persisted.
+    pub fn reopen(self) -> crate::Result<Self> {
+        let Self { hash_table, dir } = self;
+        let config = hash_table.config().clone();
+        drop(hash_table);
+        Ok(Self {
+            hash_table: ManagedHashTable::open(dir.path(), config)?,
+            dir,
+        })
+    }
+}
+
+impl Deref for TempHashTable {
+    type Target = ManagedHashTable;
+
+    fn deref(&self) -> &ManagedHashTable {
+        &self.hash_table
+    }
+}
+
+impl DerefMut for TempHashTable {
+    fn deref_mut(&mut self) -> &mut ManagedHashTable {
+        &mut self.hash_table
+    }
+}
+
+impl ManagedHashTable {
+    /// Opens a new store with the default config in a temporary directory.
+    pub fn open_temp() -> crate::Result<TempHashTable> {
+        Self::open_temp_with(HashTableConfig::default())
+    }
+
+    pub fn open_temp_with(config: HashTableConfig) -> crate::Result<TempHashTable> {
+        let dir = tempfile::tempdir()?;
+        Ok(TempHashTable {
+            hash_table: ManagedHashTable::open(dir.path(), config)?,
+            dir,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use crate::hash_table::{HashTable, HashTableEntry, HashTableScanFilter, HashTableScanner};
+
+    #[test]
+    fn test_temp_store_is_removed_on_drop() -> io::Result<()> {
+        let mut hash_table = ManagedHashTable::open_temp_with(HashTableConfig {
+            page_size: 64,
+            section_count: 4,
+            index_chunk_size: 64,
+            ..Default::default()
+        })?;
+        hash_table.insert(b"foo", b"bar")?;
+        hash_table.sync()?;
+
+        let hash_table = hash_table.reopen()?;
+        let mut scanner = hash_table.scan(HashTableScanFilter::Key(b"foo"))?;
+        assert_eq!(scanner.next()?.expect("entry must survive reopening").read_value_to_vec()?, b"bar");
+        drop(scanner);
+
+        let path = hash_table.path().to_path_buf();
+        assert!(path.join("header.json").exists());
+        drop(hash_table);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use std::{fs::{self, File, TryLockError}, io::{self, Read, Seek, Write}, path::Path};
+
+/// A file handle as used by the file-backed pager, registries and write-ahead log.
+pub trait VfsFile: Read + Write + Seek {
+    fn len(&self) -> io::Result<u64>;
+    fn set_len(&self, size: u64) -> io::Result<()>;
+    fn sync_all(&self) -> io::Result<()>;
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.sync_all()
+    }
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Takes an exclusive advisory lock, held until `unlock` or until the handle is dropped.
+    /// Fails with `WouldBlock` if any other handle holds a lock on the file.
+    /// Files that cannot be shared between processes need no locking, hence the no-op default.
+    fn try_lock(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Takes a shared advisory lock, failing with `WouldBlock` if another handle holds an exclusive one.
+    fn try_lock_shared(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn unlock(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn lock_error(err: TryLockError) -> io::Error {
+    match err {
+        TryLockError::WouldBlock => io::Error::new(io::ErrorKind::WouldBlock, "File is locked by another handle"),
+        TryLockError::Error(err) => err,
+    }
+}
+
+impl VfsFile for File {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        File::set_len(self, size)
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        File::sync_all(self)
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+
+    // `flock` on Unix-like systems and `LockFileEx` on Windows.
+    fn try_lock(&self) -> io::Result<()> {
+        File::try_lock(self).map_err(lock_error)
+    }
+
+    fn try_lock_shared(&self) -> io::Result<()> {
+        File::try_lock_shared(self).map_err(lock_error)
+    }
+
+    fn unlock(&self) -> io::Result<()> {
+        File::unlock(self)
+    }
+}
+
+/// The file system operations a store needs to open and maintain its directory.
+pub trait Vfs {
+    type File: VfsFile;
+
+    /// Opens the file for reading and writing, creating it if it does not exist.
+    fn open(&self, path: &Path) -> io::Result<Self::File>;
+
+    /// Opens an existing file for reading only.
+    fn open_read_only(&self, path: &Path) -> io::Result<Self::File>;
+
+    fn exists(&self, path: &Path) -> io::Result<bool>;
+
+    /// Atomically replaces `to` with `from`. The rename is durable once the directory is synced.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Removes the file. The removal is durable once the directory is synced.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates `dir_path` and any missing ancestors, making the new entries durable.
+    fn create_dir_all(&self, dir_path: &Path) -> io::Result<()>;
+
+    /// Flushes the directory entries of `dir_path`, so files created inside it survive a crash.
+    fn sync_dir(&self, dir_path: &Path) -> io::Result<()>;
+}
+
+impl<V: Vfs> Vfs for &V {
+    type File = V::File;
+
+    fn open(&self, path: &Path) -> io::Result<Self::File> {
+        (*self).open(path)
+    }
+
+    fn open_read_only(&self, path: &Path) -> io::Result<Self::File> {
+        (*self).open_read_only(path)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        (*self).exists(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        (*self).rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        (*self).remove_file(path)
+    }
+
+    fn create_dir_all(&self, dir_path: &Path) -> io::Result<()> {
+        (*self).create_dir_all(dir_path)
+    }
+
+    fn sync_dir(&self, dir_path: &Path) -> io::Result<()> {
+        (*self).sync_dir(dir_path)
+    }
+}
+
+fn open_options() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE: other handles, including
+        // those of a store reopened in the same process, may read, write and rename the file.
+        options.share_mode(0x1 | 0x2 | 0x4);
+    }
+    options
+}
+
+/// The operating system's file system.
+///
+/// On targets without one, such as wasm32-unknown-unknown, every operation fails with
+/// `Unsupported`; the in-memory pager and registries (see `MemoryHashTable`) work everywhere.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdFs;
+
+#[cfg(feature = "direct-io")]
+impl StdFs {
+    /// Opens a file like `open`, bypassing the operating system's page cache where supported:
+    /// `O_DIRECT` on Linux, `F_NOCACHE` on macOS and `FILE_FLAG_NO_BUFFERING` on Windows. Reads
+    /// and writes have to be aligned, so pass the file to `FilePager::new_direct`.
+    ///
+    /// Fails with `InvalidInput` on file systems without direct I/O, such as tmpfs.
+    pub fn open_direct(&self, path: &Path) -> io::Result<File> {
+        let mut options = open_options();
+        options
+            .write(true)
+            .create(true)
+            .truncate(false);
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_DIRECT);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            // FILE_FLAG_NO_BUFFERING
+            options.custom_flags(0x20000000);
+        }
+        let file = options.open(path)?;
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::fd::AsRawFd;
+            // SAFETY: `fcntl` on an open file descriptor.
+            if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(file)
+    }
+}
+
+impl Vfs for StdFs {
+    type File = File;
+
+    fn open(&self, path: &Path) -> io::Result<File> {
+        let mut options = open_options();
+        options
+            .write(true)
+            .create(true)
+            .truncate(false);
+        options.open(path)
+    }
+
+    fn open_read_only(&self, path: &Path) -> io::Result<File> {
+        open_options().open(path)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        path.try_exists()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        #[cfg(windows)]
+        {
+            // Replacing a file fails while another process (typically a virus scanner or indexer)
+            // briefly holds it open without FILE_SHARE_DELETE, so retry for a short while.
+            let mut attempts = 0;
+            loop {
+                match fs::rename(from, to) {
+                    Err(err) if err.kind() == io::ErrorKind::PermissionDenied && attempts < 10 => {
+                        attempts += 1;
+                        std::thread::sleep(std::time::Duration::from_millis(10 * attempts));
+                    },
+                    result => return result,
+                }
+            }
+        }
+        #[cfg(not(windows))]
+        {
+            fs::rename(from, to)
+        }
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn create_dir_all(&self, dir_path: &Path) -> io::Result<()> {
+        if dir_path.try_exists()? {
+            return Ok(());
+        }
+        let parent = match dir_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        self.create_dir_all(parent)?;
+        match fs::create_dir(dir_path) {
+            Ok(()) => self.sync_dir(parent),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn sync_dir(&self, dir_path: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            File::open(dir_path)?.sync_all()
+        }
+        #[cfg(not(unix))]
+        {
+            // Directory entries cannot be synced explicitly on this platform.
+            let _ = dir_path;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_advisory_locks() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("locked");
+        let first = StdFs.open(&path)?;
+        let second = StdFs.open(&path)?;
+
+        VfsFile::try_lock(&first)?;
+        assert_eq!(VfsFile::try_lock(&second).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(VfsFile::try_lock_shared(&second).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        VfsFile::unlock(&first)?;
+        VfsFile::try_lock_shared(&first)?;
+        VfsFile::try_lock_shared(&second)?;
+        assert_eq!(VfsFile::try_lock(&second).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        drop(first);
+        VfsFile::unlock(&second)?;
+        VfsFile::try_lock(&second)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_replaces_open_file() -> io::Result<()> {
+        let dir = tempdir()?;
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+        StdFs.open(&from)?.write_all(b"new")?;
+        let mut open_target = StdFs.open(&to)?;
+        open_target.write_all(b"old")?;
+
+        StdFs.rename(&from, &to)?;
+        StdFs.sync_dir(dir.path())?;
+        let mut contents = Vec::new();
+        StdFs.open(&to)?.read_to_end(&mut contents)?;
+        assert_eq!(contents, b"new");
+        assert!(!StdFs.exists(&from)?);
+        Ok(())
+    }
+}